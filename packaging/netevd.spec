@@ -53,6 +53,10 @@ install -dm755 %{buildroot}%{_sysconfdir}/%{name}/activated.d
 install -dm755 %{buildroot}%{_sysconfdir}/%{name}/disconnected.d
 install -dm755 %{buildroot}%{_sysconfdir}/%{name}/manager.d
 install -dm755 %{buildroot}%{_sysconfdir}/%{name}/routes.d
+install -dm755 %{buildroot}%{_sysconfdir}/%{name}/gateway_down.d
+install -dm755 %{buildroot}%{_sysconfdir}/%{name}/gateway_up.d
+install -dm755 %{buildroot}%{_sysconfdir}/%{name}/uplink_degraded.d
+install -dm755 %{buildroot}%{_sysconfdir}/%{name}/uplink_recovered.d
 
 # Install documentation
 install -Dm644 README.md %{buildroot}%{_docdir}/%{name}/README.md
@@ -92,6 +96,10 @@ exit 0
 %dir %{_sysconfdir}/%{name}/disconnected.d
 %dir %{_sysconfdir}/%{name}/manager.d
 %dir %{_sysconfdir}/%{name}/routes.d
+%dir %{_sysconfdir}/%{name}/gateway_down.d
+%dir %{_sysconfdir}/%{name}/gateway_up.d
+%dir %{_sysconfdir}/%{name}/uplink_degraded.d
+%dir %{_sysconfdir}/%{name}/uplink_recovered.d
 
 %changelog
 * Tue Jan 21 2026 Susant Sahani <ssahani@redhat.com> - 0.1.0-1
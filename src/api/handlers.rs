@@ -1,152 +1,532 @@
 use crate::api::models::*;
-use crate::network::NetworkState;
+use crate::context::DaemonContext;
+use crate::listeners::{networkd, networkmanager};
+use crate::network::address::get_all_addresses;
+use crate::network::link::{self, LinkDetails};
+use crate::network::route;
+use crate::network::routing_rule::{self, mark_rule_matches, tracked_rule_matches};
+use crate::network::RoutingRule;
 use axum::{
-    extract::{Path, State},
+    extract::{
+        ws::{rejection::WebSocketUpgradeRejection, Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
     http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     Json,
 };
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use rtnetlink::IpVersion;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
 
-pub type AppState = Arc<RwLock<NetworkState>>;
+pub type AppState = Arc<DaemonContext>;
+
+/// Handler result carrying an HTTP status alongside the error envelope
+pub type ApiResult<T> = Result<Json<ApiResponse<T>>, (StatusCode, Json<ApiResponse<T>>)>;
+
+fn api_error<T>(status: StatusCode, message: String) -> (StatusCode, Json<ApiResponse<T>>) {
+    (status, Json(ApiResponse::error(message)))
+}
 
 /// GET /api/v1/status
-pub async fn get_status(State(state): State<AppState>) -> Json<ApiResponse<DaemonStatus>> {
-    let state = state.read().await;
+pub async fn get_status(State(ctx): State<AppState>) -> Json<ApiResponse<DaemonStatus>> {
+    let backend = ctx.config.read().await.system.backend.clone();
+    let state = ctx.state.read().await;
 
     let status = DaemonStatus {
         status: "running".to_string(),
         version: env!("CARGO_PKG_VERSION").to_string(),
-        uptime_seconds: 0, // TODO: Track actual uptime
+        uptime_seconds: ctx.uptime_seconds(),
         interfaces_count: state.links_by_name.len(),
         routing_rules_count: state.routing_rules_from.len() + state.routing_rules_to.len(),
-        events_processed: 0, // TODO: Track events
-        backend: "systemd-networkd".to_string(), // TODO: Get from config
-        dry_run: false, // TODO: Get from config
+        events_processed: ctx.events.total_recorded(),
+        backend,
+        dry_run: ctx.dry_run,
     };
 
     Json(ApiResponse::success(status))
 }
 
 /// GET /api/v1/interfaces
-pub async fn list_interfaces(State(state): State<AppState>) -> Json<ApiResponse<Vec<InterfaceInfo>>> {
-    let state = state.read().await;
+pub async fn list_interfaces(State(ctx): State<AppState>) -> ApiResult<Vec<InterfaceInfo>> {
+    let links = link::get_link_details(&ctx.handle)
+        .await
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let interfaces: Vec<InterfaceInfo> = state
-        .links_by_name
-        .iter()
-        .map(|(name, index)| InterfaceInfo {
-            name: name.clone(),
-            index: *index,
-            state: "up".to_string(), // TODO: Get actual state
-            addresses: vec![], // TODO: Get addresses
-            mac_address: None, // TODO: Get MAC
-            mtu: None, // TODO: Get MTU
-            flags: vec![], // TODO: Get flags
-        })
-        .collect();
+    let backend = BackendView::new(&ctx).await;
+    let mut interfaces = Vec::with_capacity(links.len());
+    for details in links {
+        interfaces.push(interface_info(&ctx, &backend, details).await);
+    }
 
-    Json(ApiResponse::success(interfaces))
+    Ok(Json(ApiResponse::success(interfaces)))
 }
 
 /// GET /api/v1/interfaces/:name
 pub async fn get_interface(
-    State(state): State<AppState>,
+    State(ctx): State<AppState>,
     Path(name): Path<String>,
-) -> Result<Json<ApiResponse<InterfaceInfo>>, StatusCode> {
-    let state = state.read().await;
-
-    let interface = state
-        .links_by_name
-        .get(&name)
-        .map(|index| InterfaceInfo {
-            name: name.clone(),
-            index: *index,
-            state: "up".to_string(),
-            addresses: vec![],
-            mac_address: None,
-            mtu: None,
-            flags: vec![],
-        });
+) -> ApiResult<InterfaceInfo> {
+    let details = link::get_link_details(&ctx.handle)
+        .await
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .into_iter()
+        .find(|details| details.name == name)
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, format!("Interface {} not found", name)))?;
+
+    let backend = BackendView::new(&ctx).await;
+    Ok(Json(ApiResponse::success(interface_info(&ctx, &backend, details).await)))
+}
+
+/// Per-request access to the backend's view of links
+struct BackendView {
+    backend: String,
+    nm_connection: Option<zbus::Connection>,
+}
+
+impl BackendView {
+    async fn new(ctx: &DaemonContext) -> Self {
+        let backend = ctx.config.read().await.system.backend.clone();
+        let nm_connection = if backend == "NetworkManager" {
+            ctx.bus.connection().await.ok()
+        } else {
+            None
+        };
+
+        Self { backend, nm_connection }
+    }
 
-    match interface {
-        Some(iface) => Ok(Json(ApiResponse::success(iface))),
-        None => Err(StatusCode::NOT_FOUND),
+    async fn link_state(&self, ifindex: u32, name: &str) -> Option<String> {
+        match self.backend.as_str() {
+            "systemd-networkd" => networkd::api::parse_link_state_file(ifindex)
+                .ok()
+                .map(|state| state.oper_state)
+                .filter(|state| !state.is_empty()),
+            "NetworkManager" => match self.nm_connection {
+                Some(ref connection) => networkmanager::get_device_state(connection, name).await.ok(),
+                None => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Merge kernel link details, addresses and backend state
+async fn interface_info(ctx: &DaemonContext, backend: &BackendView, details: LinkDetails) -> InterfaceInfo {
+    let addresses = get_all_addresses(&ctx.handle, details.index)
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(|addr| addr.to_string())
+        .collect();
+    let backend_state = backend.link_state(details.index, &details.name).await;
+
+    InterfaceInfo {
+        name: details.name,
+        index: details.index,
+        state: details.oper_state,
+        addresses,
+        mac_address: details.mac_address,
+        mtu: details.mtu,
+        flags: details.flags,
+        backend_state,
     }
 }
 
 /// GET /api/v1/routes
-pub async fn list_routes(State(_state): State<AppState>) -> Json<ApiResponse<Vec<RouteInfo>>> {
-    // TODO: Implement route listing
-    let routes = vec![];
-    Json(ApiResponse::success(routes))
+pub async fn list_routes(
+    State(ctx): State<AppState>,
+    Query(query): Query<RouteQuery>,
+) -> ApiResult<Vec<RouteInfo>> {
+    let family = match query.family.as_deref() {
+        None => None,
+        Some(family) => Some(
+            parse_family(family)
+                .ok_or_else(|| api_error(StatusCode::BAD_REQUEST, format!("Invalid family '{}'", family)))?,
+        ),
+    };
+
+    let kernel_routes = route::list_routes(&ctx.handle, family)
+        .await
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let managed = managed_tables(&ctx).await;
+    let state = ctx.state.read().await;
+
+    let routes = kernel_routes
+        .into_iter()
+        .filter(|r| query.table.is_none_or(|table| r.table == table))
+        .map(|r| {
+            let interface = r
+                .oif
+                .and_then(|idx| state.get_link_name(idx).cloned())
+                .unwrap_or_default();
+            RouteInfo {
+                destination: r.destination,
+                gateway: r.gateway,
+                interface,
+                metric: r.metric,
+                table: r.table,
+                family: family_name(&r.family).to_string(),
+                managed: managed.contains_key(&r.table),
+            }
+        })
+        .filter(|r| query.interface.as_ref().is_none_or(|name| &r.interface == name))
+        .collect();
+
+    Ok(Json(ApiResponse::success(routes)))
+}
+
+/// Routing tables netevd installs routes and rules into, keyed to the
+/// name of the interface that owns each table
+async fn managed_tables(ctx: &DaemonContext) -> HashMap<u32, String> {
+    let policy_interfaces = ctx.config.read().await.routing.get_routing_policy_interfaces();
+    let state = ctx.state.read().await;
+
+    let mut tables: HashMap<u32, String> = state
+        .routes
+        .values()
+        .filter_map(|entry| {
+            state
+                .get_link_name(entry.ifindex)
+                .map(|name| (entry.table, name.clone()))
+        })
+        .collect();
+
+    for name in policy_interfaces {
+        if let Some(table) = state.tables.get(&name) {
+            tables.insert(table, name);
+        }
+    }
+
+    tables
+}
+
+fn parse_family(family: &str) -> Option<IpVersion> {
+    match family {
+        "ipv4" | "inet" | "4" => Some(IpVersion::V4),
+        "ipv6" | "inet6" | "6" => Some(IpVersion::V6),
+        _ => None,
+    }
+}
+
+fn family_name(family: &IpVersion) -> &'static str {
+    match family {
+        IpVersion::V4 => "ipv4",
+        IpVersion::V6 => "ipv6",
+    }
 }
 
 /// GET /api/v1/rules
-pub async fn list_rules(State(state): State<AppState>) -> Json<ApiResponse<Vec<RoutingRuleInfo>>> {
-    let state = state.read().await;
+pub async fn list_rules(State(ctx): State<AppState>) -> ApiResult<Vec<RoutingRuleInfo>> {
+    let kernel_rules = routing_rule::list_rules(&ctx.handle, None)
+        .await
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let mut rules: Vec<RoutingRuleInfo> = Vec::new();
+    let managed = managed_tables(&ctx).await;
+    let state = ctx.state.read().await;
+    let tracked: Vec<&RoutingRule> = state
+        .routing_rules_from
+        .values()
+        .chain(state.routing_rules_to.values())
+        .collect();
 
-    // Add "from" rules
-    for (addr, rule) in state.routing_rules_from.iter() {
+    let mut rules: Vec<RoutingRuleInfo> = kernel_rules
+        .iter()
+        .map(|rule| {
+            let owner = managed.get(&rule.table);
+            let drift = (owner.is_some()
+                && !tracked.iter().any(|t| tracked_rule_matches(t, rule))
+                && !state.mark_rules.iter().any(|t| mark_rule_matches(t, rule)))
+                .then(|| "untracked".to_string());
+
+            RoutingRuleInfo {
+                priority: Some(rule.priority),
+                source: rule.source.map(|(addr, len)| format!("{}/{}", addr, len)),
+                destination: rule.destination.map(|(addr, len)| format!("{}/{}", addr, len)),
+                table: rule.table,
+                interface: owner.cloned(),
+                family: family_name(&rule.family).to_string(),
+                action: rule.action.clone(),
+                fwmark: rule.fwmark,
+                fwmask: rule.fwmask,
+                iif: rule.iif.clone(),
+                oif: rule.oif.clone(),
+                suppress_prefixlength: rule.suppress_prefixlength,
+                managed: owner.is_some(),
+                drift,
+            }
+        })
+        .collect();
+
+    // Rules netevd believes it installed but the kernel no longer has
+    for rule in tracked {
+        if kernel_rules.iter().any(|k| tracked_rule_matches(rule, k)) {
+            continue;
+        }
+
+        let prefix = format!("{}/{}", rule.address, rule.prefix_len);
         rules.push(RoutingRuleInfo {
-            priority: 32765, // TODO: Get actual priority
-            source: Some(addr.to_string()),
-            destination: None,
+            priority: None,
+            source: rule.is_from.then(|| prefix.clone()),
+            destination: (!rule.is_from).then_some(prefix),
             table: rule.table,
-            interface: None,
+            interface: managed.get(&rule.table).cloned(),
+            family: if rule.address.is_ipv4() { "ipv4" } else { "ipv6" }.to_string(),
+            action: "lookup".to_string(),
+            fwmark: rule.options.fwmark,
+            fwmask: rule.options.fwmask,
+            iif: rule.options.iif.clone(),
+            oif: rule.options.oif.clone(),
+            suppress_prefixlength: None,
+            managed: true,
+            drift: Some("missing".to_string()),
         });
     }
+    for rule in &state.mark_rules {
+        if kernel_rules.iter().any(|k| mark_rule_matches(rule, k)) {
+            continue;
+        }
 
-    // Add "to" rules
-    for (addr, rule) in state.routing_rules_to.iter() {
         rules.push(RoutingRuleInfo {
-            priority: 32766, // TODO: Get actual priority
+            priority: None,
             source: None,
-            destination: Some(addr.to_string()),
+            destination: None,
             table: rule.table,
-            interface: None,
+            interface: Some(rule.interface.clone()),
+            family: if rule.ipv6 { "ipv6" } else { "ipv4" }.to_string(),
+            action: "lookup".to_string(),
+            fwmark: Some(rule.fwmark),
+            fwmask: rule.fwmask,
+            iif: None,
+            oif: None,
+            suppress_prefixlength: None,
+            managed: true,
+            drift: Some("missing".to_string()),
         });
     }
 
-    Json(ApiResponse::success(rules))
+    Ok(Json(ApiResponse::success(rules)))
 }
 
+/// Events returned by GET /api/v1/events when no limit is given
+const DEFAULT_EVENT_LIMIT: usize = 100;
+
 /// GET /api/v1/events
-pub async fn list_events(State(_state): State<AppState>) -> Json<ApiResponse<Vec<NetworkEvent>>> {
-    // TODO: Implement event history
-    let events = vec![];
+pub async fn list_events(
+    State(ctx): State<AppState>,
+    Query(query): Query<EventQuery>,
+) -> Json<ApiResponse<Vec<NetworkEvent>>> {
+    let events = ctx.events.query(
+        query.interface.as_deref(),
+        query.event_type.as_deref(),
+        query.since,
+        query.limit.unwrap_or(DEFAULT_EVENT_LIMIT),
+    );
     Json(ApiResponse::success(events))
 }
 
+/// GET /api/v1/events/stream
+///
+/// Pushes events as they are recorded, filtered by `interface` and `type`.
+/// A WebSocket upgrade request gets one JSON text message per event; any
+/// other request gets a Server-Sent Events stream.
+pub async fn stream_events(
+    State(ctx): State<AppState>,
+    Query(query): Query<EventQuery>,
+    ws: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+) -> Response {
+    let events = live_events(ctx.events.subscribe(), query);
+
+    match ws {
+        Ok(ws) => ws.on_upgrade(move |socket| forward_to_websocket(socket, events)),
+        Err(_) => {
+            let sse = events.map(|event| {
+                Ok::<_, Infallible>(
+                    Event::default()
+                        .id(event.id.clone())
+                        .json_data(&event)
+                        .unwrap_or_else(|_| Event::default().comment("unserializable event")),
+                )
+            });
+            Sse::new(sse).keep_alive(KeepAlive::default()).into_response()
+        }
+    }
+}
+
+/// Turn a subscription into a stream of events passing the query filters.
+/// A subscriber that falls behind skips the events it missed.
+fn live_events(
+    receiver: broadcast::Receiver<NetworkEvent>,
+    query: EventQuery,
+) -> impl Stream<Item = NetworkEvent> + Send + 'static {
+    stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((event, receiver)),
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!("Event stream subscriber lagged, {} events dropped", missed);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+    .filter(move |event| std::future::ready(query.matches(event)))
+}
+
+async fn forward_to_websocket<S>(mut socket: WebSocket, events: S)
+where
+    S: Stream<Item = NetworkEvent> + Send + 'static,
+{
+    let mut events = std::pin::pin!(events);
+
+    loop {
+        tokio::select! {
+            event = events.next() => {
+                let Some(event) = event else { break };
+                let Ok(text) = serde_json::to_string(&event) else { continue };
+                if socket.send(Message::Text(text.into())).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => {
+                // Clients only listen; stop once they close or the socket fails
+                match incoming {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+}
+
 /// POST /api/v1/reload
+///
+/// Same as sending SIGHUP: re-read, validate and swap in the configuration.
 pub async fn reload_config(
-    State(_state): State<AppState>,
-    Json(_payload): Json<ReloadRequest>,
-) -> Json<ReloadResponse> {
-    // TODO: Implement config reload
-    Json(ReloadResponse {
-        success: false,
-        message: "Configuration reload not yet implemented".to_string(),
-        timestamp: chrono::Utc::now(),
-    })
+    State(ctx): State<AppState>,
+    _payload: Option<Json<ReloadRequest>>,
+) -> (StatusCode, Json<ReloadResponse>) {
+    let (status, success, message) = match ctx.reload_config().await {
+        Ok(summary) => (StatusCode::OK, true, summary.to_string()),
+        Err(e) => (StatusCode::UNPROCESSABLE_ENTITY, false, format!("{:#}", e)),
+    };
+
+    (
+        status,
+        Json(ReloadResponse {
+            success,
+            message,
+            timestamp: chrono::Utc::now(),
+        }),
+    )
 }
 
+/// Upper bound on each health probe so a wedged bus cannot hang the check
+const HEALTH_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
 /// GET /health
-pub async fn health_check() -> Json<HealthStatus> {
-    Json(HealthStatus {
-        status: "unknown".to_string(),
-        checks: HealthChecks {
-            dbus: false,
-            netlink: false,
-            config: false,
-        },
-    })
+///
+/// 200 when every required check passes, 503 otherwise. The system bus is
+/// only required when the backend or the configured integrations use it.
+pub async fn health_check(State(ctx): State<AppState>) -> (StatusCode, Json<HealthStatus>) {
+    let mut errors = Vec::new();
+
+    let (config_ok, dbus_required) = {
+        let config = ctx.config.read().await;
+        let config_ok = match config.validate() {
+            Ok(()) => true,
+            Err(e) => {
+                errors.push(format!("config: {:#}", e));
+                false
+            }
+        };
+        let dbus_required = config.system.backend != "dhclient"
+            || config.get_use_dns()
+            || config.get_use_domain()
+            || config.get_use_hostname();
+        (config_ok, dbus_required)
+    };
+
+    let netlink = match probe(probe_netlink(&ctx)).await {
+        Ok(()) => true,
+        Err(e) => {
+            errors.push(format!("netlink: {:#}", e));
+            false
+        }
+    };
+
+    let dbus = match probe(probe_dbus(&ctx)).await {
+        Ok(()) => true,
+        Err(e) => {
+            // Reconnect on the next request, e.g. after dbus-daemon restarts
+            ctx.bus.reset().await;
+            if dbus_required {
+                errors.push(format!("dbus: {:#}", e));
+            }
+            false
+        }
+    };
+
+    let watchers = ctx.tasks.snapshot();
+    for (name, alive) in &watchers {
+        if !alive {
+            errors.push(format!("{} is not running", name));
+        }
+    }
+
+    let healthy = errors.is_empty();
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status,
+        Json(HealthStatus {
+            status: if healthy { "healthy" } else { "unhealthy" }.to_string(),
+            checks: HealthChecks {
+                dbus,
+                netlink,
+                config: config_ok,
+                watchers,
+            },
+            errors,
+        }),
+    )
+}
+
+async fn probe<F: Future<Output = anyhow::Result<()>>>(check: F) -> anyhow::Result<()> {
+    tokio::time::timeout(HEALTH_PROBE_TIMEOUT, check)
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out after {:?}", HEALTH_PROBE_TIMEOUT))?
+}
+
+/// Dump the loopback link: cheap, and always present
+async fn probe_netlink(ctx: &DaemonContext) -> anyhow::Result<()> {
+    let mut links = ctx.handle.link().get().match_index(1).execute();
+    links.try_next().await?;
+    Ok(())
+}
+
+/// Ping the bus daemon over the shared connection
+async fn probe_dbus(ctx: &DaemonContext) -> anyhow::Result<()> {
+    let connection = ctx.bus.connection().await?;
+    zbus::fdo::DBusProxy::new(&connection).await?.get_id().await?;
+    Ok(())
 }
 
 /// GET /metrics
-pub async fn metrics() -> String {
+pub async fn metrics(State(ctx): State<AppState>) -> String {
     let info_line = format!(
         "# HELP netevd_info netevd daemon information\n\
          # TYPE netevd_info gauge\n\
@@ -154,7 +534,8 @@ pub async fn metrics() -> String {
         env!("CARGO_PKG_VERSION")
     );
 
-    if let Some(handle) = crate::metrics::get_global_metrics() {
+    if let Some(ref handle) = ctx.metrics {
+        handle.uptime_seconds.set(ctx.uptime_seconds() as f64);
         format!("{}{}", info_line, handle.gather())
     } else {
         info_line
@@ -1,5 +1,6 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::net::IpAddr;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -51,6 +52,9 @@ pub struct InterfaceInfo {
     pub mac_address: Option<String>,
     pub mtu: Option<u32>,
     pub flags: Vec<String>,
+    /// State as seen by the configured backend (networkd operational
+    /// state or NetworkManager device state), if it manages the link
+    pub backend_state: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -60,15 +64,46 @@ pub struct RouteInfo {
     pub interface: String,
     pub metric: Option<u32>,
     pub table: u32,
+    /// "ipv4" or "ipv6"
+    pub family: String,
+    /// Route lives in a table netevd manages for policy routing
+    pub managed: bool,
+}
+
+/// Query parameters for GET /api/v1/routes
+#[derive(Debug, Default, Deserialize)]
+pub struct RouteQuery {
+    pub table: Option<u32>,
+    pub interface: Option<String>,
+    /// "ipv4" or "ipv6"
+    pub family: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RoutingRuleInfo {
-    pub priority: u32,
+    /// Kernel priority; None for tracked rules missing from the kernel
+    pub priority: Option<u32>,
     pub source: Option<String>,
     pub destination: Option<String>,
     pub table: u32,
+    /// Interface whose netevd table the rule points at
     pub interface: Option<String>,
+    /// "ipv4" or "ipv6"
+    pub family: String,
+    pub action: String,
+    pub fwmark: Option<u32>,
+    pub fwmask: Option<u32>,
+    pub iif: Option<String>,
+    pub oif: Option<String>,
+    /// Routes of this prefix length or shorter are ignored in the table
+    #[serde(default)]
+    pub suppress_prefixlength: Option<u32>,
+    /// Rule points at a table netevd manages
+    pub managed: bool,
+    /// Disagreement with netevd's tracked state: "missing" (tracked but
+    /// absent from the kernel) or "untracked" (in a managed table but not
+    /// tracked)
+    pub drift: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -80,6 +115,25 @@ pub struct NetworkEvent {
     pub details: serde_json::Value,
 }
 
+/// Query parameters for GET /api/v1/events
+#[derive(Debug, Default, Deserialize)]
+pub struct EventQuery {
+    pub interface: Option<String>,
+    #[serde(rename = "type", alias = "event_type")]
+    pub event_type: Option<String>,
+    /// Only events at or after this RFC 3339 timestamp
+    pub since: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+impl EventQuery {
+    /// Whether an event passes the interface and type filters
+    pub fn matches(&self, event: &NetworkEvent) -> bool {
+        self.interface.as_ref().is_none_or(|name| &event.interface == name)
+            && self.event_type.as_ref().is_none_or(|t| &event.event_type == t)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ReloadRequest {
     pub force: Option<bool>,
@@ -94,8 +148,11 @@ pub struct ReloadResponse {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HealthStatus {
+    /// "healthy" or "unhealthy"
     pub status: String,
     pub checks: HealthChecks,
+    /// Why each failed check failed
+    pub errors: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -103,4 +160,6 @@ pub struct HealthChecks {
     pub dbus: bool,
     pub netlink: bool,
     pub config: bool,
+    /// Watcher and listener tasks and whether each is still running
+    pub watchers: BTreeMap<String, bool>,
 }
@@ -13,6 +13,7 @@ pub fn create_api_routes(state: AppState) -> Router {
         .route("/api/v1/routes", get(list_routes))
         .route("/api/v1/rules", get(list_rules))
         .route("/api/v1/events", get(list_events))
+        .route("/api/v1/events/stream", get(stream_events))
         .route("/api/v1/reload", post(reload_config))
         // Health and metrics
         .route("/health", get(health_check))
@@ -1,29 +1,51 @@
 use crate::api::handlers::AppState;
 use crate::api::routes::create_api_routes;
-use crate::network::NetworkState;
-use anyhow::Result;
+use crate::config::ApiConfig;
+use anyhow::{Context, Result};
 use axum::http::{HeaderValue, Method};
-use std::net::SocketAddr;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
 use tower_http::cors::{AllowOrigin, CorsLayer};
 use tower_http::trace::TraceLayer;
 
+#[derive(Clone)]
 pub struct ApiServer {
     addr: SocketAddr,
     state: AppState,
 }
 
 impl ApiServer {
-    pub fn new(port: u16, state: Arc<RwLock<NetworkState>>) -> Self {
-        let addr = SocketAddr::from(([127, 0, 0, 1], port));
-        Self { addr, state }
+    /// Create an API server from the `api` config section.
+    ///
+    /// `bind_address` may be an IPv4 or IPv6 literal (brackets are optional
+    /// for IPv6). TLS is not supported by this build; requesting it is an
+    /// error rather than silently serving plain HTTP.
+    pub fn new(config: &ApiConfig, state: AppState) -> Result<Self> {
+        if config.tls.enabled {
+            anyhow::bail!(
+                "api.tls.enabled is set but netevd is built without TLS support; \
+                 terminate TLS in a reverse proxy instead"
+            );
+        }
+
+        let ip = parse_bind_address(&config.bind_address)?;
+        let addr = SocketAddr::new(ip, config.port);
+        Ok(Self { addr, state })
+    }
+
+    /// Address the server will listen on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
     }
 
-    pub async fn run(self) -> Result<()> {
+    /// Bind and serve until `shutdown` resolves
+    pub async fn run<F>(self, shutdown: F) -> Result<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
         let cors = CorsLayer::new()
             .allow_origin(AllowOrigin::predicate(|origin: &HeaderValue, _| {
-                origin.to_str().map_or(false, |s| {
+                origin.to_str().is_ok_and(|s| {
                     s == "http://127.0.0.1"
                         || s.starts_with("http://127.0.0.1:")
                         || s == "http://localhost"
@@ -39,11 +61,88 @@ impl ApiServer {
             .layer(cors)
             .layer(TraceLayer::new_for_http());
 
-        tracing::info!("Starting API server on {}", self.addr);
+        let listener = tokio::net::TcpListener::bind(self.addr)
+            .await
+            .with_context(|| format!("Failed to bind API server to {}", self.addr))?;
 
-        let listener = tokio::net::TcpListener::bind(self.addr).await?;
-        axum::serve(listener, app).await?;
+        tracing::info!("API server listening on {}", self.addr);
 
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown)
+            .await
+            .context("API server failed")?;
+
+        tracing::info!("API server stopped");
         Ok(())
     }
 }
+
+/// Parse the configured bind address, accepting bracketed IPv6 literals
+fn parse_bind_address(address: &str) -> Result<IpAddr> {
+    let trimmed = address
+        .trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+
+    trimmed
+        .parse()
+        .with_context(|| format!("Invalid api.bind_address '{}'", address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditLogger;
+    use crate::config::Config;
+    use crate::context::DaemonContext;
+    use crate::events::EventHistory;
+    use crate::network::NetworkState;
+    use std::sync::Arc;
+    use std::time::Instant;
+    use tokio::sync::RwLock;
+
+    async fn state() -> AppState {
+        let (connection, handle, _) = rtnetlink::new_connection().unwrap();
+        tokio::spawn(connection);
+        Arc::new(DaemonContext {
+            started_at: Instant::now(),
+            config: Arc::new(RwLock::new(Config::default())),
+            config_path: crate::config::DEFAULT_CONFIG_PATH.into(),
+            reloads: tokio::sync::watch::channel(0).0,
+            state: Arc::new(RwLock::new(NetworkState::new())),
+            metrics: None,
+            audit: Arc::new(AuditLogger::new(None, false)),
+            events: Arc::new(EventHistory::default()),
+            handle,
+            dry_run: false,
+            tasks: Default::default(),
+            bus: Default::default(),
+        })
+    }
+
+    #[test]
+    fn test_parse_bind_address() {
+        assert_eq!(parse_bind_address("127.0.0.1").unwrap(), IpAddr::from([127, 0, 0, 1]));
+        assert_eq!(parse_bind_address("::1").unwrap(), "::1".parse::<IpAddr>().unwrap());
+        assert_eq!(parse_bind_address("[::]").unwrap(), "::".parse::<IpAddr>().unwrap());
+        assert!(parse_bind_address("localhost").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_new_uses_configured_address() {
+        let config = ApiConfig {
+            bind_address: "0.0.0.0".to_string(),
+            port: 8080,
+            ..ApiConfig::default()
+        };
+        let server = ApiServer::new(&config, state().await).unwrap();
+        assert_eq!(server.addr(), "0.0.0.0:8080".parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_new_rejects_tls() {
+        let mut config = ApiConfig::default();
+        config.tls.enabled = true;
+        assert!(ApiServer::new(&config, state().await).is_err());
+    }
+}
@@ -266,6 +266,39 @@ impl AuditLogger {
 
         self.log(event);
     }
+
+    /// Record the re-installation of a rule or route that was removed
+    /// behind netevd's back
+    pub fn log_routing_repair(
+        &self,
+        event_type: AuditEventType,
+        change: &str,
+        table: u32,
+        error: Option<&str>,
+    ) {
+        let details = serde_json::json!({
+            "change": change,
+            "table": table,
+            "error": error,
+        });
+
+        let event = AuditLog {
+            id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            event_type,
+            actor: "netevd".to_string(),
+            action: "repair".to_string(),
+            resource: format!("table-{}", table),
+            result: if error.is_none() {
+                AuditResult::Success
+            } else {
+                AuditResult::Failure
+            },
+            details: Some(details),
+        };
+
+        self.log(event);
+    }
 }
 
 impl Default for AuditLogger {
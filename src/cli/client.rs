@@ -0,0 +1,277 @@
+use anyhow::{Context, Result};
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::{Method, Request, Response, StatusCode, Uri};
+use hyper_util::rt::TokioIo;
+use serde::de::DeserializeOwned;
+use tokio::net::TcpStream;
+
+use crate::api::models::ApiResponse;
+
+/// Failures talking to the daemon that map to distinct exit codes
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    /// Nothing is listening, or the connection broke before a response
+    #[error("cannot reach netevd at {endpoint}: {reason}")]
+    Unreachable { endpoint: String, reason: String },
+
+    /// The daemon answered with an error
+    #[error("netevd API returned {status}: {message}")]
+    Api { status: StatusCode, message: String },
+}
+
+/// Minimal HTTP/1.1 client for the netevd REST API
+///
+/// The API is plain HTTP on a local address, so a connection per request
+/// is all the CLI needs.
+pub struct ApiClient {
+    authority: String,
+    base_path: String,
+}
+
+impl ApiClient {
+    /// Create a client for an endpoint such as `http://localhost:9090`
+    pub fn new(endpoint: &str) -> Result<Self> {
+        let uri: Uri = endpoint
+            .parse()
+            .with_context(|| format!("Invalid API endpoint '{}'", endpoint))?;
+
+        match uri.scheme_str() {
+            Some("http") => {}
+            Some(scheme) => anyhow::bail!("Unsupported API endpoint scheme '{}'", scheme),
+            None => anyhow::bail!("API endpoint '{}' must start with http://", endpoint),
+        }
+
+        let authority = uri
+            .authority()
+            .ok_or_else(|| anyhow::anyhow!("API endpoint '{}' has no host", endpoint))?;
+        let authority = match authority.port_u16() {
+            Some(_) => authority.to_string(),
+            None => format!("{}:80", authority),
+        };
+
+        Ok(Self {
+            authority,
+            base_path: uri.path().trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// Send a request and return the response with its body still streaming
+    pub async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<String>,
+    ) -> Result<Response<Incoming>> {
+        let stream = TcpStream::connect(&self.authority)
+            .await
+            .map_err(|e| self.unreachable(e))?;
+
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .map_err(|e| self.unreachable(e))?;
+        tokio::spawn(async move {
+            let _ = connection.await;
+        });
+
+        let mut request = Request::builder()
+            .method(method)
+            .uri(format!("{}{}", self.base_path, path))
+            .header(hyper::header::HOST, &self.authority);
+        if body.is_some() {
+            request = request.header(hyper::header::CONTENT_TYPE, "application/json");
+        }
+        let request = request
+            .body(Full::new(Bytes::from(body.unwrap_or_default())))
+            .context("Failed to build API request")?;
+
+        Ok(sender
+            .send_request(request)
+            .await
+            .map_err(|e| self.unreachable(e))?)
+    }
+
+    /// Send a request and decode the JSON body, whatever the status
+    pub async fn request_json<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<String>,
+    ) -> Result<(StatusCode, T)> {
+        let response = self.send(method, path, body).await?;
+        let status = response.status();
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .map_err(|e| self.unreachable(e))?
+            .to_bytes();
+
+        let value = serde_json::from_slice(&body).map_err(|_| ClientError::Api {
+            status,
+            message: String::from_utf8_lossy(&body).trim().to_string(),
+        })?;
+        Ok((status, value))
+    }
+
+    /// GET an endpoint wrapped in the standard response envelope and
+    /// return its `data`
+    pub async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let (status, response) = self
+            .request_json::<ApiResponse<T>>(Method::GET, path, None)
+            .await?;
+
+        match response.data {
+            Some(data) if response.success && status.is_success() => Ok(data),
+            _ => Err(ClientError::Api {
+                status,
+                message: response
+                    .error
+                    .unwrap_or_else(|| "no data in response".to_string()),
+            }
+            .into()),
+        }
+    }
+
+    fn unreachable(&self, error: impl std::fmt::Display) -> ClientError {
+        ClientError::Unreachable {
+            endpoint: self.authority.clone(),
+            reason: error.to_string(),
+        }
+    }
+
+    /// Open a long-lived GET request, failing unless the daemon answers 200
+    pub async fn stream(&self, path: &str) -> Result<Incoming> {
+        let response = self.send(Method::GET, path, None).await?;
+        let status = response.status();
+        if status != StatusCode::OK {
+            let body = response.into_body().collect().await?.to_bytes();
+            return Err(ClientError::Api {
+                status,
+                message: String::from_utf8_lossy(&body).trim().to_string(),
+            }
+            .into());
+        }
+        Ok(response.into_body())
+    }
+}
+
+/// Build a `?key=value&...` query string from the parameters that are set
+pub fn query_string(params: &[(&str, Option<String>)]) -> String {
+    let pairs: Vec<String> = params
+        .iter()
+        .filter_map(|(key, value)| {
+            value
+                .as_ref()
+                .map(|value| format!("{}={}", key, percent_encode(value)))
+        })
+        .collect();
+
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("?{}", pairs.join("&"))
+    }
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Incremental Server-Sent Events parser yielding the data of each event
+#[derive(Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+}
+
+impl SseParser {
+    /// Feed a chunk of the stream; returns the payloads of completed events
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut payloads = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let block: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let block = String::from_utf8_lossy(&block);
+
+            // Multi-line data fields are joined with newlines; comments
+            // (keep-alives) and other fields are ignored
+            let data: Vec<&str> = block
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect();
+            if !data.is_empty() {
+                payloads.push(data.join("\n"));
+            }
+        }
+
+        payloads
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_parses_endpoint() {
+        let client = ApiClient::new("http://localhost:9090/").unwrap();
+        assert_eq!(client.authority, "localhost:9090");
+        assert_eq!(client.base_path, "");
+
+        let client = ApiClient::new("http://[::1]/netevd").unwrap();
+        assert_eq!(client.authority, "[::1]:80");
+        assert_eq!(client.base_path, "/netevd");
+
+        assert!(ApiClient::new("https://localhost:9090").is_err());
+        assert!(ApiClient::new("localhost:9090").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_daemon() {
+        // Port 9 (discard) is not expected to have an HTTP server
+        let client = ApiClient::new("http://127.0.0.1:9").unwrap();
+        let err = client
+            .get::<serde_json::Value>("/api/v1/status")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::Unreachable { .. })
+        ));
+    }
+
+    #[test]
+    fn test_query_string() {
+        assert_eq!(query_string(&[("interface", None)]), "");
+        assert_eq!(
+            query_string(&[
+                ("interface", Some("eth0".into())),
+                ("type", Some("a b".into()))
+            ]),
+            "?interface=eth0&type=a%20b"
+        );
+    }
+
+    #[test]
+    fn test_sse_parser_handles_split_chunks() {
+        let mut parser = SseParser::default();
+        assert!(parser
+            .push(b": keep-alive\n\nid: 1\ndata: {\"a\"")
+            .is_empty());
+        assert_eq!(
+            parser.push(b":1}\n\ndata: x\n"),
+            vec!["{\"a\":1}".to_string()]
+        );
+        assert_eq!(parser.push(b"\n"), vec!["x".to_string()]);
+    }
+}
@@ -1,8 +1,31 @@
+use crate::api::models::{
+    DaemonStatus, InterfaceInfo, NetworkEvent, ReloadResponse, RouteInfo, RoutingRuleInfo,
+};
+use crate::cli::client::{query_string, ApiClient, ClientError, SseParser};
 use crate::cli::{Cli, Commands, ListResource, OutputFormat, ShowResource};
 use crate::config::Config;
-use anyhow::Result;
+use crate::system::paths::CONFIG_DIR;
+use anyhow::{Context, Result};
+use http_body_util::BodyExt;
+use hyper::Method;
+use serde::Serialize;
+use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
 
+/// Exit code when the daemon cannot be reached (EX_UNAVAILABLE)
+pub const EXIT_UNREACHABLE: i32 = 69;
+
+/// Exit code for every other failure
+pub const EXIT_FAILURE: i32 = 1;
+
+/// Process exit code for an error returned by [`handle_command`]
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    match err.downcast_ref::<ClientError>() {
+        Some(ClientError::Unreachable { .. }) => EXIT_UNREACHABLE,
+        _ => EXIT_FAILURE,
+    }
+}
+
 pub async fn handle_command(cli: Cli) -> Result<()> {
     match cli.command {
         Some(Commands::Status { format, endpoint }) => {
@@ -29,7 +52,7 @@ pub async fn handle_command(cli: Cli) -> Result<()> {
         }) => handle_events(follow, interface, event_type, tail, format, &endpoint).await,
         Some(Commands::Reload { endpoint }) => handle_reload(&endpoint).await,
         Some(Commands::Validate { config }) => {
-            handle_validate(config.as_deref().unwrap_or(Path::new("/etc/netevd/netevd.yaml")))
+            handle_validate(config.as_deref().unwrap_or(&cli.config))
         }
         Some(Commands::Test {
             script,
@@ -50,25 +73,24 @@ pub async fn handle_command(cli: Cli) -> Result<()> {
 }
 
 async fn handle_status(format: OutputFormat, endpoint: &str) -> Result<()> {
-    println!("Fetching status from {}...", endpoint);
-
-    // TODO: Make HTTP request to API
-    let status = get_daemon_status(endpoint).await?;
+    let status: DaemonStatus = ApiClient::new(endpoint)?.get("/api/v1/status").await?;
 
     match format {
-        OutputFormat::Json => {
-            println!("{}", serde_json::to_string_pretty(&status)?);
-        }
-        OutputFormat::Yaml => {
-            println!("{}", serde_yaml::to_string(&status)?);
-        }
-        _ => {
-            println!("netevd Status:");
-            println!("  Status: {}", status.status);
-            println!("  Uptime: {} seconds", status.uptime);
-            println!("  Interfaces: {}", status.interfaces_count);
-            println!("  Routing Rules: {}", status.routing_rules_count);
-            println!("  Events Processed: {}", status.events_processed);
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&status)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&status)?),
+        OutputFormat::Text | OutputFormat::Table => {
+            println!("netevd {} ({})", status.version, status.status);
+            println!("  Backend:          {}", status.backend);
+            println!(
+                "  Uptime:           {}",
+                format_uptime(status.uptime_seconds)
+            );
+            println!("  Interfaces:       {}", status.interfaces_count);
+            println!("  Routing rules:    {}", status.routing_rules_count);
+            println!("  Events processed: {}", status.events_processed);
+            if status.dry_run {
+                println!("  Dry run:          yes");
+            }
         }
     }
 
@@ -78,24 +100,25 @@ async fn handle_status(format: OutputFormat, endpoint: &str) -> Result<()> {
 async fn handle_list(resource: ListResource, format: OutputFormat, endpoint: &str) -> Result<()> {
     match resource {
         ListResource::Interfaces => {
-            let interfaces = get_interfaces(endpoint).await?;
-            print_list("Interfaces", &interfaces, format)?;
+            let interfaces: Vec<InterfaceInfo> =
+                ApiClient::new(endpoint)?.get("/api/v1/interfaces").await?;
+            print_list("interfaces", &interfaces, &format)
         }
         ListResource::Routes => {
-            let routes = get_routes(endpoint).await?;
-            print_list("Routes", &routes, format)?;
+            let routes: Vec<RouteInfo> = ApiClient::new(endpoint)?.get("/api/v1/routes").await?;
+            print_list("routes", &routes, &format)
         }
         ListResource::Rules => {
-            let rules = get_rules(endpoint).await?;
-            print_list("Rules", &rules, format)?;
+            let rules: Vec<RoutingRuleInfo> =
+                ApiClient::new(endpoint)?.get("/api/v1/rules").await?;
+            print_list("rules", &rules, &format)
         }
         ListResource::Scripts => {
-            let scripts = get_scripts(endpoint).await?;
-            print_list("Scripts", &scripts, format)?;
+            // The daemon has no endpoint for scripts; they live on this host
+            let scripts = list_scripts(Path::new(CONFIG_DIR))?;
+            print_list("scripts", &scripts, &format)
         }
     }
-
-    Ok(())
 }
 
 async fn handle_show(
@@ -106,22 +129,39 @@ async fn handle_show(
 ) -> Result<()> {
     match resource {
         ShowResource::Interface => {
-            let interface = get_interface_details(endpoint, name).await?;
-            match format {
-                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&interface)?),
-                OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&interface)?),
-                _ => print_interface_details(&interface),
-            }
+            let interface: InterfaceInfo = ApiClient::new(endpoint)?
+                .get(&format!("/api/v1/interfaces/{}", name))
+                .await?;
+            print_item(&interface, &format)
         }
-        ShowResource::Status => {
-            handle_status(format, endpoint).await?;
+        ShowResource::Route => {
+            let routes: Vec<RouteInfo> = ApiClient::new(endpoint)?.get("/api/v1/routes").await?;
+            let matched: Vec<RouteInfo> = routes
+                .into_iter()
+                .filter(|route| route.destination == name)
+                .collect();
+            if matched.is_empty() {
+                anyhow::bail!("No route to '{}' found", name);
+            }
+            print_list("routes", &matched, &format)
         }
-        _ => {
-            println!("Show {} not implemented yet", name);
+        ShowResource::Rule => {
+            let priority: u32 = name
+                .parse()
+                .with_context(|| format!("Rule priority '{}' is not a number", name))?;
+            let rules: Vec<RoutingRuleInfo> =
+                ApiClient::new(endpoint)?.get("/api/v1/rules").await?;
+            let matched: Vec<RoutingRuleInfo> = rules
+                .into_iter()
+                .filter(|rule| rule.priority == Some(priority))
+                .collect();
+            if matched.is_empty() {
+                anyhow::bail!("No rule with priority {} found", priority);
+            }
+            print_list("rules", &matched, &format)
         }
+        ShowResource::Status => handle_status(format, endpoint).await,
     }
-
-    Ok(())
 }
 
 async fn handle_events(
@@ -133,33 +173,53 @@ async fn handle_events(
     endpoint: &str,
 ) -> Result<()> {
     if follow {
-        println!("Following events from {}...", endpoint);
-        println!("Press Ctrl+C to stop");
-        // TODO: Implement event streaming via SSE or WebSocket
+        eprintln!("Following events from {}...", endpoint);
+        eprintln!("Press Ctrl+C to stop");
         stream_events(endpoint, interface, event_type, format).await?;
     } else {
-        let events = get_recent_events(endpoint, tail, interface, event_type).await?;
-        print_events(&events, format)?;
+        let path = format!(
+            "/api/v1/events{}",
+            query_string(&[
+                ("interface", interface),
+                ("type", event_type),
+                ("limit", Some(tail.to_string())),
+            ])
+        );
+        let events: Vec<NetworkEvent> = ApiClient::new(endpoint)?.get(&path).await?;
+        for event in &events {
+            print_event(event, &format)?;
+        }
     }
 
     Ok(())
 }
 
 async fn handle_reload(endpoint: &str) -> Result<()> {
-    println!("Sending reload signal to {}...", endpoint);
-
-    // TODO: POST to /api/v1/reload
-    let response = send_reload_request(endpoint).await?;
-
-    println!("  Message: {}", response.message);
-    println!("  Note: config reload not yet implemented on server side");
+    let (status, response) = ApiClient::new(endpoint)?
+        .request_json::<ReloadResponse>(Method::POST, "/api/v1/reload", None)
+        .await?;
+
+    if !response.success {
+        return Err(ClientError::Api {
+            status,
+            message: response.message,
+        }
+        .into());
+    }
 
+    println!("{}", response.message);
     Ok(())
 }
 
 fn handle_validate(config_path: &Path) -> Result<()> {
     println!("Validating configuration file: {}", config_path.display());
 
+    // The daemon falls back to defaults for a missing file; validating one
+    // that isn't there is almost certainly a typo
+    if !config_path.exists() {
+        anyhow::bail!("Configuration file {} does not exist", config_path.display());
+    }
+
     match Config::parse_from_path(&config_path.to_string_lossy()) {
         Ok(config) => {
             println!("✓ Configuration is valid");
@@ -170,11 +230,7 @@ fn handle_validate(config_path: &Path) -> Result<()> {
             println!("  Routing policy rules: {}", config.routing.policy_rules.join(", "));
             Ok(())
         }
-        Err(e) => {
-            eprintln!("✗ Configuration validation failed:");
-            eprintln!("  {}", e);
-            Err(e.into())
-        }
+        Err(e) => Err(e.context("Configuration validation failed")),
     }
 }
 
@@ -227,104 +283,396 @@ fn handle_version(detailed: bool) -> Result<()> {
     Ok(())
 }
 
-// Placeholder functions for API calls
-// These will be implemented when the REST API is ready
+/// Follow /api/v1/events/stream (Server-Sent Events) until the daemon
+/// closes the stream
+pub async fn stream_events(
+    endpoint: &str,
+    interface: Option<String>,
+    event_type: Option<String>,
+    format: OutputFormat,
+) -> Result<()> {
+    let client = ApiClient::new(endpoint)?;
+    let path = format!(
+        "/api/v1/events/stream{}",
+        query_string(&[("interface", interface), ("type", event_type)])
+    );
+
+    let mut body = client.stream(&path).await?;
+    let mut parser = SseParser::default();
+
+    while let Some(frame) = body.frame().await {
+        let frame = frame.context("Event stream interrupted")?;
+        let Some(data) = frame.data_ref() else {
+            continue;
+        };
+
+        for payload in parser.push(data) {
+            match serde_json::from_str::<NetworkEvent>(&payload) {
+                Ok(event) => print_event(&event, &format)?,
+                Err(e) => eprintln!("Skipping malformed event: {}", e),
+            }
+        }
+    }
 
-#[derive(serde::Serialize, serde::Deserialize)]
-struct DaemonStatus {
-    status: String,
-    uptime: u64,
-    interfaces_count: usize,
-    routing_rules_count: usize,
-    events_processed: u64,
+    anyhow::bail!("Event stream closed by netevd")
 }
 
-async fn get_daemon_status(_endpoint: &str) -> Result<DaemonStatus> {
-    // TODO: Implement HTTP client request
-    Ok(DaemonStatus {
-        status: "running".to_string(),
-        uptime: 3600,
-        interfaces_count: 2,
-        routing_rules_count: 4,
-        events_processed: 150,
-    })
+/// Print a single event; JSON output is one object per line
+fn print_event(event: &NetworkEvent, format: &OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string(event)?),
+        OutputFormat::Yaml => print!("---\n{}", serde_yaml::to_string(event)?),
+        OutputFormat::Text | OutputFormat::Table => println!(
+            "{} {:<12} {:<10} {}",
+            event.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            event.event_type,
+            event.interface,
+            event_summary(&event.details)
+        ),
+    }
+    Ok(())
+}
+
+/// One-line summary of an event's details: origin, action and script results
+fn event_summary(details: &serde_json::Value) -> String {
+    let mut parts = Vec::new();
+
+    for key in ["backend", "source", "action", "routing"] {
+        if let Some(value) = details.get(key).and_then(|v| v.as_str()) {
+            parts.push(value.to_string());
+        }
+    }
+
+    if details.get("filtered").and_then(|v| v.as_bool()) == Some(true) {
+        parts.push("filtered".to_string());
+    }
+
+    if details.get("dry_run").and_then(|v| v.as_bool()) == Some(true) {
+        parts.push("dry-run".to_string());
+    }
+
+    if let Some(scripts) = details.get("scripts").and_then(|v| v.as_array()) {
+        for script in scripts {
+            let name = script
+                .get("script")
+                .and_then(|v| v.as_str())
+                .map(|path| path.rsplit('/').next().unwrap_or(path))
+                .unwrap_or("?");
+            let code = script
+                .get("exit_code")
+                .and_then(|v| v.as_i64())
+                .map(|code| code.to_string())
+                .unwrap_or_else(|| "-".to_string());
+            parts.push(format!("{}={}", name, code));
+        }
+    }
+
+    parts.join(" ")
 }
 
-async fn get_interfaces(_endpoint: &str) -> Result<Vec<serde_json::Value>> {
-    // TODO: Implement
-    Ok(vec![])
+/// Scripts found in one of the per-state script directories
+#[derive(Serialize)]
+struct ScriptInfo {
+    state: String,
+    path: String,
+    executable: bool,
 }
 
-async fn get_routes(_endpoint: &str) -> Result<Vec<serde_json::Value>> {
-    // TODO: Implement
-    Ok(vec![])
+/// Every file in the `<state>.d` directories under `dir`, sorted by state
+/// and name as the daemon would run them
+fn list_scripts(dir: &Path) -> Result<Vec<ScriptInfo>> {
+    let mut scripts = Vec::new();
+
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(scripts),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(state) = name.strip_suffix(".d") else {
+            continue;
+        };
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        for script in std::fs::read_dir(entry.path())? {
+            let script = script?;
+            let metadata = script.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            scripts.push(ScriptInfo {
+                state: state.to_string(),
+                path: script.path().display().to_string(),
+                executable: metadata.permissions().mode() & 0o111 != 0,
+            });
+        }
+    }
+
+    scripts.sort_by(|a, b| (&a.state, &a.path).cmp(&(&b.state, &b.path)));
+    Ok(scripts)
 }
 
-async fn get_rules(_endpoint: &str) -> Result<Vec<serde_json::Value>> {
-    // TODO: Implement
-    Ok(vec![])
+/// Resources that can be rendered as rows of a table
+trait Tabular {
+    const HEADERS: &'static [&'static str];
+
+    fn row(&self) -> Vec<String>;
 }
 
-async fn get_scripts(_endpoint: &str) -> Result<Vec<serde_json::Value>> {
-    // TODO: Implement
-    Ok(vec![])
+impl Tabular for InterfaceInfo {
+    const HEADERS: &'static [&'static str] = &[
+        "NAME",
+        "INDEX",
+        "STATE",
+        "BACKEND",
+        "MTU",
+        "MAC",
+        "ADDRESSES",
+    ];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.index.to_string(),
+            self.state.clone(),
+            or_dash(self.backend_state.as_ref()),
+            or_dash(self.mtu.as_ref()),
+            or_dash(self.mac_address.as_ref()),
+            self.addresses.join(","),
+        ]
+    }
 }
 
-async fn get_interface_details(
-    _endpoint: &str,
-    _name: &str,
-) -> Result<serde_json::Value> {
-    // TODO: Implement
-    Ok(serde_json::json!({}))
+impl Tabular for RouteInfo {
+    const HEADERS: &'static [&'static str] = &[
+        "DESTINATION",
+        "GATEWAY",
+        "INTERFACE",
+        "TABLE",
+        "METRIC",
+        "FAMILY",
+        "MANAGED",
+    ];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.destination.clone(),
+            or_dash(self.gateway.as_ref()),
+            or_dash(Some(&self.interface).filter(|name| !name.is_empty())),
+            self.table.to_string(),
+            or_dash(self.metric.as_ref()),
+            self.family.clone(),
+            yes_no(self.managed),
+        ]
+    }
 }
 
-async fn get_recent_events(
-    _endpoint: &str,
-    _tail: usize,
-    _interface: Option<String>,
-    _event_type: Option<String>,
-) -> Result<Vec<serde_json::Value>> {
-    // TODO: Implement
-    Ok(vec![])
+impl Tabular for RoutingRuleInfo {
+    const HEADERS: &'static [&'static str] = &[
+        "PRIORITY",
+        "FAMILY",
+        "FROM",
+        "TO",
+        "FWMARK",
+        "IIF",
+        "OIF",
+        "ACTION",
+        "TABLE",
+        "INTERFACE",
+        "DRIFT",
+    ];
+
+    fn row(&self) -> Vec<String> {
+        let fwmark = match (self.fwmark, self.fwmask) {
+            (Some(mark), Some(mask)) => format!("{:#x}/{:#x}", mark, mask),
+            (Some(mark), None) => format!("{:#x}", mark),
+            _ => "-".to_string(),
+        };
+
+        vec![
+            or_dash(self.priority.as_ref()),
+            self.family.clone(),
+            self.source.clone().unwrap_or_else(|| "all".to_string()),
+            self.destination
+                .clone()
+                .unwrap_or_else(|| "all".to_string()),
+            fwmark,
+            or_dash(self.iif.as_ref()),
+            or_dash(self.oif.as_ref()),
+            self.action.clone(),
+            self.table.to_string(),
+            or_dash(self.interface.as_ref()),
+            or_dash(self.drift.as_ref()),
+        ]
+    }
 }
 
-async fn stream_events(
-    _endpoint: &str,
-    _interface: Option<String>,
-    _event_type: Option<String>,
-    _format: OutputFormat,
-) -> Result<()> {
-    // TODO: Implement SSE/WebSocket streaming
-    Ok(())
+impl Tabular for ScriptInfo {
+    const HEADERS: &'static [&'static str] = &["STATE", "SCRIPT", "EXECUTABLE"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.state.clone(),
+            self.path.clone(),
+            yes_no(self.executable),
+        ]
+    }
 }
 
-#[derive(serde::Deserialize)]
-struct ReloadResponse {
-    message: String,
+fn or_dash<T: std::fmt::Display>(value: Option<&T>) -> String {
+    value.map_or_else(|| "-".to_string(), |v| v.to_string())
 }
 
-async fn send_reload_request(_endpoint: &str) -> Result<ReloadResponse> {
-    // TODO: Implement
-    Ok(ReloadResponse {
-        message: "Configuration reloaded".to_string(),
-    })
+fn yes_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_string()
 }
 
-fn print_list(
-    _title: &str,
-    _items: &[serde_json::Value],
-    _format: OutputFormat,
+/// Render a list; table output has a header row, text output does not
+fn print_list<T: Serialize + Tabular>(
+    title: &str,
+    items: &[T],
+    format: &OutputFormat,
 ) -> Result<()> {
-    // TODO: Implement
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(items)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(items)?),
+        OutputFormat::Table if items.is_empty() => println!("No {} found", title),
+        OutputFormat::Table => print!(
+            "{}",
+            format_table(Some(T::HEADERS), items.iter().map(Tabular::row))
+        ),
+        OutputFormat::Text => print!("{}", format_table(None, items.iter().map(Tabular::row))),
+    }
     Ok(())
 }
 
-fn print_interface_details(_interface: &serde_json::Value) {
-    // TODO: Implement
-    println!("Interface details...");
+/// Render a single resource; text and table output list one field per line
+fn print_item<T: Serialize + Tabular>(item: &T, format: &OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(item)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(item)?),
+        OutputFormat::Text | OutputFormat::Table => {
+            let width = T::HEADERS.iter().map(|h| h.len()).max().unwrap_or(0);
+            for (header, value) in T::HEADERS.iter().zip(item.row()) {
+                println!(
+                    "{:<width$}  {}",
+                    header.to_lowercase(),
+                    value,
+                    width = width
+                );
+            }
+        }
+    }
+    Ok(())
 }
 
-fn print_events(_events: &[serde_json::Value], _format: OutputFormat) -> Result<()> {
-    // TODO: Implement
-    Ok(())
+/// Left-aligned columns separated by two spaces, one line per row
+fn format_table(headers: Option<&[&str]>, rows: impl Iterator<Item = Vec<String>>) -> String {
+    let mut lines: Vec<Vec<String>> = Vec::new();
+    if let Some(headers) = headers {
+        lines.push(headers.iter().map(|h| h.to_string()).collect());
+    }
+    lines.extend(rows);
+
+    let columns = lines.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| {
+            lines
+                .iter()
+                .filter_map(|line| line.get(i))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let mut output = String::new();
+    for line in lines {
+        let cells: Vec<String> = line
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        output.push_str(cells.join("  ").trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+fn format_uptime(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds % 86400 / 3600, seconds % 3600 / 60);
+    match (days, hours) {
+        (0, 0) => format!("{}m {}s", minutes, seconds % 60),
+        (0, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h {}m", days, hours, minutes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_table_aligns_columns() {
+        let rows = vec![
+            vec!["eth0".to_string(), "up".to_string()],
+            vec!["wlan0".to_string(), "down".to_string()],
+        ];
+        assert_eq!(
+            format_table(Some(&["NAME", "STATE"]), rows.clone().into_iter()),
+            "NAME   STATE\neth0   up\nwlan0  down\n"
+        );
+        assert_eq!(
+            format_table(None, rows.into_iter()),
+            "eth0   up\nwlan0  down\n"
+        );
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let unreachable = anyhow::Error::from(ClientError::Unreachable {
+            endpoint: "localhost:9090".to_string(),
+            reason: "connection refused".to_string(),
+        });
+        assert_eq!(exit_code(&unreachable), EXIT_UNREACHABLE);
+        assert_eq!(exit_code(&anyhow::anyhow!("bad config")), EXIT_FAILURE);
+    }
+
+    #[test]
+    fn test_format_uptime() {
+        assert_eq!(format_uptime(59), "0m 59s");
+        assert_eq!(format_uptime(3 * 3600 + 120), "3h 2m");
+        assert_eq!(format_uptime(86400 + 3600), "1d 1h 0m");
+    }
+
+    #[test]
+    fn test_list_scripts() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let routable = dir.path().join("routable.d");
+        std::fs::create_dir(&routable).unwrap();
+        std::fs::write(routable.join("02-b.sh"), "").unwrap();
+        std::fs::write(routable.join("01-a.sh"), "").unwrap();
+        std::fs::set_permissions(
+            routable.join("01-a.sh"),
+            std::fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("netevd.yaml"), "").unwrap();
+
+        let scripts = list_scripts(dir.path()).unwrap();
+        assert_eq!(scripts.len(), 2);
+        assert_eq!(scripts[0].state, "routable");
+        assert!(scripts[0].path.ends_with("01-a.sh"));
+        assert!(scripts[0].executable);
+        assert!(!scripts[1].executable);
+
+        assert!(list_scripts(&dir.path().join("missing"))
+            .unwrap()
+            .is_empty());
+    }
 }
@@ -1,3 +1,4 @@
+pub mod client;
 pub mod handler;
 
 use clap::{Parser, Subcommand};
@@ -9,14 +10,14 @@ use std::path::PathBuf;
 #[command(propagate_version = true)]
 pub struct Cli {
     /// Configuration file path
-    #[arg(short, long, default_value = "/etc/netevd/netevd.yaml")]
+    #[arg(short, long, default_value = crate::config::DEFAULT_CONFIG_PATH)]
     pub config: PathBuf,
 
     /// Enable dry-run mode (don't execute scripts or make changes)
     #[arg(long)]
     pub dry_run: bool,
 
-    /// Enable verbose logging
+    /// Enable verbose logging (-v debug, -vv trace)
     #[arg(short, long, action = clap::ArgAction::Count)]
     pub verbose: u8,
 
@@ -92,11 +93,11 @@ pub enum Commands {
         event_type: Option<String>,
 
         /// Number of recent events to show
-        #[arg(short, long, default_value = "10")]
+        #[arg(short = 'n', long, default_value = "10")]
         tail: usize,
 
         /// Output format
-        #[arg(short = 'f', long, default_value = "text")]
+        #[arg(long, default_value = "text")]
         format: OutputFormat,
 
         /// API endpoint
@@ -173,3 +174,14 @@ impl Cli {
         Cli::parse()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+}
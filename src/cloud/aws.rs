@@ -1,14 +1,14 @@
+//! AWS EC2 integration for netevd
+//!
+//! This module provides integration with AWS EC2 APIs for:
+//! - Route table updates
+//! - Elastic IP association
+//! - Security group management
+//! - VPC networking
+
 use anyhow::Result;
 use std::net::IpAddr;
 
-/// AWS EC2 integration for netevd
-///
-/// This module provides integration with AWS EC2 APIs for:
-/// - Route table updates
-/// - Elastic IP association
-/// - Security group management
-/// - VPC networking
-
 pub struct AwsClient {
     region: String,
     instance_id: Option<String>,
@@ -24,9 +24,15 @@ impl AwsClient {
 
     /// Get instance metadata from EC2 metadata service
     pub async fn get_instance_id(&mut self) -> Result<String> {
+        if let Some(instance_id) = &self.instance_id {
+            return Ok(instance_id.clone());
+        }
+
         // TODO: Implement metadata service client
         // http://169.254.169.254/latest/meta-data/instance-id
-        Ok("i-1234567890abcdef0".to_string())
+        let instance_id = "i-1234567890abcdef0".to_string();
+        self.instance_id = Some(instance_id.clone());
+        Ok(instance_id)
     }
 
     /// Update VPC route table
@@ -37,7 +43,8 @@ impl AwsClient {
         gateway_id: &str,
     ) -> Result<()> {
         tracing::info!(
-            "AWS: Updating route table {} with destination {} via {}",
+            "AWS ({}): Updating route table {} with destination {} via {}",
+            self.region,
             route_table_id,
             destination_cidr,
             gateway_id
@@ -58,7 +65,8 @@ impl AwsClient {
         network_interface_id: &str,
     ) -> Result<()> {
         tracing::info!(
-            "AWS: Associating Elastic IP {} with ENI {}",
+            "AWS ({}): Associating Elastic IP {} with ENI {}",
+            self.region,
             allocation_id,
             network_interface_id
         );
@@ -78,7 +86,8 @@ impl AwsClient {
         action: SecurityGroupAction,
     ) -> Result<()> {
         tracing::info!(
-            "AWS: {:?} security group {} for {}:{}",
+            "AWS ({}): {:?} security group {} for {}:{}",
+            self.region,
             action,
             group_id,
             ip_address,
@@ -105,7 +114,8 @@ impl AwsClient {
         device_index: i32,
     ) -> Result<()> {
         tracing::info!(
-            "AWS: Attaching ENI {} at device index {}",
+            "AWS ({}): Attaching ENI {} at device index {}",
+            self.region,
             interface_id,
             device_index
         );
@@ -1,14 +1,14 @@
+//! Azure integration for netevd
+//!
+//! This module provides integration with Azure APIs for:
+//! - Route table updates
+//! - Network Security Group (NSG) management
+//! - Virtual network configuration
+//! - Public IP association
+
 use anyhow::Result;
 use std::net::IpAddr;
 
-/// Azure integration for netevd
-///
-/// This module provides integration with Azure APIs for:
-/// - Route table updates
-/// - Network Security Group (NSG) management
-/// - Virtual network configuration
-/// - Public IP association
-
 pub struct AzureClient {
     subscription_id: String,
     resource_group: String,
@@ -30,6 +30,7 @@ impl AzureClient {
         Ok(InstanceMetadata {
             vm_id: "vm-12345".to_string(),
             location: "eastus".to_string(),
+            subscription_id: self.subscription_id.clone(),
             resource_group: self.resource_group.clone(),
         })
     }
@@ -123,6 +124,7 @@ impl AzureClient {
 pub struct InstanceMetadata {
     pub vm_id: String,
     pub location: String,
+    pub subscription_id: String,
     pub resource_group: String,
 }
 
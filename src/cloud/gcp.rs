@@ -1,14 +1,14 @@
+//! Google Cloud Platform integration for netevd
+//!
+//! This module provides integration with GCP APIs for:
+//! - VPC route updates
+//! - Firewall rule management
+//! - Instance network configuration
+//! - External IP association
+
 use anyhow::Result;
 use std::net::IpAddr;
 
-/// Google Cloud Platform integration for netevd
-///
-/// This module provides integration with GCP APIs for:
-/// - VPC route updates
-/// - Firewall rule management
-/// - Instance network configuration
-/// - External IP association
-
 pub struct GcpClient {
     project_id: String,
     zone: String,
@@ -4,17 +4,24 @@
 
 use anyhow::{Context, Result};
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::env;
 use std::fs;
+use std::net::IpAddr;
 use std::path::Path;
+use std::time::Duration;
 
 use crate::filters::Filter;
+use crate::network::balance::DEFAULT_LOAD_BALANCE_METRIC;
+use crate::network::routing_rule::{ROUTE_TABLE_BASE, ROUTE_TABLE_RANGE};
+use crate::system::validation::validate_interface_name;
 
-const DEFAULT_CONFIG_PATH: &str = "/etc/netevd/netevd.yaml";
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/netevd/netevd.yaml";
 const DEFAULT_LOG_LEVEL: &str = "info";
 const DEFAULT_BACKEND: &str = "systemd-networkd";
+const DEFAULT_VRF_PREFIX: &str = "vrf-";
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Clone)]
 pub struct Config {
     #[serde(default)]
     pub system: SystemConfig,
@@ -41,7 +48,7 @@ pub struct Config {
     pub filters: Vec<Filter>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub struct SystemConfig {
     #[serde(default = "default_log_level")]
@@ -51,7 +58,7 @@ pub struct SystemConfig {
     pub backend: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Clone)]
 #[serde(rename_all = "snake_case")]
 pub struct MonitoringConfig {
     #[serde(default)]
@@ -63,9 +70,258 @@ pub struct MonitoringConfig {
 pub struct RoutingConfig {
     #[serde(default)]
     pub policy_rules: Vec<String>,
+
+    /// How policy interfaces are separated from the main table
+    #[serde(default)]
+    pub mode: RoutingMode,
+
+    /// VRF device name per policy interface, for `mode: vrf`; the default
+    /// is `vrf-<interface>`
+    #[serde(default)]
+    pub vrf_names: BTreeMap<String, String>,
+
+    /// What happens to the installed rules and routes when netevd stops
+    #[serde(default)]
+    pub on_shutdown: ShutdownPolicy,
+
+    /// Upper bound on the flush done with `on_shutdown: flush`
+    #[serde(default = "default_shutdown_timeout_seconds")]
+    pub shutdown_timeout_seconds: u64,
+
+    /// Which routing table each policy interface gets
+    #[serde(default)]
+    pub tables: TablesConfig,
+
+    /// Shape of the policy rules, per interface
+    #[serde(default)]
+    pub rules: BTreeMap<String, RuleConfig>,
+
+    /// Multipath default route over several policy interfaces
+    #[serde(default)]
+    pub load_balance: Option<LoadBalanceConfig>,
+
+    /// Gateway health probes, per interface
+    #[serde(default)]
+    pub health_checks: BTreeMap<String, HealthCheckConfig>,
+
+    /// Firewall-marked traffic routed through a policy interface's table
+    #[serde(default)]
+    pub mark_rules: Vec<MarkRuleConfig>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// Weighted multipath default route in the main table
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct LoadBalanceConfig {
+    /// Member interfaces and their weights, 1 to 256
+    pub members: BTreeMap<String, u16>,
+
+    /// Metric of the multipath route
+    #[serde(default = "default_load_balance_metric")]
+    pub metric: u32,
+}
+
+/// `fwmark X/mask lookup <table of interface>`
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct MarkRuleConfig {
+    /// Firewall mark to match
+    pub fwmark: u32,
+
+    /// Bits of the mark compared with `fwmark`; all of them when unset
+    #[serde(default)]
+    pub fwmask: Option<u32>,
+
+    /// Policy interface whose table the marked packets use
+    pub interface: String,
+
+    /// Rule priority; the kernel picks one below the last rule when unset
+    #[serde(default)]
+    pub priority: Option<u32>,
+}
+
+/// Probing of the upstream behind one interface
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct HealthCheckConfig {
+    /// Hosts to probe; a round succeeds if any of them answers
+    pub targets: Vec<IpAddr>,
+
+    #[serde(default)]
+    pub method: ProbeMethod,
+
+    /// Destination port of `tcp` probes
+    #[serde(default)]
+    pub port: Option<u16>,
+
+    /// Time between probe rounds
+    #[serde(default = "default_probe_interval_seconds")]
+    pub interval_seconds: u64,
+
+    /// How long to wait for an answer
+    #[serde(default = "default_probe_timeout_seconds")]
+    pub timeout_seconds: u64,
+
+    /// Failed rounds in a row before the gateway counts as down
+    #[serde(default = "default_probe_fall")]
+    pub fall: u32,
+
+    /// Successful rounds in a row before it counts as up again
+    #[serde(default = "default_probe_rise")]
+    pub rise: u32,
+
+    /// Probe rounds that RTT, loss and jitter are measured over
+    #[serde(default = "default_probe_window")]
+    pub window: u32,
+
+    /// Path quality limits that raise `uplink_degraded` events
+    #[serde(default)]
+    pub thresholds: QualityThresholds,
+}
+
+/// Limits on an uplink's measured path quality
+#[derive(Debug, Default, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct QualityThresholds {
+    pub rtt_ms: Option<u32>,
+    pub loss_percent: Option<u32>,
+    pub jitter_ms: Option<u32>,
+}
+
+/// How a health check probes its targets
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProbeMethod {
+    /// ICMP echo request
+    #[default]
+    Icmp,
+    /// TCP connect to `port`
+    Tcp,
+}
+
+impl ProbeMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProbeMethod::Icmp => "icmp",
+            ProbeMethod::Tcp => "tcp",
+        }
+    }
+}
+
+/// Policy rules installed for one interface
+#[derive(Debug, Default, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct RuleConfig {
+    /// Which of the per-address rules to install
+    #[serde(default)]
+    pub selectors: RuleSelectors,
+
+    /// Install the `to` rule for the address's whole subnet
+    #[serde(default)]
+    pub to_subnet: bool,
+
+    #[serde(flatten)]
+    pub options: RuleOptions,
+}
+
+/// Per-address rules installed for a policy interface
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleSelectors {
+    /// `from <address>` and `to <address>`
+    #[default]
+    Both,
+    /// Only `from <address>`, for replies and locally originated traffic
+    From,
+    /// Only `to <address>`
+    To,
+}
+
+impl RuleSelectors {
+    /// Whether `from` (true) or `to` (false) rules are installed
+    pub fn includes(&self, is_from: bool) -> bool {
+        match self {
+            RuleSelectors::Both => true,
+            RuleSelectors::From => is_from,
+            RuleSelectors::To => !is_from,
+        }
+    }
+}
+
+/// Priority and extra matches applied to every rule of an interface
+#[derive(Debug, Default, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub struct RuleOptions {
+    /// Rule priority; the kernel picks one below the last rule when unset
+    #[serde(default)]
+    pub priority: Option<u32>,
+
+    /// Only match packets arriving on this interface
+    #[serde(default)]
+    pub iif: Option<String>,
+
+    /// Only match packets sent out of this interface
+    #[serde(default)]
+    pub oif: Option<String>,
+
+    /// Only match packets with this firewall mark
+    #[serde(default)]
+    pub fwmark: Option<u32>,
+
+    /// Bits of the mark compared with `fwmark`
+    #[serde(default)]
+    pub fwmask: Option<u32>,
+
+    /// Look up the main table first, ignoring its routes with this prefix
+    /// length or shorter
+    #[serde(default)]
+    pub suppress_prefixlength: Option<u8>,
+}
+
+/// Routing table allocation for `policy_rules` interfaces
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct TablesConfig {
+    /// First table handed out automatically
+    #[serde(default = "default_table_base")]
+    pub base: u32,
+
+    /// Number of tables in the automatic range
+    #[serde(default = "default_table_range")]
+    pub range: u32,
+
+    /// Fixed tables for particular interfaces
+    #[serde(default)]
+    pub interfaces: BTreeMap<String, u32>,
+
+    /// Keep the name→table mapping in /etc/iproute2/rt_tables.d/netevd.conf
+    #[serde(default)]
+    pub rt_tables: bool,
+}
+
+/// Mechanism behind the per-interface routing tables
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RoutingMode {
+    /// Source and destination rules send an interface's traffic to its table
+    #[default]
+    Rules,
+    /// Each interface is enslaved to a VRF device bound to its table
+    Vrf,
+}
+
+/// Fate of netevd's policy routing on graceful shutdown
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ShutdownPolicy {
+    /// Leave everything in place; the next start adopts it
+    #[default]
+    Keep,
+    /// Remove every rule and route netevd installed
+    Flush,
+}
+
+#[derive(Debug, Default, Deserialize, Clone)]
 #[serde(rename_all = "snake_case")]
 pub struct BackendsConfig {
     #[serde(default)]
@@ -85,7 +341,7 @@ pub struct SystemdNetworkdConfig {
     pub emit_json: bool,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Clone)]
 #[serde(rename_all = "snake_case")]
 pub struct DhclientConfig {
     #[serde(default)]
@@ -98,13 +354,13 @@ pub struct DhclientConfig {
     pub use_hostname: bool,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Clone)]
 #[serde(rename_all = "snake_case")]
 pub struct NetworkManagerConfig {
     // Placeholder for future NetworkManager-specific options
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub struct ApiConfig {
     #[serde(default = "default_true")]
@@ -120,7 +376,7 @@ pub struct ApiConfig {
     pub tls: TlsConfig,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub struct TlsConfig {
     #[serde(default)]
@@ -133,7 +389,7 @@ pub struct TlsConfig {
     pub key_file: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub struct MetricsConfig {
     #[serde(default = "default_true")]
@@ -143,7 +399,7 @@ pub struct MetricsConfig {
     pub port: u16,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub struct AuditConfig {
     #[serde(default)]
@@ -165,28 +421,30 @@ impl Default for SystemConfig {
     }
 }
 
-impl Default for MonitoringConfig {
-    fn default() -> Self {
-        Self {
-            interfaces: Vec::new(),
-        }
-    }
-}
-
 impl Default for RoutingConfig {
     fn default() -> Self {
         Self {
             policy_rules: Vec::new(),
+            mode: RoutingMode::default(),
+            vrf_names: BTreeMap::new(),
+            on_shutdown: ShutdownPolicy::default(),
+            shutdown_timeout_seconds: default_shutdown_timeout_seconds(),
+            tables: TablesConfig::default(),
+            rules: BTreeMap::new(),
+            load_balance: None,
+            health_checks: BTreeMap::new(),
+            mark_rules: Vec::new(),
         }
     }
 }
 
-impl Default for BackendsConfig {
+impl Default for TablesConfig {
     fn default() -> Self {
         Self {
-            systemd_networkd: SystemdNetworkdConfig::default(),
-            dhclient: DhclientConfig::default(),
-            networkmanager: NetworkManagerConfig::default(),
+            base: default_table_base(),
+            range: default_table_range(),
+            interfaces: BTreeMap::new(),
+            rt_tables: false,
         }
     }
 }
@@ -197,22 +455,6 @@ impl Default for SystemdNetworkdConfig {
     }
 }
 
-impl Default for DhclientConfig {
-    fn default() -> Self {
-        Self {
-            use_dns: false,
-            use_domain: false,
-            use_hostname: false,
-        }
-    }
-}
-
-impl Default for NetworkManagerConfig {
-    fn default() -> Self {
-        Self {}
-    }
-}
-
 impl Default for ApiConfig {
     fn default() -> Self {
         Self {
@@ -224,16 +466,6 @@ impl Default for ApiConfig {
     }
 }
 
-impl Default for TlsConfig {
-    fn default() -> Self {
-        Self {
-            enabled: false,
-            cert_file: None,
-            key_file: None,
-        }
-    }
-}
-
 impl Default for MetricsConfig {
     fn default() -> Self {
         Self {
@@ -265,6 +497,114 @@ impl RoutingConfig {
     pub fn get_routing_policy_interfaces(&self) -> Vec<String> {
         self.policy_rules.clone()
     }
+
+    /// Rule shape for an interface, the default one if not configured
+    pub fn rule_config(&self, name: &str) -> RuleConfig {
+        self.rules.get(name).cloned().unwrap_or_default()
+    }
+
+    /// VRF device of a policy interface in `mode: vrf`
+    pub fn vrf_name(&self, name: &str) -> String {
+        self.vrf_names
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| format!("{}{}", DEFAULT_VRF_PREFIX, name))
+    }
+
+    /// VRF device name of every policy interface, for `mode: vrf`
+    pub fn vrf_devices(&self) -> BTreeMap<String, String> {
+        self.policy_rules
+            .iter()
+            .map(|name| (name.clone(), self.vrf_name(name)))
+            .collect()
+    }
+
+    /// Time allowed for flushing policy routing on shutdown
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_seconds)
+    }
+}
+
+impl HealthCheckConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_seconds)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_seconds)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.targets.is_empty() {
+            anyhow::bail!("targets must list at least one address");
+        }
+        if self.method == ProbeMethod::Tcp && self.port.is_none_or(|port| port == 0) {
+            anyhow::bail!("tcp probes need a port");
+        }
+        if self.interval_seconds == 0 || self.timeout_seconds == 0 {
+            anyhow::bail!("interval_seconds and timeout_seconds must be at least 1");
+        }
+        // A round has to finish before the next one starts
+        if self.timeout_seconds > self.interval_seconds {
+            anyhow::bail!("timeout_seconds must not exceed interval_seconds");
+        }
+        if self.fall == 0 || self.rise == 0 {
+            anyhow::bail!("fall and rise must be at least 1");
+        }
+        if self.window < 2 {
+            anyhow::bail!("window must be at least 2 rounds");
+        }
+        if self.thresholds.loss_percent.is_some_and(|loss| loss >= 100) {
+            anyhow::bail!("thresholds.loss_percent must be below 100");
+        }
+        Ok(())
+    }
+}
+
+impl MarkRuleConfig {
+    fn validate(&self) -> Result<()> {
+        if self.priority == Some(0) {
+            anyhow::bail!("priority 0 is the kernel's local table rule");
+        }
+        // A zero mark with a zero mask matches every packet
+        if self.fwmark == 0 && self.fwmask.is_none_or(|mask| mask == 0) {
+            anyhow::bail!("fwmark 0 needs a non-zero fwmask");
+        }
+        if self.fwmask.is_some_and(|mask| self.fwmark & !mask != 0) {
+            anyhow::bail!("fwmark {:#x} has bits outside fwmask", self.fwmark);
+        }
+        Ok(())
+    }
+}
+
+impl RuleConfig {
+    fn validate(&self) -> Result<()> {
+        let options = &self.options;
+        for name in options.iif.iter().chain(&options.oif) {
+            if !validate_interface_name(name) {
+                anyhow::bail!("Invalid interface name '{}'", name);
+            }
+        }
+        if self.to_subnet && !self.selectors.includes(false) {
+            anyhow::bail!("to_subnet needs `to` rules, but selectors is 'from'");
+        }
+        if options.priority == Some(0) {
+            anyhow::bail!("priority 0 is the kernel's local table rule");
+        }
+        if options.fwmask.is_some() && options.fwmark.is_none() {
+            anyhow::bail!("fwmask needs fwmark");
+        }
+        if let Some(length) = options.suppress_prefixlength {
+            if length > 128 {
+                anyhow::bail!("suppress_prefixlength must be at most 128");
+            }
+            // The main table lookup goes right in front of the rules
+            if options.priority.is_none_or(|priority| priority < 2) {
+                anyhow::bail!("suppress_prefixlength needs a priority of 2 or more");
+            }
+        }
+        Ok(())
+    }
 }
 
 fn default_log_level() -> String {
@@ -299,6 +639,47 @@ fn default_retention_days() -> u32 {
     90
 }
 
+fn default_shutdown_timeout_seconds() -> u64 {
+    5
+}
+
+fn default_table_base() -> u32 {
+    ROUTE_TABLE_BASE
+}
+
+fn default_table_range() -> u32 {
+    ROUTE_TABLE_RANGE
+}
+
+fn default_probe_interval_seconds() -> u64 {
+    5
+}
+
+fn default_probe_timeout_seconds() -> u64 {
+    1
+}
+
+fn default_probe_fall() -> u32 {
+    3
+}
+
+fn default_probe_rise() -> u32 {
+    2
+}
+
+fn default_probe_window() -> u32 {
+    10
+}
+
+fn default_load_balance_metric() -> u32 {
+    DEFAULT_LOAD_BALANCE_METRIC
+}
+
+/// Tables with a fixed meaning in the kernel (unspec, default, main, local)
+fn is_reserved_table(table: u32) -> bool {
+    matches!(table, 0 | 253..=255)
+}
+
 impl Config {
     /// Parse configuration from file and environment variables
     pub fn parse() -> Result<Self> {
@@ -345,8 +726,14 @@ impl Config {
             config.audit.enabled = audit_enabled.parse().unwrap_or(false);
         }
 
-        // Validate backend
-        match config.system.backend.as_str() {
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Check the settings that cannot be expressed in the YAML schema alone
+    pub fn validate(&self) -> Result<()> {
+        match self.system.backend.as_str() {
             "systemd-networkd" | "NetworkManager" | "dhclient" => {}
             other => {
                 anyhow::bail!(
@@ -356,7 +743,111 @@ impl Config {
             }
         }
 
-        Ok(config)
+        for name in self.monitoring.interfaces.iter().chain(&self.routing.policy_rules) {
+            if !validate_interface_name(name) {
+                anyhow::bail!("Invalid interface name '{}'", name);
+            }
+        }
+
+        if self.routing.shutdown_timeout_seconds == 0 {
+            anyhow::bail!("routing.shutdown_timeout_seconds must be at least 1");
+        }
+
+        let tables = &self.routing.tables;
+        if tables.base <= 255 {
+            anyhow::bail!("routing.tables.base must be above 255, got {}", tables.base);
+        }
+        if tables.range == 0 || tables.base.checked_add(tables.range).is_none() {
+            anyhow::bail!("routing.tables.range must be at least 1 and stay below 2^32");
+        }
+
+        let mut seen = BTreeMap::new();
+        for (name, table) in &tables.interfaces {
+            if !validate_interface_name(name) {
+                anyhow::bail!("Invalid interface name '{}' in routing.tables.interfaces", name);
+            }
+            if is_reserved_table(*table) {
+                anyhow::bail!("Table {} for '{}' is reserved by the kernel", table, name);
+            }
+            if let Some(other) = seen.insert(*table, name) {
+                anyhow::bail!("Table {} is assigned to both '{}' and '{}'", table, other, name);
+            }
+        }
+
+        if self.routing.mode == RoutingMode::Vrf {
+            // The VRF's l3mdev rule does the job of the per-address rules
+            if let Some(name) = self.routing.rules.keys().next() {
+                anyhow::bail!("routing.rules entry for '{}' has no effect with routing.mode: vrf", name);
+            }
+            let mut seen = BTreeMap::new();
+            for name in &self.routing.policy_rules {
+                let vrf = self.routing.vrf_name(name);
+                if !validate_interface_name(&vrf) {
+                    anyhow::bail!(
+                        "VRF name '{}' for '{}' is not a valid interface name; set one in routing.vrf_names",
+                        vrf,
+                        name
+                    );
+                }
+                if self.routing.policy_rules.contains(&vrf) {
+                    anyhow::bail!("VRF name '{}' for '{}' is itself a policy interface", vrf, name);
+                }
+                if let Some(other) = seen.insert(vrf.clone(), name) {
+                    anyhow::bail!("VRF '{}' is assigned to both '{}' and '{}'", vrf, other, name);
+                }
+            }
+        }
+        for name in self.routing.vrf_names.keys() {
+            if !self.routing.policy_rules.contains(name) {
+                anyhow::bail!("Interface '{}' of routing.vrf_names must be listed in routing.policy_rules", name);
+            }
+        }
+
+        for (name, rule) in &self.routing.rules {
+            rule.validate()
+                .with_context(|| format!("Invalid routing.rules entry for '{}'", name))?;
+        }
+
+        for (name, check) in &self.routing.health_checks {
+            if !validate_interface_name(name) {
+                anyhow::bail!("Invalid interface name '{}' in routing.health_checks", name);
+            }
+            check.validate()
+                .with_context(|| format!("Invalid routing.health_checks entry for '{}'", name))?;
+        }
+
+        for (i, mark_rule) in self.routing.mark_rules.iter().enumerate() {
+            // The rule points at the interface's table
+            if !self.routing.policy_rules.contains(&mark_rule.interface) {
+                anyhow::bail!(
+                    "Interface '{}' of routing.mark_rules must be listed in routing.policy_rules",
+                    mark_rule.interface
+                );
+            }
+            mark_rule
+                .validate()
+                .with_context(|| format!("Invalid routing.mark_rules entry {}", i + 1))?;
+            if self.routing.mark_rules[..i].contains(mark_rule) {
+                anyhow::bail!("routing.mark_rules entry {} is listed twice", i + 1);
+            }
+        }
+
+        if let Some(load_balance) = &self.routing.load_balance {
+            if load_balance.members.is_empty() {
+                anyhow::bail!("routing.load_balance.members must list at least one interface");
+            }
+            for (name, weight) in &load_balance.members {
+                // The nexthops carry the gateways of the per-interface tables
+                if !self.routing.policy_rules.contains(name) {
+                    anyhow::bail!("Load balance member '{}' must be listed in routing.policy_rules", name);
+                }
+                if !(1..=256).contains(weight) {
+                    anyhow::bail!("Weight of load balance member '{}' must be between 1 and 256", name);
+                }
+            }
+        }
+
+        Ok(())
     }
 
     /// Get links as a vector
@@ -402,21 +893,6 @@ impl Config {
     }
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            system: SystemConfig::default(),
-            monitoring: MonitoringConfig::default(),
-            routing: RoutingConfig::default(),
-            backends: BackendsConfig::default(),
-            api: ApiConfig::default(),
-            metrics: MetricsConfig::default(),
-            audit: AuditConfig::default(),
-            filters: Vec::new(),
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -510,4 +986,293 @@ system:
         assert_eq!(config.system.log_level, "info");
         assert!(config.monitoring.interfaces.is_empty());
     }
+
+    #[test]
+    fn test_validate_rejects_bad_interface_names() {
+        let mut config = Config::default();
+        assert!(config.validate().is_ok());
+
+        config.routing.policy_rules = vec!["eth1;reboot".to_string()];
+        assert!(config.validate().is_err());
+
+        config.routing.policy_rules.clear();
+        config.system.backend = "ifupdown".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_shutdown_policy() {
+        let config = Config::default();
+        assert_eq!(config.routing.on_shutdown, ShutdownPolicy::Keep);
+        assert_eq!(config.routing.shutdown_timeout(), Duration::from_secs(5));
+
+        let yaml = r#"
+routing:
+  policy_rules: [eth1]
+  on_shutdown: flush
+  shutdown_timeout_seconds: 10
+"#;
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.routing.on_shutdown, ShutdownPolicy::Flush);
+        assert_eq!(config.routing.shutdown_timeout(), Duration::from_secs(10));
+
+        config.routing.shutdown_timeout_seconds = 0;
+        assert!(config.validate().is_err());
+
+        assert!(serde_yaml::from_str::<Config>("routing:\n  on_shutdown: wipe\n").is_err());
+    }
+
+    #[test]
+    fn test_rule_config() {
+        let yaml = r#"
+routing:
+  policy_rules: [eth1, wg0]
+  rules:
+    eth1:
+      selectors: from
+      priority: 100
+      iif: eth1
+      fwmark: 0x10
+      fwmask: 0xff
+    wg0:
+      priority: 200
+      suppress_prefixlength: 0
+      to_subnet: true
+"#;
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.validate().is_ok());
+
+        let eth1 = config.routing.rule_config("eth1");
+        assert_eq!(eth1.selectors, RuleSelectors::From);
+        assert!(eth1.selectors.includes(true));
+        assert!(!eth1.selectors.includes(false));
+        assert_eq!(eth1.options.priority, Some(100));
+        assert_eq!(eth1.options.iif.as_deref(), Some("eth1"));
+        assert_eq!(eth1.options.fwmark, Some(0x10));
+        assert_eq!(eth1.options.fwmask, Some(0xff));
+        assert_eq!(config.routing.rule_config("wg0").options.suppress_prefixlength, Some(0));
+        assert!(config.routing.rule_config("wg0").to_subnet);
+        assert!(!eth1.to_subnet);
+        assert_eq!(config.routing.rule_config("eth2"), RuleConfig::default());
+
+        config.routing.rules.get_mut("wg0").unwrap().options.priority = None;
+        assert!(config.validate().is_err());
+
+        config.routing.rules.get_mut("wg0").unwrap().options.priority = Some(200);
+        config.routing.rules.get_mut("eth1").unwrap().options.fwmark = None;
+        assert!(config.validate().is_err());
+
+        config.routing.rules.get_mut("eth1").unwrap().options.fwmark = Some(0x10);
+        config.routing.rules.get_mut("eth1").unwrap().to_subnet = true;
+        assert!(config.validate().is_err());
+
+        assert!(serde_yaml::from_str::<Config>("routing:\n  rules:\n    eth1:\n      selectors: sideways\n").is_err());
+    }
+
+    #[test]
+    fn test_load_balance_config() {
+        let yaml = r#"
+routing:
+  policy_rules: [eth1, eth2]
+  load_balance:
+    members:
+      eth1: 3
+      eth2: 1
+"#;
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.validate().is_ok());
+        let load_balance = config.routing.load_balance.clone().unwrap();
+        assert_eq!(load_balance.metric, DEFAULT_LOAD_BALANCE_METRIC);
+        assert_eq!(load_balance.members["eth1"], 3);
+        assert!(Config::default().routing.load_balance.is_none());
+
+        let members = &mut config.routing.load_balance.as_mut().unwrap().members;
+        members.insert("eth1".to_string(), 0);
+        assert!(config.validate().is_err());
+
+        let members = &mut config.routing.load_balance.as_mut().unwrap().members;
+        members.insert("eth1".to_string(), 256);
+        members.insert("eth3".to_string(), 1);
+        assert!(config.validate().is_err());
+
+        config.routing.load_balance.as_mut().unwrap().members.clear();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_mark_rules_config() {
+        let yaml = r#"
+routing:
+  policy_rules: [eth1, wg0]
+  mark_rules:
+    - fwmark: 0x100
+      fwmask: 0xff00
+      interface: wg0
+      priority: 500
+    - fwmark: 0x2
+      interface: eth1
+"#;
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.validate().is_ok());
+        let rules = &config.routing.mark_rules;
+        assert_eq!(rules[0].fwmark, 0x100);
+        assert_eq!(rules[0].fwmask, Some(0xff00));
+        assert_eq!(rules[1].fwmask, None);
+        assert_eq!(rules[1].priority, None);
+        assert!(Config::default().routing.mark_rules.is_empty());
+
+        config.routing.mark_rules[1].interface = "eth2".to_string();
+        assert!(config.validate().is_err());
+
+        config.routing.mark_rules[1] = config.routing.mark_rules[0].clone();
+        assert!(config.validate().is_err());
+
+        config.routing.mark_rules.truncate(1);
+        config.routing.mark_rules[0].fwmark = 0x101;
+        assert!(config.validate().is_err());
+
+        config.routing.mark_rules[0].fwmark = 0;
+        assert!(config.validate().is_ok());
+        config.routing.mark_rules[0].fwmask = None;
+        assert!(config.validate().is_err());
+
+        config.routing.mark_rules[0].fwmark = 0x100;
+        config.routing.mark_rules[0].priority = Some(0);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_vrf_mode_config() {
+        let yaml = r#"
+routing:
+  mode: vrf
+  policy_rules: [eth1, enp0s20f0u1u2]
+  vrf_names:
+    enp0s20f0u1u2: red
+"#;
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.routing.mode, RoutingMode::Vrf);
+        assert_eq!(config.routing.vrf_name("eth1"), "vrf-eth1");
+        assert_eq!(config.routing.vrf_name("enp0s20f0u1u2"), "red");
+        assert_eq!(config.routing.vrf_devices().len(), 2);
+        assert_eq!(Config::default().routing.mode, RoutingMode::Rules);
+        assert!(serde_yaml::from_str::<Config>("routing:\n  mode: netns\n").is_err());
+
+        // vrf-enp0s20f0u1u2 is too long for an interface name
+        config.routing.vrf_names.clear();
+        assert!(config.validate().is_err());
+
+        config.routing.vrf_names.insert("enp0s20f0u1u2".to_string(), "vrf-eth1".to_string());
+        assert!(config.validate().is_err());
+        config.routing.vrf_names.insert("enp0s20f0u1u2".to_string(), "eth1".to_string());
+        assert!(config.validate().is_err());
+        config.routing.vrf_names.insert("enp0s20f0u1u2".to_string(), "red".to_string());
+
+        config.routing.vrf_names.insert("eth2".to_string(), "blue".to_string());
+        assert!(config.validate().is_err());
+        config.routing.vrf_names.remove("eth2");
+
+        config.routing.rules.insert("eth1".to_string(), RuleConfig::default());
+        assert!(config.validate().is_err());
+        config.routing.mode = RoutingMode::Rules;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_health_check_config() {
+        let yaml = r#"
+routing:
+  health_checks:
+    eth1:
+      targets: [1.1.1.1, "2606:4700:4700::1111"]
+    eth2:
+      targets: [192.0.2.10]
+      method: tcp
+      port: 443
+      interval_seconds: 10
+      timeout_seconds: 2
+      fall: 5
+      rise: 1
+      window: 20
+      thresholds:
+        loss_percent: 5
+        rtt_ms: 150
+"#;
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.validate().is_ok());
+        let eth1 = &config.routing.health_checks["eth1"];
+        assert_eq!(eth1.method, ProbeMethod::Icmp);
+        assert_eq!(eth1.targets.len(), 2);
+        assert_eq!((eth1.interval_seconds, eth1.timeout_seconds), (5, 1));
+        assert_eq!((eth1.fall, eth1.rise), (3, 2));
+        assert_eq!(eth1.window, 10);
+        assert_eq!(eth1.thresholds, QualityThresholds::default());
+        let eth2 = &config.routing.health_checks["eth2"];
+        assert_eq!(eth2.port, Some(443));
+        assert_eq!(eth2.window, 20);
+        assert_eq!(eth2.thresholds.loss_percent, Some(5));
+        assert_eq!(eth2.thresholds.jitter_ms, None);
+
+        let eth2 = config.routing.health_checks.get_mut("eth2").unwrap();
+        eth2.port = None;
+        assert!(config.validate().is_err());
+
+        let eth2 = config.routing.health_checks.get_mut("eth2").unwrap();
+        eth2.port = Some(443);
+        eth2.timeout_seconds = 11;
+        assert!(config.validate().is_err());
+
+        let eth2 = config.routing.health_checks.get_mut("eth2").unwrap();
+        eth2.timeout_seconds = 2;
+        eth2.thresholds.loss_percent = Some(100);
+        assert!(config.validate().is_err());
+
+        config.routing.health_checks.get_mut("eth2").unwrap().thresholds.loss_percent = None;
+        config.routing.health_checks.get_mut("eth1").unwrap().window = 1;
+        assert!(config.validate().is_err());
+
+        config.routing.health_checks.get_mut("eth1").unwrap().window = 10;
+        assert!(config.validate().is_ok());
+        config.routing.health_checks.get_mut("eth1").unwrap().targets.clear();
+        assert!(config.validate().is_err());
+
+        assert!(serde_yaml::from_str::<Config>("routing:\n  health_checks:\n    eth1:\n      targets: [1.1.1.1]\n      method: udp\n").is_err());
+    }
+
+    #[test]
+    fn test_tables_config() {
+        let config = Config::default();
+        assert_eq!(config.routing.tables.base, 1000);
+        assert_eq!(config.routing.tables.range, 1000);
+        assert!(!config.routing.tables.rt_tables);
+
+        let yaml = r#"
+routing:
+  policy_rules: [eth1, wan]
+  tables:
+    base: 2000
+    range: 50
+    rt_tables: true
+    interfaces:
+      wan: 100
+"#;
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.routing.tables.base, 2000);
+        assert_eq!(config.routing.tables.interfaces.get("wan"), Some(&100));
+
+        config.routing.tables.interfaces.insert("eth1".to_string(), 100);
+        assert!(config.validate().is_err());
+
+        config.routing.tables.interfaces.insert("eth1".to_string(), 254);
+        assert!(config.validate().is_err());
+
+        config.routing.tables.interfaces.remove("eth1");
+        config.routing.tables.base = 200;
+        assert!(config.validate().is_err());
+
+        config.routing.tables.base = u32::MAX - 10;
+        assert!(config.validate().is_err());
+    }
 }
@@ -0,0 +1,265 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+
+//! Daemon-wide context shared with the API server
+
+use anyhow::{Context, Result};
+use rtnetlink::Handle;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{watch, RwLock};
+use tracing::{info, warn};
+
+use crate::audit::AuditLogger;
+use crate::config::Config;
+use crate::events::EventHistory;
+use crate::metrics::MetricsHandle;
+use crate::network::NetworkState;
+
+/// Everything a handler needs to report on or act upon the running daemon
+pub struct DaemonContext {
+    /// When the daemon started, for uptime reporting
+    pub started_at: Instant,
+
+    /// Currently active configuration
+    pub config: Arc<RwLock<Config>>,
+
+    /// File the configuration is (re)loaded from
+    pub config_path: PathBuf,
+
+    /// Bumped after every successful configuration reload
+    pub reloads: watch::Sender<u64>,
+
+    /// Links, routes and rules tracked by the watchers
+    pub state: Arc<RwLock<NetworkState>>,
+
+    /// Prometheus metrics (None when metrics are disabled)
+    pub metrics: Option<MetricsHandle>,
+
+    pub audit: Arc<AuditLogger>,
+
+    /// Recent events processed by the listeners and watchers
+    pub events: Arc<EventHistory>,
+
+    /// Netlink handle for querying the kernel
+    pub handle: Handle,
+
+    /// Whether the daemon only logs the changes it would make
+    pub dry_run: bool,
+
+    /// Liveness of the watcher and listener tasks
+    pub tasks: TaskMonitor,
+
+    /// System bus connection shared by the API handlers
+    pub bus: SystemBus,
+}
+
+/// A system bus connection opened on first use and kept for later requests
+#[derive(Default)]
+pub struct SystemBus {
+    connection: tokio::sync::Mutex<Option<zbus::Connection>>,
+}
+
+impl SystemBus {
+    /// The shared connection, connecting if there is none yet
+    pub async fn connection(&self) -> zbus::Result<zbus::Connection> {
+        let mut connection = self.connection.lock().await;
+        if let Some(ref connection) = *connection {
+            return Ok(connection.clone());
+        }
+
+        let new = zbus::Connection::system().await?;
+        *connection = Some(new.clone());
+        Ok(new)
+    }
+
+    /// Drop the shared connection so the next caller reconnects
+    pub async fn reset(&self) {
+        self.connection.lock().await.take();
+    }
+}
+
+/// Tracks which long-running daemon tasks are still running
+#[derive(Default)]
+pub struct TaskMonitor {
+    tasks: Mutex<BTreeMap<&'static str, bool>>,
+}
+
+impl TaskMonitor {
+    /// Run `task`, reporting it alive until it returns
+    pub async fn track<F: Future>(&self, name: &'static str, task: F) -> F::Output {
+        self.set(name, true);
+        let output = task.await;
+        self.set(name, false);
+        output
+    }
+
+    /// Every task seen so far and whether it is still running
+    pub fn snapshot(&self) -> BTreeMap<String, bool> {
+        self.tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, alive)| (name.to_string(), *alive))
+            .collect()
+    }
+
+    fn set(&self, name: &'static str, alive: bool) {
+        self.tasks.lock().unwrap().insert(name, alive);
+    }
+}
+
+/// What a successful configuration reload changed
+#[derive(Debug, Default)]
+pub struct ReloadSummary {
+    /// Interfaces newly listed in `routing.policy_rules`
+    pub policy_added: Vec<String>,
+    /// Interfaces dropped from `routing.policy_rules`; their rules are removed
+    pub policy_removed: Vec<String>,
+    /// Changed sections that only take effect after a restart
+    pub restart_required: Vec<&'static str>,
+}
+
+impl std::fmt::Display for ReloadSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Configuration reloaded")?;
+        if !self.policy_added.is_empty() {
+            write!(f, "; policy interfaces added: {}", self.policy_added.join(", "))?;
+        }
+        if !self.policy_removed.is_empty() {
+            write!(f, "; policy interfaces removed: {}", self.policy_removed.join(", "))?;
+        }
+        if !self.restart_required.is_empty() {
+            write!(f, "; restart required for: {}", self.restart_required.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl DaemonContext {
+    /// Seconds since the daemon started
+    pub fn uptime_seconds(&self) -> u64 {
+        self.started_at.elapsed().as_secs()
+    }
+
+    /// Re-read the configuration file and swap it in if it is valid
+    ///
+    /// Filters, monitored interfaces and script settings take effect with the
+    /// next event. The address watcher is notified so it can tear down or
+    /// set up policy routing for interfaces removed from or added to
+    /// `routing.policy_rules`. On failure the running configuration is kept.
+    pub async fn reload_config(&self) -> Result<ReloadSummary> {
+        let result = self.swap_config().await;
+        self.audit.log_config_reload(result.is_ok());
+
+        match &result {
+            Ok(summary) => {
+                info!("{}", summary);
+                for section in &summary.restart_required {
+                    warn!("Changes to '{}' take effect only after a restart", section);
+                }
+            }
+            Err(e) => warn!("Configuration reload failed, keeping current configuration: {:#}", e),
+        }
+
+        result
+    }
+
+    async fn swap_config(&self) -> Result<ReloadSummary> {
+        let path = self.config_path.to_string_lossy();
+        let new_config = Config::parse_from_path(&path)
+            .with_context(|| format!("Invalid configuration in {}", path))?;
+
+        let summary = {
+            let mut config = self.config.write().await;
+            let summary = diff_configs(&config, &new_config);
+            *config = new_config;
+            summary
+        };
+
+        self.reloads.send_modify(|generation| *generation += 1);
+        Ok(summary)
+    }
+}
+
+fn diff_configs(old: &Config, new: &Config) -> ReloadSummary {
+    let old_policy = &old.routing.policy_rules;
+    let new_policy = &new.routing.policy_rules;
+
+    let mut restart_required = Vec::new();
+    if old.system != new.system {
+        restart_required.push("system");
+    }
+    if old.api != new.api {
+        restart_required.push("api");
+    }
+    if old.metrics != new.metrics {
+        restart_required.push("metrics");
+    }
+    if old.audit != new.audit {
+        restart_required.push("audit");
+    }
+    if old.routing.tables != new.routing.tables {
+        restart_required.push("routing.tables");
+    }
+    if old.routing.mode != new.routing.mode {
+        restart_required.push("routing.mode");
+    }
+
+    ReloadSummary {
+        policy_added: new_policy
+            .iter()
+            .filter(|name| !old_policy.contains(name))
+            .cloned()
+            .collect(),
+        policy_removed: old_policy
+            .iter()
+            .filter(|name| !new_policy.contains(name))
+            .cloned()
+            .collect(),
+        restart_required,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_task_monitor_tracks_exit() {
+        let monitor = TaskMonitor::default();
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+
+        let task = monitor.track("watcher", async {
+            let _ = rx.await;
+        });
+        tokio::pin!(task);
+
+        // Not finished yet: the first poll registers it as alive
+        assert!(futures::poll!(task.as_mut()).is_pending());
+        assert_eq!(monitor.snapshot().get("watcher"), Some(&true));
+
+        tx.send(()).unwrap();
+        task.await;
+        assert_eq!(monitor.snapshot().get("watcher"), Some(&false));
+    }
+
+    #[test]
+    fn test_diff_configs() {
+        let mut old = Config::default();
+        old.routing.policy_rules = vec!["eth0".to_string(), "eth1".to_string()];
+
+        let mut new = old.clone();
+        new.routing.policy_rules = vec!["eth1".to_string(), "eth2".to_string()];
+        new.api.port = 9999;
+        new.routing.tables.base = 2000;
+        new.routing.mode = crate::config::RoutingMode::Vrf;
+
+        let summary = diff_configs(&old, &new);
+        assert_eq!(summary.policy_added, vec!["eth2"]);
+        assert_eq!(summary.policy_removed, vec!["eth0"]);
+        assert_eq!(summary.restart_required, vec!["api", "routing.tables", "routing.mode"]);
+    }
+}
@@ -0,0 +1,196 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+
+//! In-memory history of processed network events
+
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+use crate::api::models::NetworkEvent;
+
+/// Default number of events kept in memory
+pub const DEFAULT_HISTORY_CAPACITY: usize = 1000;
+
+/// Events buffered per live subscriber before it starts missing events
+const SUBSCRIBER_BUFFER: usize = 256;
+
+/// Bounded ring buffer of recent events
+///
+/// Once full, the oldest event is dropped for every new one. The total
+/// counter keeps counting so the status endpoint can report how many
+/// events the daemon has processed since start. Every recorded event is
+/// also broadcast to live subscribers (the streaming endpoint).
+pub struct EventHistory {
+    capacity: usize,
+    events: Mutex<VecDeque<NetworkEvent>>,
+    total: AtomicU64,
+    live: broadcast::Sender<NetworkEvent>,
+}
+
+impl EventHistory {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            events: Mutex::new(VecDeque::with_capacity(capacity)),
+            total: AtomicU64::new(0),
+            live: broadcast::channel(SUBSCRIBER_BUFFER).0,
+        }
+    }
+
+    /// Append an event, evicting the oldest one if the buffer is full
+    pub fn record(&self, event: NetworkEvent) {
+        {
+            let mut events = self.events.lock().unwrap();
+            if events.len() == self.capacity {
+                events.pop_front();
+            }
+            events.push_back(event.clone());
+        }
+        self.total.fetch_add(1, Ordering::Relaxed);
+
+        // No receivers is the common case and not an error
+        let _ = self.live.send(event);
+    }
+
+    /// Receive every event recorded from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<NetworkEvent> {
+        self.live.subscribe()
+    }
+
+    /// Build and record an event stamped with a fresh id and the current time
+    pub fn record_event(&self, event_type: &str, interface: &str, details: serde_json::Value) {
+        self.record(NetworkEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            event_type: event_type.to_string(),
+            interface: interface.to_string(),
+            details,
+        });
+    }
+
+    /// Up to `limit` most recent events matching every given criterion,
+    /// oldest first
+    pub fn query(
+        &self,
+        interface: Option<&str>,
+        event_type: Option<&str>,
+        since: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Vec<NetworkEvent> {
+        let events = self.events.lock().unwrap();
+        let mut matched: Vec<NetworkEvent> = events
+            .iter()
+            .rev()
+            .filter(|e| interface.is_none_or(|name| e.interface == name))
+            .filter(|e| event_type.is_none_or(|t| e.event_type == t))
+            .filter(|e| since.is_none_or(|since| e.timestamp >= since))
+            .take(limit)
+            .cloned()
+            .collect();
+        matched.reverse();
+        matched
+    }
+
+    /// Up to `limit` most recent events, oldest first
+    pub fn recent(&self, limit: usize) -> Vec<NetworkEvent> {
+        let events = self.events.lock().unwrap();
+        let skip = events.len().saturating_sub(limit);
+        events.iter().skip(skip).cloned().collect()
+    }
+
+    /// Number of events currently held
+    pub fn len(&self) -> usize {
+        self.events.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Total number of events recorded since start, including evicted ones
+    pub fn total_recorded(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for EventHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(interface: &str) -> NetworkEvent {
+        NetworkEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now(),
+            event_type: "routable".to_string(),
+            interface: interface.to_string(),
+            details: serde_json::Value::Null,
+        }
+    }
+
+    #[test]
+    fn test_history_evicts_oldest() {
+        let history = EventHistory::new(2);
+        history.record(event("eth0"));
+        history.record(event("eth1"));
+        history.record(event("eth2"));
+
+        let recent = history.recent(10);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].interface, "eth1");
+        assert_eq!(recent[1].interface, "eth2");
+        assert_eq!(history.total_recorded(), 3);
+    }
+
+    #[test]
+    fn test_recent_limit() {
+        let history = EventHistory::default();
+        for name in ["eth0", "eth1", "eth2"] {
+            history.record(event(name));
+        }
+
+        let recent = history.recent(1);
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].interface, "eth2");
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_receives_new_events() {
+        let history = EventHistory::default();
+        history.record(event("eth0"));
+
+        let mut live = history.subscribe();
+        history.record(event("eth1"));
+
+        let received = live.recv().await.unwrap();
+        assert_eq!(received.interface, "eth1");
+        assert!(live.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_query_filters() {
+        let history = EventHistory::default();
+        history.record_event("routable", "eth0", serde_json::Value::Null);
+        history.record_event("address", "eth0", serde_json::Value::Null);
+        history.record_event("routable", "eth1", serde_json::Value::Null);
+        history.record_event("routable", "eth0", serde_json::Value::Null);
+
+        let routable = history.query(Some("eth0"), Some("routable"), None, 10);
+        assert_eq!(routable.len(), 2);
+        assert!(routable[0].timestamp <= routable[1].timestamp);
+
+        assert_eq!(history.query(None, None, None, 3).len(), 3);
+        assert_eq!(history.query(Some("eth1"), None, None, 10).len(), 1);
+
+        let future = Utc::now() + chrono::Duration::seconds(60);
+        assert!(history.query(None, None, Some(future), 10).is_empty());
+    }
+}
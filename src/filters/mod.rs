@@ -62,6 +62,19 @@ pub struct NetworkEvent {
     pub addresses: Vec<IpAddr>,
     pub has_gateway: bool,
     pub dns_servers: Vec<IpAddr>,
+    /// Set for events raised by a gateway health check
+    pub path: Option<PathQuality>,
+}
+
+/// Quality of the path through an uplink, measured by its health check
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PathQuality {
+    /// Mean round-trip time in milliseconds; None if no probe was answered
+    pub rtt_ms: Option<f64>,
+    /// Unanswered probes in percent
+    pub loss_percent: f64,
+    /// Mean difference between consecutive round-trip times in milliseconds
+    pub jitter_ms: Option<f64>,
 }
 
 impl EventFilter {
@@ -211,6 +224,28 @@ impl Filter {
             }
         }
 
+        // Path quality: loss in percent, rtt and jitter in milliseconds
+        for name in ["loss", "rtt", "jitter"] {
+            let Some(rest) = condition.strip_prefix(name) else {
+                continue;
+            };
+            let Some((op, threshold)) = parse_comparison(rest) else {
+                break;
+            };
+            let value = event.path.and_then(|path| match name {
+                "loss" => Some(path.loss_percent),
+                "rtt" => path.rtt_ms,
+                _ => path.jitter_ms,
+            });
+            // Events without a measurement never match
+            return value.is_some_and(|value| match op {
+                ">=" => value >= threshold,
+                "<=" => value <= threshold,
+                ">" => value > threshold,
+                _ => value < threshold,
+            });
+        }
+
         if condition.starts_with("interface ==") || condition.starts_with("interface=") {
             if let Some(start) = condition.find('"') {
                 if let Some(end) = condition[start + 1..].find('"') {
@@ -226,6 +261,16 @@ impl Filter {
     }
 }
 
+/// Split `> 5`, `>= 2.5` and the like into operator and number
+fn parse_comparison(expression: &str) -> Option<(&str, f64)> {
+    let expression = expression.trim_start();
+    let op = [">=", "<=", ">", "<"]
+        .into_iter()
+        .find(|op| expression.starts_with(op))?;
+    let threshold = expression[op.len()..].trim().parse().ok()?;
+    Some((op, threshold))
+}
+
 impl Default for EventFilter {
     fn default() -> Self {
         Self::new()
@@ -254,6 +299,7 @@ filters:
             addresses: vec![],
             has_gateway: true,
             dns_servers: vec![],
+            path: None,
         };
 
         assert!(filter.should_execute(&event));
@@ -276,6 +322,7 @@ filters:
             addresses: vec![],
             has_gateway: false,
             dns_servers: vec![],
+            path: None,
         };
 
         assert!(!filter.should_execute(&event));
@@ -299,8 +346,74 @@ filters:
             addresses: vec![],
             has_gateway: true,
             dns_servers: vec!["8.8.8.8".parse().unwrap()],
+            path: None,
         };
 
         assert!(filter.should_execute(&event));
     }
+
+    #[test]
+    fn test_path_quality_conditions() {
+        let filter_yaml = r#"
+filters:
+  - match_rule:
+      event_type: "uplink_degraded"
+      condition: "loss > 5 || rtt >= 150"
+    action: execute
+  - match_rule:
+      event_type: "uplink_degraded"
+    action: ignore
+"#;
+
+        let filter = EventFilter::from_yaml(filter_yaml).unwrap();
+        let mut event = NetworkEvent {
+            interface: "eth1".to_string(),
+            event_type: "uplink_degraded".to_string(),
+            backend: "netevd".to_string(),
+            addresses: vec![],
+            has_gateway: true,
+            dns_servers: vec![],
+            path: Some(PathQuality {
+                rtt_ms: Some(20.0),
+                loss_percent: 10.0,
+                jitter_ms: Some(1.5),
+            }),
+        };
+        assert!(filter.should_execute(&event));
+
+        event.path = Some(PathQuality {
+            rtt_ms: Some(150.0),
+            loss_percent: 0.0,
+            jitter_ms: None,
+        });
+        assert!(filter.should_execute(&event));
+
+        event.path = Some(PathQuality::default());
+        assert!(!filter.should_execute(&event));
+
+        // Without a measurement the conditions do not match
+        event.path = None;
+        assert!(!filter.should_execute(&event));
+
+        let jitter = Filter {
+            match_rule: MatchRule {
+                interface: None,
+                interface_pattern: None,
+                event_type: None,
+                ip_family: None,
+                backend: None,
+                condition: Some("jitter < 2".to_string()),
+            },
+            action: FilterAction::Execute,
+            scripts: vec![],
+        };
+        event.path = Some(PathQuality {
+            rtt_ms: Some(20.0),
+            loss_percent: 0.0,
+            jitter_ms: Some(1.5),
+        });
+        assert!(jitter.matches(&event));
+        event.path.as_mut().unwrap().jitter_ms = None;
+        assert!(!jitter.matches(&event));
+    }
 }
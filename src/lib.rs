@@ -15,5 +15,7 @@ pub mod api;
 pub mod audit;
 pub mod cli;
 pub mod cloud;
+pub mod context;
+pub mod events;
 pub mod filters;
 pub mod metrics;
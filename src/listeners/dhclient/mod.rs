@@ -13,12 +13,12 @@ use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::sync::RwLock;
-use tokio::time;
 use tracing::{debug, info, warn};
 
 use crate::audit::{AuditLogger, AuditResult};
 use crate::bus::{hostnamed, resolved};
 use crate::config::Config;
+use crate::events::EventHistory;
 use crate::filters::{EventFilter, NetworkEvent};
 use crate::metrics::MetricsHandle;
 use crate::network::NetworkState;
@@ -31,11 +31,13 @@ const DEBOUNCE_DURATION: Duration = Duration::from_secs(2);
 
 /// Watch dhclient lease file for changes
 pub async fn watch_lease_file(
-    config: Config,
+    config: Arc<RwLock<Config>>,
     handle: Handle,
     state: Arc<RwLock<NetworkState>>,
     metrics: Option<MetricsHandle>,
     audit: Arc<AuditLogger>,
+    events: Arc<EventHistory>,
+    dry_run: bool,
 ) -> Result<()> {
     info!("Starting dhclient lease file watcher: {}", DHCLIENT_LEASE_FILE);
 
@@ -76,7 +78,10 @@ pub async fn watch_lease_file(
 
     // Process initial leases if file exists
     if Path::new(DHCLIENT_LEASE_FILE).exists() {
-        if let Err(e) = process_lease_file(&config, &handle, &state, &metrics, &audit).await {
+        let config = config.read().await.clone();
+        if let Err(e) =
+            process_lease_file(&config, &handle, &state, &metrics, &audit, &events, dry_run).await
+        {
             warn!("Failed to process initial lease file: {}", e);
         }
     }
@@ -101,7 +106,11 @@ pub async fn watch_lease_file(
             }
             () = &mut debounce_sleep, if pending_update => {
                 pending_update = false;
-                if let Err(e) = process_lease_file(&config, &handle, &state, &metrics, &audit).await {
+                // Snapshot so a reload applies from the next lease update on
+                let config = config.read().await.clone();
+                if let Err(e) =
+                    process_lease_file(&config, &handle, &state, &metrics, &audit, &events, dry_run).await
+                {
                     warn!("Failed to process lease file: {}", e);
                 }
                 // Reset for next event
@@ -118,6 +127,8 @@ async fn process_lease_file(
     state: &Arc<RwLock<NetworkState>>,
     metrics: &Option<MetricsHandle>,
     audit: &Arc<AuditLogger>,
+    events: &EventHistory,
+    dry_run: bool,
 ) -> Result<()> {
     debug!("Processing lease file: {}", DHCLIENT_LEASE_FILE);
 
@@ -132,6 +143,11 @@ async fn process_lease_file(
 
     // Process each lease
     for (interface, lease) in leases.iter() {
+        if !config.should_monitor_link(interface) {
+            debug!("Interface {} is not monitored, skipping lease", interface);
+            continue;
+        }
+
         info!("Processing DHCP lease for interface {}: {}", interface, lease.address);
 
         // Record metrics for DHCP lease event
@@ -167,22 +183,33 @@ async fn process_lease_file(
         };
 
         // Send DNS to systemd-resolved if configured
+        let mut changes = Vec::new();
         if config.get_use_dns() && !lease.dns_servers.is_empty() {
-            if let Err(e) = resolved::set_link_dns(ifindex, lease.dns_servers.clone()).await {
+            let change = format!("resolved: DNS {}", lease.dns_servers.join(" "));
+            if dry_run {
+                info!("Dry run: would set DNS for {}: {:?}", interface, lease.dns_servers);
+                changes.push(change);
+            } else if let Err(e) = resolved::set_link_dns(ifindex, lease.dns_servers.clone()).await {
                 warn!("Failed to set DNS for {}: {}", interface, e);
             } else {
                 info!("Set DNS for {}: {:?}", interface, lease.dns_servers);
+                changes.push(change);
             }
         }
 
         // Send domain to systemd-resolved if configured
         if config.get_use_domain() {
             if let Some(domain) = &lease.domain_name {
+                let change = format!("resolved: domains {}", domain);
                 let domains = vec![domain.clone()];
-                if let Err(e) = resolved::set_link_domains(ifindex, domains).await {
+                if dry_run {
+                    info!("Dry run: would set domain for {}: {}", interface, domain);
+                    changes.push(change);
+                } else if let Err(e) = resolved::set_link_domains(ifindex, domains).await {
                     warn!("Failed to set domain for {}: {}", interface, e);
                 } else {
                     info!("Set domain for {}: {}", interface, domain);
+                    changes.push(change);
                 }
             }
         }
@@ -191,10 +218,15 @@ async fn process_lease_file(
         if config.get_use_hostname() {
             if let Some(hostname) = &lease.hostname {
                 if crate::system::validation::validate_hostname(hostname) {
-                    if let Err(e) = hostnamed::set_static_hostname(hostname).await {
+                    let change = format!("hostnamed: hostname {}", hostname);
+                    if dry_run {
+                        info!("Dry run: would set hostname: {}", hostname);
+                        changes.push(change);
+                    } else if let Err(e) = hostnamed::set_static_hostname(hostname).await {
                         warn!("Failed to set hostname: {}", e);
                     } else {
                         info!("Set hostname: {}", hostname);
+                        changes.push(change);
                     }
                 } else {
                     warn!("Rejected invalid hostname from DHCP: {}", hostname);
@@ -228,10 +260,13 @@ async fn process_lease_file(
             addresses,
             has_gateway: !lease.routers.is_empty(),
             dns_servers,
+            path: None,
         };
 
         // Check if scripts should be executed based on filters
-        if event_filter.should_execute(&network_event) {
+        let mut script_results = Vec::new();
+        let filtered = !event_filter.should_execute(&network_event);
+        if !filtered {
             debug!("Event passed filters, executing scripts for {}", interface);
 
             let mut env_vars = HashMap::new();
@@ -265,13 +300,31 @@ async fn process_lease_file(
             }
 
             // Execute scripts
-            if let Err(e) = execute::execute_scripts(&script_dir, env_vars).await {
-                warn!("Failed to execute scripts in {}: {}", script_dir, e);
+            let results = execute::run_scripts(&script_dir, env_vars, dry_run).await;
+            match results {
+                Ok(results) => script_results = results,
+                Err(e) => warn!("Failed to execute scripts in {}: {}", script_dir, e),
             }
         } else {
             debug!("Event filtered out, skipping script execution for {}", interface);
         }
 
+        events.record_event(
+            "routable",
+            interface,
+            serde_json::json!({
+                "backend": "dhclient",
+                "ifindex": ifindex,
+                "address": lease.address,
+                "gateway": lease.routers,
+                "dns": lease.dns_servers,
+                "filtered": filtered,
+                "scripts": script_results,
+                "changes": changes,
+                "dry_run": dry_run,
+            }),
+        );
+
         // Handle routing policy rules if configured
         let routing_policy_interfaces = config.routing.get_routing_policy_interfaces();
         if routing_policy_interfaces.contains(interface) {
@@ -66,15 +66,16 @@ pub fn parse_link_state_file(ifindex: u32) -> Result<LinkState> {
 
     let kv = parse_key_value_file(&path)?;
 
-    let mut state = LinkState::default();
-
-    state.admin_state = kv.get("ADMIN_STATE").cloned().unwrap_or_default();
-    state.oper_state = kv.get("OPER_STATE").cloned().unwrap_or_default();
-    state.carrier_state = kv.get("CARRIER_STATE").cloned().unwrap_or_default();
-    state.address_state = kv.get("ADDRESS_STATE").cloned().unwrap_or_default();
-    state.ipv4_address_state = kv.get("IPV4_ADDRESS_STATE").cloned().unwrap_or_default();
-    state.ipv6_address_state = kv.get("IPV6_ADDRESS_STATE").cloned().unwrap_or_default();
-    state.online_state = kv.get("ONLINE_STATE").cloned().unwrap_or_default();
+    let mut state = LinkState {
+        admin_state: kv.get("ADMIN_STATE").cloned().unwrap_or_default(),
+        oper_state: kv.get("OPER_STATE").cloned().unwrap_or_default(),
+        carrier_state: kv.get("CARRIER_STATE").cloned().unwrap_or_default(),
+        address_state: kv.get("ADDRESS_STATE").cloned().unwrap_or_default(),
+        ipv4_address_state: kv.get("IPV4_ADDRESS_STATE").cloned().unwrap_or_default(),
+        ipv6_address_state: kv.get("IPV6_ADDRESS_STATE").cloned().unwrap_or_default(),
+        online_state: kv.get("ONLINE_STATE").cloned().unwrap_or_default(),
+        ..Default::default()
+    };
 
     // Parse DNS servers (space-separated on a single line)
     if let Some(dns_str) = kv.get("DNS") {
@@ -105,14 +106,14 @@ pub fn parse_manager_state_file() -> Result<ManagerState> {
 
     let kv = parse_key_value_file(&path)?;
 
-    let mut state = ManagerState::default();
-
-    state.operational_state = kv.get("OPER_STATE").cloned().unwrap_or_default();
-    state.carrier_state = kv.get("CARRIER_STATE").cloned().unwrap_or_default();
-    state.address_state = kv.get("ADDRESS_STATE").cloned().unwrap_or_default();
-    state.ipv4_address_state = kv.get("IPV4_ADDRESS_STATE").cloned().unwrap_or_default();
-    state.ipv6_address_state = kv.get("IPV6_ADDRESS_STATE").cloned().unwrap_or_default();
-    state.online_state = kv.get("ONLINE_STATE").cloned().unwrap_or_default();
+    let state = ManagerState {
+        operational_state: kv.get("OPER_STATE").cloned().unwrap_or_default(),
+        carrier_state: kv.get("CARRIER_STATE").cloned().unwrap_or_default(),
+        address_state: kv.get("ADDRESS_STATE").cloned().unwrap_or_default(),
+        ipv4_address_state: kv.get("IPV4_ADDRESS_STATE").cloned().unwrap_or_default(),
+        ipv6_address_state: kv.get("IPV6_ADDRESS_STATE").cloned().unwrap_or_default(),
+        online_state: kv.get("ONLINE_STATE").cloned().unwrap_or_default(),
+    };
 
     debug!("Parsed manager state: {:?}", state);
     Ok(state)
@@ -14,6 +14,7 @@ use zbus::Connection;
 use crate::audit::{AuditLogger, AuditResult};
 use crate::bus::{hostnamed, resolved};
 use crate::config::Config;
+use crate::events::EventHistory;
 use crate::filters::{EventFilter, NetworkEvent};
 use crate::metrics::MetricsHandle;
 use crate::network::{address::get_all_addresses, NetworkState};
@@ -50,11 +51,13 @@ fn decode_networkd_ifindex(path: &str) -> Option<u32> {
 
 /// Start systemd-networkd DBus listener
 pub async fn listen_networkd(
-    config: Config,
+    config: Arc<RwLock<Config>>,
     handle: Handle,
     state: Arc<RwLock<NetworkState>>,
     metrics: Option<MetricsHandle>,
     audit: Arc<AuditLogger>,
+    events: Arc<EventHistory>,
+    dry_run: bool,
 ) -> Result<()> {
     info!("Starting systemd-networkd DBus listener");
 
@@ -88,6 +91,8 @@ pub async fn listen_networkd(
                     // Check if this is a link signal from networkd
                     if path.starts_with(NETWORKD_LINK_PREFIX) {
                         if let Some(ifindex) = decode_networkd_ifindex(path) {
+                            // Snapshot so a reload applies from the next signal on
+                            let config = config.read().await.clone();
                             if let Err(e) = handle_link_signal(
                                 &config,
                                 &handle,
@@ -96,6 +101,8 @@ pub async fn listen_networkd(
                                 &mut last_states,
                                 &metrics,
                                 &audit,
+                                &events,
+                                dry_run,
                             )
                             .await
                             {
@@ -112,6 +119,7 @@ pub async fn listen_networkd(
 }
 
 /// Handle link PropertiesChanged signal
+#[allow(clippy::too_many_arguments)]
 async fn handle_link_signal(
     config: &Config,
     handle: &Handle,
@@ -120,6 +128,8 @@ async fn handle_link_signal(
     last_states: &mut HashMap<u32, String>,
     metrics: &Option<MetricsHandle>,
     audit: &Arc<AuditLogger>,
+    events: &EventHistory,
+    dry_run: bool,
 ) -> Result<()> {
     // Get link name
     let link_name = {
@@ -130,6 +140,12 @@ async fn handle_link_signal(
             .unwrap_or_else(|| format!("unknown{}", ifindex))
     };
 
+    if !config.should_monitor_link(&link_name) {
+        debug!("Interface {} is not monitored, ignoring", link_name);
+        last_states.remove(&ifindex);
+        return Ok(());
+    }
+
     // Parse link state from systemd-networkd
     let link_state = parse_link_state_file(ifindex)?;
 
@@ -193,15 +209,28 @@ async fn handle_link_signal(
     };
 
     // Handle systemd-resolved integration
+    let mut changes = Vec::new();
     if config.get_use_dns() && !link_state.dns.is_empty() {
-        if let Err(e) = resolved::set_link_dns(ifindex, link_state.dns.clone()).await {
+        let change = format!("resolved: DNS {}", link_state.dns.join(" "));
+        if dry_run {
+            info!("Dry run: would set DNS for interface {}: {:?}", ifindex, link_state.dns);
+            changes.push(change);
+        } else if let Err(e) = resolved::set_link_dns(ifindex, link_state.dns.clone()).await {
             warn!("Failed to set DNS for interface {}: {}", ifindex, e);
+        } else {
+            changes.push(change);
         }
     }
 
     if config.get_use_domain() && !link_state.domains.is_empty() {
-        if let Err(e) = resolved::set_link_domains(ifindex, link_state.domains.clone()).await {
+        let change = format!("resolved: domains {}", link_state.domains.join(" "));
+        if dry_run {
+            info!("Dry run: would set domains for interface {}: {:?}", ifindex, link_state.domains);
+            changes.push(change);
+        } else if let Err(e) = resolved::set_link_domains(ifindex, link_state.domains.clone()).await {
             warn!("Failed to set domains for interface {}: {}", ifindex, e);
+        } else {
+            changes.push(change);
         }
     }
 
@@ -210,8 +239,14 @@ async fn handle_link_signal(
         // Try to extract hostname from domains
         if let Some(hostname) = link_state.domains.first() {
             if crate::system::validation::validate_hostname(hostname) {
-                if let Err(e) = hostnamed::set_static_hostname(hostname).await {
+                let change = format!("hostnamed: hostname {}", hostname);
+                if dry_run {
+                    info!("Dry run: would set hostname: {}", hostname);
+                    changes.push(change);
+                } else if let Err(e) = hostnamed::set_static_hostname(hostname).await {
                     warn!("Failed to set hostname: {}", e);
+                } else {
+                    changes.push(change);
                 }
             } else {
                 warn!("Rejected invalid hostname from DHCP: {}", hostname);
@@ -221,6 +256,8 @@ async fn handle_link_signal(
 
     // Execute scripts for this state (with filtering)
     let script_dir = get_script_dir(&current_state);
+    let mut script_results = Vec::new();
+    let mut filtered = false;
     if !current_state.is_empty() {
         // Create event filter from config
         let event_filter = EventFilter {
@@ -237,6 +274,7 @@ async fn handle_link_signal(
             dns_servers: link_state.dns.iter()
                 .filter_map(|s| s.parse().ok())
                 .collect(),
+            path: None,
         };
 
         // Check if scripts should be executed based on filters
@@ -257,14 +295,32 @@ async fn handle_link_signal(
                 }
             }
 
-            if let Err(e) = execute::execute_scripts(&script_dir, env_vars).await {
-                warn!("Failed to execute scripts in {}: {}", &script_dir, e);
+            let results = execute::run_scripts(&script_dir, env_vars, dry_run).await;
+            match results {
+                Ok(results) => script_results = results,
+                Err(e) => warn!("Failed to execute scripts in {}: {}", &script_dir, e),
             }
         } else {
             debug!("Event filtered out, skipping script execution for {}", link_name);
+            filtered = true;
         }
     }
 
+    events.record_event(
+        &current_state,
+        &link_name,
+        serde_json::json!({
+            "backend": "systemd-networkd",
+            "ifindex": ifindex,
+            "addresses": address_strings,
+            "dns": link_state.dns,
+            "filtered": filtered,
+            "scripts": script_results,
+            "changes": changes,
+            "dry_run": dry_run,
+        }),
+    );
+
     // Handle routing policy rules for routable state
     if link_state.oper_state == "routable" {
         let routing_policy_interfaces = config.routing.get_routing_policy_interfaces();
@@ -13,6 +13,7 @@ use zbus::Connection;
 
 use crate::audit::{AuditLogger, AuditResult};
 use crate::config::Config;
+use crate::events::EventHistory;
 use crate::filters::{EventFilter, NetworkEvent};
 use crate::metrics::MetricsHandle;
 use crate::network::{address::get_all_addresses, NetworkState};
@@ -39,11 +40,13 @@ const NM_DEVICE_STATE_FAILED: u32 = 120;
 
 /// Start NetworkManager DBus listener
 pub async fn listen_networkmanager(
-    config: Config,
+    config: Arc<RwLock<Config>>,
     handle: Handle,
     state: Arc<RwLock<NetworkState>>,
     metrics: Option<MetricsHandle>,
     audit: Arc<AuditLogger>,
+    events: Arc<EventHistory>,
+    dry_run: bool,
 ) -> Result<()> {
     info!("Starting NetworkManager DBus listener");
 
@@ -77,6 +80,9 @@ pub async fn listen_networkmanager(
                 if let Some(path) = signal.path().map(|p| p.as_str()) {
                     // Check if this is a device signal: /org/freedesktop/NetworkManager/Devices/{N}
                     if path.starts_with("/org/freedesktop/NetworkManager/Devices/") {
+                        // Snapshot so a reload applies from the next signal on
+                        let config = config.read().await.clone();
+
                         // Try to extract device information and handle the signal
                         if let Err(e) = handle_device_state_changed(
                             &config,
@@ -87,6 +93,8 @@ pub async fn listen_networkmanager(
                             &mut last_states,
                             &metrics,
                             &audit,
+                            &events,
+                            dry_run,
                         )
                         .await
                         {
@@ -102,6 +110,7 @@ pub async fn listen_networkmanager(
 }
 
 /// Handle device StateChanged signal
+#[allow(clippy::too_many_arguments)]
 async fn handle_device_state_changed(
     config: &Config,
     handle: &Handle,
@@ -111,6 +120,8 @@ async fn handle_device_state_changed(
     last_states: &mut HashMap<u32, u32>,
     metrics: &Option<MetricsHandle>,
     audit: &Arc<AuditLogger>,
+    events: &EventHistory,
+    dry_run: bool,
 ) -> Result<()> {
     // Get device properties via DBus
     let proxy = zbus::Proxy::new(
@@ -147,6 +158,12 @@ async fn handle_device_state_changed(
         }
     };
 
+    if !config.should_monitor_link(&interface) {
+        debug!("Interface {} is not monitored, ignoring", interface);
+        last_states.remove(&ifindex);
+        return Ok(());
+    }
+
     // Check if state changed
     if let Some(last_state) = last_states.get(&ifindex) {
         if *last_state == device_state {
@@ -188,6 +205,8 @@ async fn handle_device_state_changed(
 
     // Execute scripts for this state (with filtering)
     let script_dir = get_script_dir(&state_name);
+    let mut script_results = Vec::new();
+    let mut filtered = false;
     if !state_name.is_empty() {
         // Create event filter from config
         let event_filter = EventFilter {
@@ -202,6 +221,7 @@ async fn handle_device_state_changed(
             addresses: addresses.clone(),
             has_gateway: device_state == NM_DEVICE_STATE_ACTIVATED,
             dns_servers: Vec::new(), // NetworkManager DNS handled differently
+            path: None,
         };
 
         // Check if scripts should be executed based on filters
@@ -219,14 +239,31 @@ async fn handle_device_state_changed(
                 env_vars.insert("ADDRESSES".to_string(), address_strings.join(" "));
             }
 
-            if let Err(e) = execute::execute_scripts(&script_dir, env_vars).await {
-                warn!("Failed to execute scripts in {}: {}", &script_dir, e);
+            let results = execute::run_scripts(&script_dir, env_vars, dry_run).await;
+            match results {
+                Ok(results) => script_results = results,
+                Err(e) => warn!("Failed to execute scripts in {}: {}", &script_dir, e),
             }
         } else {
             debug!("Event filtered out, skipping script execution for {}", interface);
+            filtered = true;
         }
     }
 
+    events.record_event(
+        &state_name,
+        &interface,
+        serde_json::json!({
+            "backend": "NetworkManager",
+            "ifindex": ifindex,
+            "device_state": device_state,
+            "addresses": address_strings,
+            "filtered": filtered,
+            "scripts": script_results,
+            "dry_run": dry_run,
+        }),
+    );
+
     // Handle routing policy rules for activated state
     if device_state == NM_DEVICE_STATE_ACTIVATED {
         let routing_policy_interfaces = config.routing.get_routing_policy_interfaces();
@@ -241,6 +278,33 @@ async fn handle_device_state_changed(
     Ok(())
 }
 
+/// Look up the current state of the NetworkManager device backing an interface
+pub async fn get_device_state(connection: &Connection, interface: &str) -> Result<String> {
+    let manager = zbus::Proxy::new(connection, NM_SERVICE, NM_PATH, NM_SERVICE)
+        .await
+        .context("Failed to create NetworkManager proxy")?;
+
+    let device_path: zbus::zvariant::OwnedObjectPath = manager
+        .call("GetDeviceByIpIface", &(interface,))
+        .await
+        .with_context(|| format!("NetworkManager has no device for {}", interface))?;
+
+    let device = zbus::Proxy::new(
+        connection,
+        NM_SERVICE,
+        device_path.as_str(),
+        "org.freedesktop.NetworkManager.Device",
+    )
+    .await?;
+
+    let state: u32 = device
+        .get_property("State")
+        .await
+        .context("Failed to read device State")?;
+
+    Ok(device_state_to_string(state))
+}
+
 /// Convert NetworkManager device state to string
 fn device_state_to_string(state: u32) -> String {
     match state {
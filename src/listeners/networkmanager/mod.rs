@@ -5,4 +5,4 @@
 pub mod dbus;
 
 // Re-export the main listener function
-pub use dbus::listen_networkmanager;
+pub use dbus::{get_device_state, listen_networkmanager};
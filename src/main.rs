@@ -8,44 +8,80 @@
 use anyhow::{Context, Result};
 use rtnetlink::Handle;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::signal::unix::{signal, SignalKind};
-use tokio::sync::RwLock;
+use tokio::sync::{watch, RwLock};
+use tokio::task::JoinHandle;
 use tracing::{info, warn};
 use tracing_subscriber::EnvFilter;
 
-mod audit;
-mod bus;
-mod config;
-mod filters;
-mod listeners;
-mod metrics;
-mod network;
-mod system;
-
-use audit::AuditLogger;
-use config::Config;
-use metrics::{Metrics, MetricsHandle};
-use network::{link, watcher, NetworkState};
-use std::path::PathBuf;
-use system::user;
+use netevd::api::ApiServer;
+use netevd::audit::{AuditLogger, AuditResult};
+use netevd::cli::{handler, Cli, Commands};
+use netevd::config::{Config, RoutingMode, ShutdownPolicy, DEFAULT_CONFIG_PATH};
+use netevd::context::{DaemonContext, SystemBus, TaskMonitor};
+use netevd::events::EventHistory;
+use netevd::listeners;
+use netevd::metrics::{self, Metrics, MetricsHandle};
+use netevd::network::table::{TableAllocator, RT_TABLES_PATH};
+use netevd::network::{health, link, watcher, NetworkState};
+use netevd::system::user;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 const DEFAULT_USER: &str = "netevd";
+const API_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+const API_RESTART_DELAY: Duration = Duration::from_secs(5);
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse_args();
+
+    match cli.command {
+        None | Some(Commands::Start { .. }) => run_daemon(cli).await,
+        Some(_) => {
+            if let Err(e) = handler::handle_command(cli).await {
+                eprintln!("Error: {:#}", e);
+                std::process::exit(handler::exit_code(&e));
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn run_daemon(cli: Cli) -> Result<()> {
+    let started_at = Instant::now();
+
+    // A missing file at the default path means "run with defaults"; an
+    // explicitly given path that doesn't exist is a mistake
+    if cli.config.as_os_str() != DEFAULT_CONFIG_PATH && !cli.config.exists() {
+        anyhow::bail!("Configuration file {} does not exist", cli.config.display());
+    }
+
     // Parse configuration first (before logging init so we can apply log level)
-    let config = Config::parse().context("Failed to parse configuration")?;
+    let config = Config::parse_from_path(&cli.config.to_string_lossy())
+        .context("Failed to parse configuration")?;
 
-    // Initialize logging with config level (RUST_LOG env takes precedence)
-    init_logging(&config.system.log_level);
+    // Initialize logging with config level, raised by -v (RUST_LOG env takes precedence)
+    let log_level = match cli.verbose {
+        0 => config.system.log_level.as_str(),
+        1 => "debug",
+        _ => "trace",
+    };
+    init_logging(log_level);
 
     info!("Starting netevd - Network Event Daemon");
     info!("Version: {}", env!("CARGO_PKG_VERSION"));
 
     info!(
-        "Configuration loaded: backend={}, log_level={}",
-        config.system.backend, config.system.log_level
+        "Configuration loaded from {}: backend={}, log_level={}",
+        cli.config.display(),
+        config.system.backend,
+        config.system.log_level
     );
+    if cli.dry_run {
+        info!("Dry-run mode: scripts, DNS/hostname updates and routing changes are logged, not applied");
+    }
 
     // Initialize metrics if enabled
     let metrics: Option<MetricsHandle> = if config.metrics.enabled {
@@ -77,6 +113,26 @@ async fn main() -> Result<()> {
         info!("Audit logging disabled");
     }
 
+    // Assign the policy routing tables; the rt_tables.d file is written
+    // while still root and handed to the daemon user, so that reloads
+    // adding interfaces can rewrite it
+    let tables = TableAllocator::for_config(&config.routing);
+    if config.routing.tables.rt_tables {
+        if cli.dry_run {
+            info!("Dry run: would write routing table names to {}", RT_TABLES_PATH);
+        } else {
+            match tables.write_rt_tables(Path::new(RT_TABLES_PATH)) {
+                Ok(()) => info!("Routing table names written to {}", RT_TABLES_PATH),
+                Err(e) => warn!("{:#}", e),
+            }
+            if user::is_root() {
+                if let Err(e) = user::chown_to(Path::new(RT_TABLES_PATH), DEFAULT_USER) {
+                    warn!("{:#}", e);
+                }
+            }
+        }
+    }
+
     // Drop privileges if running as root
     if user::is_root() {
         info!("Running as root, attempting to drop privileges to user '{}'", DEFAULT_USER);
@@ -87,7 +143,18 @@ async fn main() -> Result<()> {
     }
 
     // Initialize network state
-    let state = Arc::new(RwLock::new(NetworkState::new()));
+    let state = Arc::new(RwLock::new(NetworkState {
+        tables,
+        rule_configs: config.routing.rules.clone().into_iter().collect(),
+        load_balance: config.routing.load_balance.clone(),
+        mark_rule_configs: config.routing.mark_rules.clone(),
+        mode: config.routing.mode,
+        vrf_names: match config.routing.mode {
+            RoutingMode::Vrf => config.routing.vrf_devices(),
+            RoutingMode::Rules => Default::default(),
+        },
+        ..NetworkState::new()
+    }));
     info!("Network state initialized");
 
     // Get netlink handle
@@ -105,49 +172,178 @@ async fn main() -> Result<()> {
     }
     info!("Initial network links acquired");
 
-    // Get routing policy interfaces from config
-    let routing_policy_interfaces = config.routing.get_routing_policy_interfaces();
-
     // Clone handles for async tasks
     let state_addr = state.clone();
     let state_route = state.clone();
     let state_link = state.clone();
+    let state_rule = state.clone();
+    let state_health = state.clone();
     let state_listener = state.clone();
     let handle_addr = handle.clone();
     let handle_route = handle.clone();
     let handle_link = handle.clone();
+    let handle_rule = handle.clone();
+    let handle_health = handle.clone();
     let handle_listener = handle.clone();
-    let config_listener = config.clone();
     let metrics_listener = metrics.clone();
     let audit_listener = audit_logger.clone();
 
+    // Shared context for the API handlers
+    let context = Arc::new(DaemonContext {
+        started_at,
+        config: Arc::new(RwLock::new(config.clone())),
+        config_path: cli.config.clone(),
+        reloads: watch::channel(0).0,
+        state: state.clone(),
+        metrics: metrics.clone(),
+        audit: audit_logger.clone(),
+        events: Arc::new(EventHistory::default()),
+        handle: handle.clone(),
+        dry_run: cli.dry_run,
+        tasks: TaskMonitor::default(),
+        bus: SystemBus::default(),
+    });
+
+    // Start the REST API server if enabled
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let api_task: Option<JoinHandle<()>> = if config.api.enabled {
+        let server = ApiServer::new(&config.api, context.clone())
+            .context("Failed to configure API server")?;
+        info!("Starting API server on {}", server.addr());
+        Some(tokio::spawn(supervise_api_server(server, shutdown_rx)))
+    } else {
+        info!("API server disabled");
+        None
+    };
+
     // Set up signal handlers
     let mut sigterm = signal(SignalKind::terminate())
         .context("Failed to set up SIGTERM handler")?;
     let mut sigint = signal(SignalKind::interrupt())
         .context("Failed to set up SIGINT handler")?;
+    let mut sighup = signal(SignalKind::hangup())
+        .context("Failed to set up SIGHUP handler")?;
 
     info!("netevd initialized successfully, waiting for events...");
 
+    // Watchers and the listener run for the lifetime of the daemon; pin
+    // them so the loop below can poll them again after handling a SIGHUP.
+    // Each is tracked so /health can report a task that has stopped.
+    let tasks = &context.tasks;
+    // The link watcher asks the rule watcher to check a table once its
+    // link is back up
+    let (repair_tx, repair_rx) = tokio::sync::mpsc::unbounded_channel();
+    let address_watcher = tasks.track(
+        "address_watcher",
+        watcher::watch_addresses(
+            handle_addr,
+            state_addr,
+            context.config.clone(),
+            context.reloads.subscribe(),
+            context.events.clone(),
+            context.dry_run,
+        ),
+    );
+    let route_watcher = tasks.track(
+        "route_watcher",
+        watcher::watch_routes(handle_route, state_route, context.events.clone(), context.dry_run),
+    );
+    let link_watcher = tasks.track(
+        "link_watcher",
+        watcher::watch_links(handle_link, state_link, repair_tx, context.events.clone(), context.dry_run),
+    );
+    let rule_watcher = tasks.track(
+        "rule_watcher",
+        watcher::watch_rules(
+            handle_rule,
+            state_rule,
+            repair_rx,
+            context.events.clone(),
+            audit_logger.clone(),
+            metrics.clone(),
+            context.dry_run,
+        ),
+    );
+    let gateway_checker = tasks.track(
+        "gateway_checker",
+        health::watch_gateways(
+            handle_health,
+            state_health,
+            context.config.clone(),
+            context.reloads.subscribe(),
+            context.events.clone(),
+            metrics.clone(),
+            context.dry_run,
+        ),
+    );
+    let listener = tasks.track(
+        "listener",
+        spawn_listener(
+            context.config.clone(),
+            handle_listener,
+            state_listener,
+            metrics_listener,
+            audit_listener,
+            context.events.clone(),
+            context.dry_run,
+        ),
+    );
+    tokio::pin!(address_watcher, route_watcher, link_watcher, rule_watcher, gateway_checker, listener);
+
     // Main event loop with async watchers
-    tokio::select! {
-        _ = sigterm.recv() => {
-            info!("Received SIGTERM, shutting down gracefully");
-        }
-        _ = sigint.recv() => {
-            info!("Received SIGINT (Ctrl+C), shutting down gracefully");
-        }
-        result = watcher::watch_addresses(handle_addr, state_addr, routing_policy_interfaces) => {
-            warn!("Address watcher exited: {:?}", result);
-        }
-        result = watcher::watch_routes(handle_route, state_route) => {
-            warn!("Route watcher exited: {:?}", result);
-        }
-        result = watcher::watch_links(handle_link, state_link) => {
-            warn!("Link watcher exited: {:?}", result);
+    loop {
+        tokio::select! {
+            _ = sigterm.recv() => {
+                info!("Received SIGTERM, shutting down gracefully");
+                break;
+            }
+            _ = sigint.recv() => {
+                info!("Received SIGINT (Ctrl+C), shutting down gracefully");
+                break;
+            }
+            _ = sighup.recv() => {
+                info!("Received SIGHUP, reloading configuration");
+                // Outcome is logged and audited by reload_config
+                let _ = context.reload_config().await;
+            }
+            result = &mut address_watcher => {
+                warn!("Address watcher exited: {:?}", result);
+                break;
+            }
+            result = &mut route_watcher => {
+                warn!("Route watcher exited: {:?}", result);
+                break;
+            }
+            result = &mut link_watcher => {
+                warn!("Link watcher exited: {:?}", result);
+                break;
+            }
+            result = &mut rule_watcher => {
+                warn!("Rule watcher exited: {:?}", result);
+                break;
+            }
+            result = &mut gateway_checker => {
+                warn!("Gateway health checks exited: {:?}", result);
+                break;
+            }
+            result = &mut listener => {
+                warn!("Backend listener exited: {:?}", result);
+                break;
+            }
         }
-        result = spawn_listener(config_listener, handle_listener, state_listener, metrics_listener, audit_listener) => {
-            warn!("Backend listener exited: {:?}", result);
+    }
+
+    // Tear down policy routing before the API goes away, so /health keeps
+    // answering while it runs
+    flush_on_shutdown(&context).await;
+
+    // Stop the API server, giving in-flight requests a bounded time to finish
+    let _ = shutdown_tx.send(true);
+    if let Some(task) = api_task {
+        match tokio::time::timeout(API_SHUTDOWN_TIMEOUT, task).await {
+            Ok(Err(e)) => warn!("API server task failed: {}", e),
+            Err(_) => warn!("API server did not stop within {:?}", API_SHUTDOWN_TIMEOUT),
+            Ok(Ok(())) => {}
         }
     }
 
@@ -155,6 +351,57 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Remove netevd's rules and routes if `routing.on_shutdown` is `flush`
+///
+/// Bounded by `routing.shutdown_timeout_seconds`; each interface's teardown
+/// is audited as `shutdown_flush`, marked `timed_out` if the flush was cut
+/// short. Steps that never started are not audited.
+async fn flush_on_shutdown(context: &DaemonContext) {
+    let routing = context.config.read().await.routing.clone();
+    if routing.on_shutdown != ShutdownPolicy::Flush {
+        return;
+    }
+
+    info!("Flushing policy routing (routing.on_shutdown: flush)");
+    let results = watcher::FlushResults::default();
+    let flush = watcher::flush_policy_routing(&context.handle, &context.state, context.dry_run, &results);
+    let timed_out = tokio::time::timeout(routing.shutdown_timeout(), flush).await.is_err();
+    if timed_out {
+        warn!(
+            "Flushing policy routing did not finish within {}s",
+            routing.shutdown_timeout_seconds
+        );
+    }
+
+    for (interface, result) in results.into_inner().unwrap() {
+        let (audit_result, details) = match result {
+            Some(Ok(changes)) => (
+                AuditResult::Success,
+                serde_json::json!({
+                    "changes": changes,
+                    "dry_run": context.dry_run,
+                    "timed_out": timed_out,
+                }),
+            ),
+            Some(Err(e)) => {
+                warn!("Failed to flush policy routing for {}: {:#}", interface, e);
+                (
+                    AuditResult::Failure,
+                    serde_json::json!({ "error": format!("{:#}", e), "timed_out": timed_out }),
+                )
+            }
+            // Still running when the timeout hit
+            None => (
+                AuditResult::Failure,
+                serde_json::json!({ "error": "did not finish in time", "timed_out": timed_out }),
+            ),
+        };
+        context
+            .audit
+            .log_network_event(&interface, "shutdown_flush", audit_result, Some(details));
+    }
+}
+
 /// Initialize logging with the given default level (RUST_LOG env takes precedence)
 fn init_logging(default_level: &str) {
     let env_filter = EnvFilter::try_from_default_env()
@@ -167,27 +414,62 @@ fn init_logging(default_level: &str) {
         .init();
 }
 
+/// Serve the API until shutdown, restarting it whenever it fails
+///
+/// A bind failure or a server error is logged and retried after
+/// `API_RESTART_DELAY`; network event handling never waits on the API.
+async fn supervise_api_server(server: ApiServer, mut shutdown: watch::Receiver<bool>) {
+    loop {
+        let mut stop = shutdown.clone();
+        let result = server
+            .clone()
+            .run(async move {
+                let _ = stop.wait_for(|stop| *stop).await;
+            })
+            .await;
+        if *shutdown.borrow() {
+            if let Err(e) = result {
+                warn!("API server stopped with error: {:#}", e);
+            }
+            return;
+        }
+
+        match result {
+            Ok(()) => warn!("API server exited unexpectedly, restarting in {:?}", API_RESTART_DELAY),
+            Err(e) => warn!("{:#}; restarting in {:?}", e, API_RESTART_DELAY),
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(API_RESTART_DELAY) => {}
+            _ = shutdown.wait_for(|stop| *stop) => return,
+        }
+    }
+}
+
 /// Spawn the appropriate listener based on the configured backend
 async fn spawn_listener(
-    config: Config,
+    config: Arc<RwLock<Config>>,
     handle: Handle,
     state: Arc<RwLock<NetworkState>>,
     metrics: Option<MetricsHandle>,
     audit: Arc<AuditLogger>,
+    events: Arc<EventHistory>,
+    dry_run: bool,
 ) -> Result<()> {
-    match config.system.backend.as_str() {
+    // The backend cannot change on reload, so read it once
+    let backend = config.read().await.system.backend.clone();
+    match backend.as_str() {
         "systemd-networkd" => {
             info!("Starting systemd-networkd listener");
-            listeners::networkd::listen_networkd(config, handle, state, metrics, audit).await
+            listeners::networkd::listen_networkd(config, handle, state, metrics, audit, events, dry_run).await
         }
         "NetworkManager" => {
             info!("Starting NetworkManager listener");
-            listeners::networkmanager::listen_networkmanager(config, handle, state, metrics, audit).await
+            listeners::networkmanager::listen_networkmanager(config, handle, state, metrics, audit, events, dry_run).await
         }
         "dhclient" => {
             info!("Starting dhclient listener");
-            listeners::dhclient::watch_lease_file(config, handle, state, metrics, audit).await
+            listeners::dhclient::watch_lease_file(config, handle, state, metrics, audit, events, dry_run).await
         }
-        _ => anyhow::bail!("Unknown backend: {}", config.system.backend),
+        _ => anyhow::bail!("Unknown backend: {}", backend),
     }
 }
@@ -1,5 +1,5 @@
 use prometheus::{
-    Counter, CounterVec, Gauge, HistogramOpts, HistogramVec, Opts, Registry,
+    Counter, CounterVec, Gauge, GaugeVec, HistogramOpts, HistogramVec, Opts, Registry,
 };
 use std::sync::Arc;
 
@@ -18,6 +18,13 @@ pub struct Metrics {
     // Routing metrics
     pub routing_rules_total: Gauge,
     pub routes_total: Gauge,
+    pub routing_repairs_total: CounterVec,
+
+    // Uplink health check metrics
+    pub uplink_up: GaugeVec,
+    pub uplink_rtt_seconds: GaugeVec,
+    pub uplink_loss_ratio: GaugeVec,
+    pub uplink_jitter_seconds: GaugeVec,
 
     // Script execution metrics
     pub script_executions_total: CounterVec,
@@ -89,6 +96,52 @@ impl Metrics {
         ))?;
         registry.register(Box::new(routes_total.clone()))?;
 
+        let routing_repairs_total = CounterVec::new(
+            Opts::new(
+                "netevd_routing_repairs_total",
+                "Total number of rules and routes re-installed after external removal",
+            ),
+            &["kind", "result"],
+        )?;
+        registry.register(Box::new(routing_repairs_total.clone()))?;
+
+        // Uplink health check metrics
+        let uplink_up = GaugeVec::new(
+            Opts::new(
+                "netevd_uplink_up",
+                "Whether the uplink's gateway passes its health check (1) or not (0)",
+            ),
+            &["interface"],
+        )?;
+        registry.register(Box::new(uplink_up.clone()))?;
+
+        let uplink_rtt_seconds = GaugeVec::new(
+            Opts::new(
+                "netevd_uplink_rtt_seconds",
+                "Mean round-trip time of the uplink's answered health check probes",
+            ),
+            &["interface"],
+        )?;
+        registry.register(Box::new(uplink_rtt_seconds.clone()))?;
+
+        let uplink_loss_ratio = GaugeVec::new(
+            Opts::new(
+                "netevd_uplink_loss_ratio",
+                "Share of the uplink's health check probes that went unanswered",
+            ),
+            &["interface"],
+        )?;
+        registry.register(Box::new(uplink_loss_ratio.clone()))?;
+
+        let uplink_jitter_seconds = GaugeVec::new(
+            Opts::new(
+                "netevd_uplink_jitter_seconds",
+                "Mean difference between consecutive round-trip times of the uplink's probes",
+            ),
+            &["interface"],
+        )?;
+        registry.register(Box::new(uplink_jitter_seconds.clone()))?;
+
         // Script execution metrics
         let script_executions_total = CounterVec::new(
             Opts::new(
@@ -156,6 +209,11 @@ impl Metrics {
             interface_state_changes,
             routing_rules_total,
             routes_total,
+            routing_repairs_total,
+            uplink_up,
+            uplink_rtt_seconds,
+            uplink_loss_ratio,
+            uplink_jitter_seconds,
             script_executions_total,
             script_duration,
             script_failures_total,
@@ -4,7 +4,9 @@
 
 use anyhow::{Context, Result};
 use futures::stream::TryStreamExt;
-use rtnetlink::packet_route::address::AddressAttribute;
+use rtnetlink::packet_route::address::{
+    AddressAttribute, AddressFlags, AddressHeaderFlags, AddressMessage, AddressScope,
+};
 use rtnetlink::packet_route::AddressFamily;
 use rtnetlink::Handle;
 use std::net::IpAddr;
@@ -40,6 +42,87 @@ pub async fn get_ipv4_addresses(handle: &Handle, ifindex: u32) -> Result<Vec<IpA
     Ok(addresses)
 }
 
+/// Get the addresses of an interface that get policy routing: IPv4
+/// addresses followed by usable IPv6 addresses
+pub async fn get_policy_addresses(handle: &Handle, ifindex: u32) -> Result<Vec<IpAddr>> {
+    Ok(get_policy_prefixes(handle, ifindex)
+        .await?
+        .into_iter()
+        .map(|(address, _)| address)
+        .collect())
+}
+
+/// Like [`get_policy_addresses`], with the prefix length of each address
+pub async fn get_policy_prefixes(handle: &Handle, ifindex: u32) -> Result<Vec<(IpAddr, u8)>> {
+    let mut ipv4 = Vec::new();
+    let mut ipv6 = Vec::new();
+    let mut addr_stream = handle.address().get().set_link_index_filter(ifindex).execute();
+
+    while let Some(msg) = addr_stream
+        .try_next()
+        .await
+        .context("Failed to get next address")?
+    {
+        let prefix_len = msg.header.prefix_len;
+        match msg.header.family {
+            AddressFamily::Inet => {
+                for attr in &msg.attributes {
+                    if let AddressAttribute::Address(ip_addr) = attr {
+                        if !is_link_local(ip_addr) {
+                            ipv4.push((*ip_addr, prefix_len));
+                        }
+                    }
+                }
+            }
+            AddressFamily::Inet6 => {
+                if let Some(ip_addr) = usable_ipv6_address(&msg) {
+                    debug!("Found IPv6 address {}/{} on interface {}", ip_addr, prefix_len, ifindex);
+                    ipv6.push((ip_addr, prefix_len));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    ipv4.extend(ipv6);
+    Ok(ipv4)
+}
+
+/// The address of an IPv6 address message if it can be used as a source
+///
+/// Only global-scope addresses qualify, so link-local addresses are
+/// skipped. So are tentative addresses (DAD still running) and ones that
+/// failed DAD, since the kernel won't source traffic from them; the address
+/// is picked up by the RTM_NEWADDR sent once DAD completes.
+fn usable_ipv6_address(msg: &AddressMessage) -> Option<IpAddr> {
+    if msg.header.scope != AddressScope::Universe {
+        return None;
+    }
+
+    // IFA_FLAGS carries the full 32-bit flags; the header only has the low 8
+    let unusable = match msg.attributes.iter().find_map(|attr| match attr {
+        AddressAttribute::Flags(flags) => Some(*flags),
+        _ => None,
+    }) {
+        Some(flags) => flags.intersects(AddressFlags::Tentative | AddressFlags::Dadfailed),
+        None => msg
+            .header
+            .flags
+            .intersects(AddressHeaderFlags::Tentative | AddressHeaderFlags::Dadfailed),
+    };
+    if unusable {
+        return None;
+    }
+
+    msg.attributes
+        .iter()
+        .find_map(|attr| match attr {
+            AddressAttribute::Address(ip_addr) => Some(*ip_addr),
+            _ => None,
+        })
+        .filter(|ip_addr| ip_addr.is_ipv6() && !is_link_local(ip_addr))
+}
+
 /// Get all addresses (IPv4 and IPv6) for a specific interface
 pub async fn get_all_addresses(handle: &Handle, ifindex: u32) -> Result<Vec<IpAddr>> {
     let mut addresses = Vec::new();
@@ -65,6 +148,30 @@ pub async fn get_all_addresses(handle: &Handle, ifindex: u32) -> Result<Vec<IpAd
     Ok(addresses)
 }
 
+/// Prefix length that covers a single address of the family
+pub fn host_prefix_len(addr: &IpAddr) -> u8 {
+    if addr.is_ipv4() {
+        32
+    } else {
+        128
+    }
+}
+
+/// Network address of the subnet an address with the given prefix length
+/// belongs to
+pub fn network_address(addr: IpAddr, prefix_len: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(ipv4) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix_len.min(32))).unwrap_or(0);
+            IpAddr::V4((u32::from(ipv4) & mask).into())
+        }
+        IpAddr::V6(ipv6) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix_len.min(128))).unwrap_or(0);
+            IpAddr::V6((u128::from(ipv6) & mask).into())
+        }
+    }
+}
+
 /// Check if an IP address is link-local
 /// IPv4: 169.254.0.0/16
 /// IPv6: fe80::/10
@@ -95,6 +202,51 @@ mod tests {
         assert!(!is_link_local(&IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))));
     }
 
+    fn ipv6_message(address: &str, scope: AddressScope, flags: AddressFlags) -> AddressMessage {
+        let mut msg = AddressMessage::default();
+        msg.header.family = AddressFamily::Inet6;
+        msg.header.prefix_len = 64;
+        msg.header.scope = scope;
+        msg.attributes.push(AddressAttribute::Address(address.parse().unwrap()));
+        msg.attributes.push(AddressAttribute::Flags(flags));
+        msg
+    }
+
+    #[test]
+    fn test_usable_ipv6_address() {
+        let global = ipv6_message("2001:db8::10", AddressScope::Universe, AddressFlags::Permanent);
+        assert_eq!(usable_ipv6_address(&global), Some("2001:db8::10".parse().unwrap()));
+
+        let ula = ipv6_message("fd00::10", AddressScope::Universe, AddressFlags::empty());
+        assert!(usable_ipv6_address(&ula).is_some());
+
+        let link_local = ipv6_message("fe80::1", AddressScope::Link, AddressFlags::Permanent);
+        assert_eq!(usable_ipv6_address(&link_local), None);
+
+        let tentative = ipv6_message("2001:db8::11", AddressScope::Universe, AddressFlags::Tentative);
+        assert_eq!(usable_ipv6_address(&tentative), None);
+
+        let dad_failed = ipv6_message(
+            "2001:db8::12",
+            AddressScope::Universe,
+            AddressFlags::Tentative | AddressFlags::Dadfailed,
+        );
+        assert_eq!(usable_ipv6_address(&dad_failed), None);
+    }
+
+    #[test]
+    fn test_network_address() {
+        let addr: IpAddr = "192.168.1.100".parse().unwrap();
+        assert_eq!(network_address(addr, 24), "192.168.1.0".parse::<IpAddr>().unwrap());
+        assert_eq!(network_address(addr, 32), addr);
+        assert_eq!(network_address(addr, 0), "0.0.0.0".parse::<IpAddr>().unwrap());
+
+        let addr: IpAddr = "2001:db8:1:2::10".parse().unwrap();
+        assert_eq!(network_address(addr, 64), "2001:db8:1:2::".parse::<IpAddr>().unwrap());
+        assert_eq!(network_address(addr, 128), addr);
+        assert_eq!(host_prefix_len(&addr), 128);
+    }
+
     #[test]
     fn test_is_link_local_ipv6() {
         assert!(is_link_local(&IpAddr::V6(Ipv6Addr::new(
@@ -0,0 +1,321 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+
+//! Weighted multipath default route over several uplinks
+//!
+//! With `routing.load_balance`, netevd keeps one multipath default route
+//! per address family in the main table. Its nexthops are the gateways of
+//! the member interfaces that are usable right now: the link is up, its
+//! health check (if any) passes, and netevd has set up policy routing for
+//! the family, so the interface has an address and a gateway. The address,
+//! route and link watchers and the health checks call [`update`] whenever
+//! one of those changes.
+
+use anyhow::{Context, Result};
+use rtnetlink::packet_route::route::{RouteAttribute, RouteHeader, RouteMessage, RouteNextHop};
+use rtnetlink::{Handle, RouteMessageBuilder, RouteNextHopBuilder};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, warn};
+
+use super::{is_not_found, NetworkState};
+use crate::config::LoadBalanceConfig;
+
+/// Metric of the multipath route unless configured otherwise
+pub const DEFAULT_LOAD_BALANCE_METRIC: u32 = 10;
+
+/// Updates come from several watchers; one at a time keeps the kernel and
+/// the tracked route in step
+static UPDATE: Mutex<()> = Mutex::const_new(());
+
+/// One member's share of a multipath route
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nexthop {
+    pub interface: String,
+    pub ifindex: u32,
+    pub gateway: IpAddr,
+    /// Weight as in `ip route`, 1 to 256
+    pub weight: u16,
+}
+
+/// A multipath default route in the main table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultipathRoute {
+    pub metric: u32,
+    /// Never empty
+    pub nexthops: Vec<Nexthop>,
+}
+
+impl MultipathRoute {
+    pub fn is_ipv6(&self) -> bool {
+        self.nexthops
+            .first()
+            .is_some_and(|nexthop| nexthop.gateway.is_ipv6())
+    }
+
+    /// The route in `ip route` terms
+    pub fn describe(&self) -> String {
+        let mut description = format!("default metric {}", self.metric);
+        for nexthop in &self.nexthops {
+            description.push_str(&format!(
+                " nexthop via {} dev {} weight {}",
+                nexthop.gateway, nexthop.interface, nexthop.weight
+            ));
+        }
+        description
+    }
+}
+
+/// The multipath route a family should have; `None` if no member is usable
+pub fn wanted_route(state: &NetworkState, config: &LoadBalanceConfig, ipv6: bool) -> Option<MultipathRoute> {
+    let nexthops: Vec<Nexthop> = config
+        .members
+        .iter()
+        .filter_map(|(name, weight)| {
+            let route = state.usable_uplink(name, ipv6)?;
+            Some(Nexthop {
+                interface: name.clone(),
+                ifindex: route.ifindex,
+                gateway: route.default_gateway(ipv6)?,
+                weight: *weight,
+            })
+        })
+        .collect();
+
+    (!nexthops.is_empty()).then_some(MultipathRoute {
+        metric: config.metric,
+        nexthops,
+    })
+}
+
+/// Bring the multipath default routes in line with the members' state
+///
+/// Returns the changes made, in `ip route` terms. In dry-run mode nothing
+/// is sent to the kernel or tracked; the changes are only logged.
+pub async fn update(handle: &Handle, state: &Arc<RwLock<NetworkState>>, dry_run: bool) -> Vec<String> {
+    let _guard = UPDATE.lock().await;
+    let mut changes = Vec::new();
+
+    for ipv6 in [false, true] {
+        let (wanted, installed) = {
+            let state_read = state.read().await;
+            (
+                state_read
+                    .load_balance
+                    .as_ref()
+                    .and_then(|config| wanted_route(&state_read, config, ipv6)),
+                state_read
+                    .multipath
+                    .iter()
+                    .find(|route| route.is_ipv6() == ipv6)
+                    .cloned(),
+            )
+        };
+        if wanted == installed {
+            continue;
+        }
+
+        let family = if ipv6 { "-6 " } else { "" };
+        // Replacing only hits a route with the same metric, so after a
+        // metric change the old route has to go explicitly
+        let stale = installed.filter(|old| wanted.as_ref().is_none_or(|new| new.metric != old.metric));
+
+        // A stale route that cannot be removed must not keep the wanted
+        // one from being installed
+        if let Some(old) = &stale {
+            let change = format!("{}route del default metric {}", family, old.metric);
+            if dry_run {
+                info!("Dry run: would run `ip {}`", change);
+                changes.push(change);
+            } else if let Err(e) = remove_multipath(handle, ipv6, old.metric).await {
+                warn!("Failed to remove multipath default route: {:#}", e);
+            } else {
+                changes.push(change);
+            }
+        }
+
+        if let Some(new) = &wanted {
+            let change = format!("{}route replace {}", family, new.describe());
+            if dry_run {
+                info!("Dry run: would run `ip {}`", change);
+            } else if let Err(e) = replace_multipath(handle, new).await {
+                warn!("Failed to install multipath default route: {:#}", e);
+                continue;
+            } else {
+                info!("Installed multipath default route: {}", new.describe());
+            }
+            changes.push(change);
+        }
+
+        if !dry_run {
+            let mut state_write = state.write().await;
+            state_write.multipath.retain(|route| route.is_ipv6() != ipv6);
+            state_write.multipath.extend(wanted);
+        }
+    }
+
+    changes
+}
+
+/// Remove the multipath default routes netevd installed
+///
+/// Used on shutdown with `routing.on_shutdown: flush`.
+pub async fn flush(handle: &Handle, state: &Arc<RwLock<NetworkState>>, dry_run: bool) -> Result<Vec<String>> {
+    let _guard = UPDATE.lock().await;
+    let installed = state.read().await.multipath.clone();
+
+    let mut changes = Vec::new();
+    for route in installed {
+        let change = format!(
+            "{}route del default metric {}",
+            if route.is_ipv6() { "-6 " } else { "" },
+            route.metric
+        );
+        if dry_run {
+            info!("Dry run: would run `ip {}`", change);
+        } else {
+            remove_multipath(handle, route.is_ipv6(), route.metric).await?;
+            state.write().await.multipath.retain(|tracked| *tracked != route);
+        }
+        changes.push(change);
+    }
+
+    Ok(changes)
+}
+
+async fn replace_multipath(handle: &Handle, route: &MultipathRoute) -> Result<()> {
+    let mut nexthops = Vec::new();
+    for nexthop in &route.nexthops {
+        let builder = if nexthop.gateway.is_ipv6() {
+            RouteNextHopBuilder::new_ipv6()
+        } else {
+            RouteNextHopBuilder::new_ipv4()
+        };
+        nexthops.push(
+            builder
+                .interface(nexthop.ifindex)
+                .via(nexthop.gateway)
+                .with_context(|| format!("Invalid gateway {}", nexthop.gateway))?
+                // The kernel counts weights from 0
+                .weight((nexthop.weight - 1) as u8)
+                .build(),
+        );
+    }
+
+    handle
+        .route()
+        .add(multipath_message(route.is_ipv6(), route.metric, nexthops))
+        .replace()
+        .execute()
+        .await
+        .with_context(|| format!("Failed to replace route {}", route.describe()))
+}
+
+/// Delete the multipath default route with `metric`; a route that is
+/// already gone counts as removed
+async fn remove_multipath(handle: &Handle, ipv6: bool, metric: u32) -> Result<()> {
+    // Without nexthops the kernel deletes the whole route
+    match handle.route().del(multipath_message(ipv6, metric, Vec::new())).execute().await {
+        Err(e) if is_not_found(&e) => Ok(()),
+        result => result.with_context(|| format!("Failed to remove default route with metric {}", metric)),
+    }
+}
+
+fn multipath_message(ipv6: bool, metric: u32, nexthops: Vec<RouteNextHop>) -> RouteMessage {
+    let table = RouteHeader::RT_TABLE_MAIN.into();
+    let mut message = if ipv6 {
+        RouteMessageBuilder::<Ipv6Addr>::new()
+            .destination_prefix(Ipv6Addr::UNSPECIFIED, 0)
+            .priority(metric)
+            .table_id(table)
+            .build()
+    } else {
+        RouteMessageBuilder::<Ipv4Addr>::new()
+            .destination_prefix(Ipv4Addr::UNSPECIFIED, 0)
+            .priority(metric)
+            .table_id(table)
+            .build()
+    };
+    if !nexthops.is_empty() {
+        message.attributes.push(RouteAttribute::MultiPath(nexthops));
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn config(members: &[(&str, u16)]) -> LoadBalanceConfig {
+        LoadBalanceConfig {
+            members: members
+                .iter()
+                .map(|(name, weight)| (name.to_string(), *weight))
+                .collect::<BTreeMap<_, _>>(),
+            metric: DEFAULT_LOAD_BALANCE_METRIC,
+        }
+    }
+
+    fn uplink(state: &mut NetworkState, name: &str, ifindex: u32, gateway: &str) {
+        state.add_link(name.to_string(), ifindex);
+        let table = state.tables.allocate(name).unwrap();
+        state.add_route(ifindex, table, Some(gateway.parse().unwrap()));
+    }
+
+    #[test]
+    fn test_wanted_route() {
+        let mut state = NetworkState::new();
+        uplink(&mut state, "eth1", 3, "192.168.1.1");
+        uplink(&mut state, "eth2", 4, "10.0.0.1");
+        uplink(&mut state, "eth2", 4, "fe80::1");
+        let config = config(&[("eth1", 3), ("eth2", 1), ("eth3", 1)]);
+
+        let route = wanted_route(&state, &config, false).unwrap();
+        assert!(!route.is_ipv6());
+        assert_eq!(
+            route.describe(),
+            "default metric 10 nexthop via 192.168.1.1 dev eth1 weight 3 nexthop via 10.0.0.1 dev eth2 weight 1"
+        );
+
+        // Only eth2 has an IPv6 gateway
+        let route = wanted_route(&state, &config, true).unwrap();
+        assert!(route.is_ipv6());
+        assert_eq!(route.nexthops.len(), 1);
+
+        // A link without carrier drops out; without members there is no route
+        state.set_link_up(4, false);
+        assert_eq!(wanted_route(&state, &config, false).unwrap().nexthops.len(), 1);
+        assert_eq!(wanted_route(&state, &config, true), None);
+
+        // So does a member whose gateway fails its health check
+        state.set_link_up(4, true);
+        state.failed_gateways.insert("eth1".to_string());
+        let route = wanted_route(&state, &config, false).unwrap();
+        assert_eq!(route.nexthops.len(), 1);
+        assert_eq!(route.nexthops[0].interface, "eth2");
+    }
+
+    #[test]
+    fn test_multipath_message() {
+        let nexthop = RouteNextHopBuilder::new_ipv4()
+            .interface(3)
+            .via("192.168.1.1".parse().unwrap())
+            .unwrap()
+            .build();
+        let message = multipath_message(false, 10, vec![nexthop]);
+        assert_eq!(message.header.table, RouteHeader::RT_TABLE_MAIN);
+        assert_eq!(message.header.destination_prefix_length, 0);
+        assert!(message.attributes.contains(&RouteAttribute::Priority(10)));
+        assert!(message
+            .attributes
+            .iter()
+            .any(|attr| matches!(attr, RouteAttribute::MultiPath(nexthops) if nexthops.len() == 1)));
+
+        let removal = multipath_message(true, 10, Vec::new());
+        assert!(!removal
+            .attributes
+            .iter()
+            .any(|attr| matches!(attr, RouteAttribute::MultiPath(_))));
+    }
+}
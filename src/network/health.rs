@@ -0,0 +1,950 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+
+//! Gateway health checks
+//!
+//! An interface with an address and a gateway is not necessarily usable:
+//! the upstream behind the gateway may be dead. For each interface under
+//! `routing.health_checks`, netevd probes a set of targets through that
+//! interface, with ICMP echo requests or TCP connects from a socket bound
+//! to it (`SO_BINDTODEVICE`) and to one of its addresses, so the policy
+//! rules send the probes out through the interface's own table.
+//!
+//! After `fall` failed rounds in a row the gateway counts as down: the
+//! default routes in the interface's own table are withdrawn, so traffic
+//! from its addresses falls through to the main table, the interface
+//! leaves the `load_balance` multipath route and a `gateway_down` event
+//! runs the `gateway_down.d` scripts, subject to the event filters. After
+//! `rise` successful rounds it counts as up again: the routes are put
+//! back, with a `gateway_up` event.
+//!
+//! Over the last `window` rounds, the round-trip time, loss and jitter of
+//! the probes are exported as `netevd_uplink_*` metrics. A measurement
+//! crossing one of the configured thresholds raises an `uplink_degraded`
+//! event, and `uplink_recovered` once it is back within the limit; filters
+//! can match on the values, e.g. `condition: "loss > 5"`.
+
+use anyhow::{Context, Result};
+use rtnetlink::Handle;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr, SocketAddrV6};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpSocket, UdpSocket};
+use tokio::sync::{watch, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+use super::route::{remove_default_route, replace_route};
+use super::{address::get_policy_addresses, balance, ip_family, mark, NetworkState};
+use crate::config::{Config, HealthCheckConfig, ProbeMethod, QualityThresholds};
+use crate::events::EventHistory;
+use crate::filters::{EventFilter, NetworkEvent, PathQuality};
+use crate::metrics::MetricsHandle;
+use crate::system::execute::{self, ScriptResult};
+use crate::system::paths::get_script_dir;
+
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+
+/// Up/down state of a gateway with hysteresis
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hysteresis {
+    fall: u32,
+    rise: u32,
+    healthy: bool,
+    /// Rounds in a row that disagree with `healthy`
+    streak: u32,
+}
+
+impl Hysteresis {
+    /// A gateway starts out healthy
+    pub fn new(fall: u32, rise: u32) -> Self {
+        Self {
+            fall,
+            rise,
+            healthy: true,
+            streak: 0,
+        }
+    }
+
+    pub fn healthy(&self) -> bool {
+        self.healthy
+    }
+
+    /// Count a probe round; returns the new state if it flipped
+    pub fn record(&mut self, success: bool) -> Option<bool> {
+        if success == self.healthy {
+            self.streak = 0;
+            return None;
+        }
+
+        self.streak += 1;
+        let needed = if self.healthy { self.fall } else { self.rise };
+        if self.streak < needed {
+            return None;
+        }
+
+        self.healthy = success;
+        self.streak = 0;
+        Some(success)
+    }
+}
+
+/// What became of the probe of one target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeOutcome {
+    /// Answered after the round-trip time
+    Answered(Duration),
+    /// Sent, but not answered within the timeout
+    Lost,
+    /// Not sent because of a local problem, such as a socket that could
+    /// not be opened or bound, or no source address yet. This says nothing
+    /// about the gateway.
+    NotSent,
+}
+
+impl ProbeOutcome {
+    fn rtt(&self) -> Option<Duration> {
+        match self {
+            ProbeOutcome::Answered(rtt) => Some(*rtt),
+            _ => None,
+        }
+    }
+}
+
+/// Probe results of the last rounds, one entry per target in each round
+#[derive(Debug, Clone)]
+pub struct ProbeWindow {
+    size: usize,
+    rounds: VecDeque<Vec<ProbeOutcome>>,
+}
+
+impl ProbeWindow {
+    pub fn new(size: u32) -> Self {
+        Self {
+            size: size as usize,
+            rounds: VecDeque::with_capacity(size as usize),
+        }
+    }
+
+    /// Add a round's outcomes, in target order
+    pub fn push(&mut self, round: Vec<ProbeOutcome>) {
+        if self.rounds.len() == self.size {
+            self.rounds.pop_front();
+        }
+        self.rounds.push_back(round);
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.rounds.len() == self.size
+    }
+
+    /// RTT, loss and jitter over the window
+    ///
+    /// Jitter is the mean difference between consecutive answers of the
+    /// same target, so targets at different distances do not inflate it.
+    /// Probes that were not sent count neither as answered nor as lost.
+    pub fn quality(&self) -> PathQuality {
+        let samples: Vec<Option<Duration>> = self
+            .rounds
+            .iter()
+            .flatten()
+            .filter(|outcome| **outcome != ProbeOutcome::NotSent)
+            .map(ProbeOutcome::rtt)
+            .collect();
+        let answered: Vec<f64> = samples.iter().flatten().map(as_millis).collect();
+
+        let targets = self.rounds.iter().map(Vec::len).max().unwrap_or(0);
+        let mut deltas = Vec::new();
+        for target in 0..targets {
+            let rtts: Vec<f64> = self
+                .rounds
+                .iter()
+                .filter_map(|round| round.get(target).and_then(ProbeOutcome::rtt))
+                .map(|rtt| as_millis(&rtt))
+                .collect();
+            deltas.extend(rtts.windows(2).map(|pair| (pair[1] - pair[0]).abs()));
+        }
+
+        PathQuality {
+            rtt_ms: mean(&answered),
+            loss_percent: if samples.is_empty() {
+                0.0
+            } else {
+                (samples.len() - answered.len()) as f64 * 100.0 / samples.len() as f64
+            },
+            jitter_ms: mean(&deltas),
+        }
+    }
+}
+
+fn as_millis(duration: &Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// Metrics whose threshold the measurement exceeds: `rtt`, `loss`, `jitter`
+pub fn exceeded_thresholds(quality: &PathQuality, thresholds: &QualityThresholds) -> BTreeSet<&'static str> {
+    let over = |value: Option<f64>, limit: Option<u32>| match (value, limit) {
+        (Some(value), Some(limit)) => value > f64::from(limit),
+        _ => false,
+    };
+
+    let mut exceeded = BTreeSet::new();
+    if over(quality.rtt_ms, thresholds.rtt_ms) {
+        exceeded.insert("rtt");
+    }
+    if over(Some(quality.loss_percent), thresholds.loss_percent) {
+        exceeded.insert("loss");
+    }
+    if over(quality.jitter_ms, thresholds.jitter_ms) {
+        exceeded.insert("jitter");
+    }
+    exceeded
+}
+
+/// Run the configured health checks, following configuration reloads
+///
+/// A check whose entry changed or went away on reload is stopped and its
+/// interface counts as healthy again; changed entries start over.
+pub async fn watch_gateways(
+    handle: Handle,
+    state: Arc<RwLock<NetworkState>>,
+    config: Arc<RwLock<Config>>,
+    mut reloads: watch::Receiver<u64>,
+    events: Arc<EventHistory>,
+    metrics: Option<MetricsHandle>,
+    dry_run: bool,
+) -> Result<()> {
+    info!("Starting gateway health checks");
+
+    let mut checks: HashMap<String, (HealthCheckConfig, JoinHandle<()>)> = HashMap::new();
+    loop {
+        let wanted = config.read().await.routing.health_checks.clone();
+
+        let stale: Vec<String> = checks
+            .iter()
+            .filter(|(name, (check, _))| wanted.get(*name) != Some(check))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in stale {
+            if let Some((_, task)) = checks.remove(&name) {
+                task.abort();
+            }
+            info!("Stopped health check of interface {}", name);
+            if let Some(metrics) = &metrics {
+                forget_metrics(metrics, &name);
+            }
+            if state.write().await.set_gateway_health(&name, true) {
+                update_table_routes(&handle, &state, &name, true, dry_run).await;
+                balance::update(&handle, &state, dry_run).await;
+                mark::update(&handle, &state, dry_run).await;
+            }
+        }
+
+        for (name, check) in wanted {
+            if checks.contains_key(&name) {
+                continue;
+            }
+            info!(
+                "Probing gateway of interface {} via {} every {}s",
+                name,
+                check.method.as_str(),
+                check.interval_seconds
+            );
+            let task = tokio::spawn(run_check(
+                handle.clone(),
+                state.clone(),
+                config.clone(),
+                events.clone(),
+                metrics.clone(),
+                name.clone(),
+                check.clone(),
+                dry_run,
+            ));
+            checks.insert(name, (check, task));
+        }
+
+        if reloads.changed().await.is_err() {
+            // No more reloads; the checks keep running on their own
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// Probe one interface's targets every interval, acting on state changes
+#[allow(clippy::too_many_arguments)]
+async fn run_check(
+    handle: Handle,
+    state: Arc<RwLock<NetworkState>>,
+    config: Arc<RwLock<Config>>,
+    events: Arc<EventHistory>,
+    metrics: Option<MetricsHandle>,
+    name: String,
+    check: HealthCheckConfig,
+    dry_run: bool,
+) {
+    let mut ticker = tokio::time::interval(check.interval());
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut health = Hysteresis::new(check.fall, check.rise);
+    let mut window = ProbeWindow::new(check.window);
+    let mut exceeded = BTreeSet::new();
+    let mut sequence: u16 = 0;
+    // Local problems are logged once until a round goes out cleanly
+    let mut warned = false;
+
+    loop {
+        ticker.tick().await;
+        sequence = sequence.wrapping_add(1);
+
+        let (round, problems) = probe_round(&handle, &state, &name, &check, sequence).await;
+        if problems.is_empty() {
+            warned = false;
+        } else if !warned {
+            let problems: Vec<String> = problems.into_iter().collect();
+            warn!(
+                "Health check of {}: probes not sent, which does not count against the gateway: {}",
+                name,
+                problems.join("; ")
+            );
+            warned = true;
+        }
+        // Nothing went out, so the round tells nothing about the gateway
+        if round.iter().all(|outcome| *outcome == ProbeOutcome::NotSent) {
+            continue;
+        }
+
+        let success = round.iter().any(|outcome| matches!(outcome, ProbeOutcome::Answered(_)));
+        window.push(round);
+        let quality = window.quality();
+        let change = health.record(success);
+        if let Some(metrics) = &metrics {
+            export_metrics(metrics, &name, &quality, health.healthy());
+        }
+
+        if let Some(healthy) = change {
+            gateway_changed(&handle, &state, &config, &events, &name, &check, healthy, &quality, dry_run).await;
+        }
+
+        // A partial window would make a single lost probe look like heavy loss
+        if !window.is_full() {
+            continue;
+        }
+        let now = exceeded_thresholds(&quality, &check.thresholds);
+        for metric in now.difference(&exceeded) {
+            quality_changed(&handle, &state, &config, &events, &name, &check, metric, true, &quality, dry_run).await;
+        }
+        for metric in exceeded.difference(&now) {
+            quality_changed(&handle, &state, &config, &events, &name, &check, metric, false, &quality, dry_run).await;
+        }
+        exceeded = now;
+    }
+}
+
+fn export_metrics(metrics: &MetricsHandle, name: &str, quality: &PathQuality, healthy: bool) {
+    metrics
+        .uplink_up
+        .with_label_values(&[name])
+        .set(if healthy { 1.0 } else { 0.0 });
+    metrics
+        .uplink_loss_ratio
+        .with_label_values(&[name])
+        .set(quality.loss_percent / 100.0);
+    // Without a single answer in the window there is nothing to report
+    if let Some(rtt) = quality.rtt_ms {
+        metrics.uplink_rtt_seconds.with_label_values(&[name]).set(rtt / 1000.0);
+    }
+    if let Some(jitter) = quality.jitter_ms {
+        metrics.uplink_jitter_seconds.with_label_values(&[name]).set(jitter / 1000.0);
+    }
+}
+
+fn forget_metrics(metrics: &MetricsHandle, name: &str) {
+    for gauge in [
+        &metrics.uplink_up,
+        &metrics.uplink_rtt_seconds,
+        &metrics.uplink_loss_ratio,
+        &metrics.uplink_jitter_seconds,
+    ] {
+        let _ = gauge.remove_label_values(&[name]);
+    }
+}
+
+/// Probe every target once
+///
+/// Returns the outcome per target, and the local problems that kept
+/// probes from being sent.
+async fn probe_round(
+    handle: &Handle,
+    state: &Arc<RwLock<NetworkState>>,
+    name: &str,
+    check: &HealthCheckConfig,
+    sequence: u16,
+) -> (Vec<ProbeOutcome>, BTreeSet<String>) {
+    let not_sent = vec![ProbeOutcome::NotSent; check.targets.len()];
+    let Some(ifindex) = state.read().await.get_link_index(name) else {
+        return (not_sent, BTreeSet::from(["interface not present".to_string()]));
+    };
+    let sources = match get_policy_addresses(handle, ifindex).await {
+        Ok(sources) => sources,
+        Err(e) => return (not_sent, BTreeSet::from([format!("failed to get its addresses: {}", e)])),
+    };
+
+    let probes = check.targets.iter().map(|target| {
+        let source = sources.iter().copied().find(|source| source.is_ipv6() == target.is_ipv6());
+        async move {
+            let Some(source) = source else {
+                let family = if target.is_ipv6() { "IPv6" } else { "IPv4" };
+                return Err(format!("no {} address to probe {} from", family, target));
+            };
+            match probe(check, name, ifindex, source, *target, sequence).await {
+                Ok(Ok(rtt)) => {
+                    debug!("Health check of {}: {} answered in {:?}", name, target, rtt);
+                    Ok(ProbeOutcome::Answered(rtt))
+                }
+                Ok(Err(e)) => {
+                    debug!("Health check of {}: {} did not answer: {:#}", name, target, e);
+                    Ok(ProbeOutcome::Lost)
+                }
+                Err(e) => Err(format!("{:#}", e)),
+            }
+        }
+    });
+
+    let mut problems = BTreeSet::new();
+    let outcomes = futures::future::join_all(probes)
+        .await
+        .into_iter()
+        .map(|outcome| {
+            outcome.unwrap_or_else(|problem| {
+                problems.insert(problem);
+                ProbeOutcome::NotSent
+            })
+        })
+        .collect();
+    (outcomes, problems)
+}
+
+/// Probe a target through an interface
+///
+/// The outer error means the probe could not be sent: the socket could
+/// not be opened or bound. Otherwise the round-trip time is returned, or
+/// why no answer came. `sequence` tells the answers of consecutive ICMP
+/// probes apart.
+pub async fn probe(
+    check: &HealthCheckConfig,
+    device: &str,
+    ifindex: u32,
+    source: IpAddr,
+    target: IpAddr,
+    sequence: u16,
+) -> Result<Result<Duration>> {
+    let source = socket_addr(source, 0, ifindex);
+    let started;
+    let answer = match check.method {
+        ProbeMethod::Icmp => {
+            let socket = icmp_socket(device, source, target.is_ipv6())?;
+            started = Instant::now();
+            tokio::time::timeout(
+                check.timeout(),
+                icmp_echo(&socket, socket_addr(target, 0, ifindex), sequence),
+            )
+            .await
+        }
+        ProbeMethod::Tcp => {
+            let socket = tcp_socket(device, source, target.is_ipv6())?;
+            started = Instant::now();
+            tokio::time::timeout(
+                check.timeout(),
+                tcp_connect(socket, socket_addr(target, check.port.unwrap_or_default(), ifindex)),
+            )
+            .await
+        }
+    };
+
+    Ok(match answer {
+        Ok(Ok(())) => Ok(started.elapsed()),
+        Ok(Err(e)) => Err(e),
+        Err(_) => Err(anyhow::anyhow!("No answer within the timeout")),
+    })
+}
+
+/// Link-local IPv6 addresses are only meaningful with the interface as scope
+fn socket_addr(address: IpAddr, port: u16, ifindex: u32) -> SocketAddr {
+    match address {
+        IpAddr::V4(_) => SocketAddr::new(address, port),
+        IpAddr::V6(v6) => SocketAddr::V6(SocketAddrV6::new(v6, port, 0, ifindex)),
+    }
+}
+
+/// Open an ICMP datagram socket bound to the interface and source address
+///
+/// Unprivileged ICMP sockets need the daemon's group in
+/// `net.ipv4.ping_group_range`; the kernel fills in the identifier and
+/// checksum and only hands back replies to this socket.
+fn icmp_socket(device: &str, source: SocketAddr, ipv6: bool) -> Result<UdpSocket> {
+    let (domain, protocol) = if ipv6 {
+        (Domain::IPV6, Protocol::ICMPV6)
+    } else {
+        (Domain::IPV4, Protocol::ICMPV4)
+    };
+
+    let socket = Socket::new(domain, Type::DGRAM, Some(protocol))
+        .context("Failed to open ICMP socket, is net.ipv4.ping_group_range set?")?;
+    socket.set_nonblocking(true)?;
+    socket
+        .bind_device(Some(device.as_bytes()))
+        .with_context(|| format!("Failed to bind to device {}", device))?;
+    socket
+        .bind(&source.into())
+        .with_context(|| format!("Failed to bind to {}", source.ip()))?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+/// Send an echo request and wait for the matching reply
+async fn icmp_echo(socket: &UdpSocket, target: SocketAddr, sequence: u16) -> Result<()> {
+    let ipv6 = target.is_ipv6();
+    socket
+        .send_to(&echo_request(ipv6, sequence), target)
+        .await
+        .with_context(|| format!("Failed to send echo request to {}", target.ip()))?;
+
+    let mut buffer = [0u8; 1500];
+    loop {
+        let (length, from) = socket.recv_from(&mut buffer).await?;
+        if from.ip() == target.ip() && is_echo_reply(ipv6, &buffer[..length], sequence) {
+            return Ok(());
+        }
+    }
+}
+
+/// Open a TCP socket bound to the interface and source address
+fn tcp_socket(device: &str, source: SocketAddr, ipv6: bool) -> Result<TcpSocket> {
+    let socket = if ipv6 {
+        TcpSocket::new_v6()?
+    } else {
+        TcpSocket::new_v4()?
+    };
+    socket
+        .bind_device(Some(device.as_bytes()))
+        .with_context(|| format!("Failed to bind to device {}", device))?;
+    socket
+        .bind(source)
+        .with_context(|| format!("Failed to bind to {}", source.ip()))?;
+    Ok(socket)
+}
+
+/// Open a TCP connection; a refusal proves the target is reachable, too
+async fn tcp_connect(socket: TcpSocket, target: SocketAddr) -> Result<()> {
+    match socket.connect(target).await {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to connect to {}", target)),
+    }
+}
+
+/// ICMP echo request with zero identifier and checksum, both set by the kernel
+fn echo_request(ipv6: bool, sequence: u16) -> Vec<u8> {
+    let mut packet = vec![if ipv6 { ICMPV6_ECHO_REQUEST } else { ICMP_ECHO_REQUEST }, 0, 0, 0, 0, 0];
+    packet.extend_from_slice(&sequence.to_be_bytes());
+    packet.extend_from_slice(b"netevd\0\0");
+    packet
+}
+
+fn is_echo_reply(ipv6: bool, packet: &[u8], sequence: u16) -> bool {
+    let reply = if ipv6 { ICMPV6_ECHO_REPLY } else { ICMP_ECHO_REPLY };
+    packet.len() >= 8 && packet[0] == reply && packet[6..8] == sequence.to_be_bytes()
+}
+
+/// Act on a gateway going down or coming back
+#[allow(clippy::too_many_arguments)]
+async fn gateway_changed(
+    handle: &Handle,
+    state: &Arc<RwLock<NetworkState>>,
+    config: &Arc<RwLock<Config>>,
+    events: &EventHistory,
+    name: &str,
+    check: &HealthCheckConfig,
+    healthy: bool,
+    quality: &PathQuality,
+    dry_run: bool,
+) {
+    let event_type = if healthy { "gateway_up" } else { "gateway_down" };
+    if healthy {
+        info!("Gateway of interface {} is up again", name);
+    } else {
+        warn!(
+            "Gateway of interface {} is down: {} probe rounds failed",
+            name, check.fall
+        );
+    }
+
+    state.write().await.set_gateway_health(name, healthy);
+    let mut changes = update_table_routes(handle, state, name, healthy, dry_run).await;
+    changes.extend(balance::update(handle, state, dry_run).await);
+    changes.extend(mark::update(handle, state, dry_run).await);
+
+    let event = uplink_event(handle, state, name, event_type, healthy, quality).await;
+    let (scripts, filtered) = run_scripts(config, &event, check, Vec::new(), dry_run).await;
+
+    events.record_event(
+        event_type,
+        name,
+        serde_json::json!({
+            "source": "probe",
+            "method": check.method.as_str(),
+            "targets": check.targets,
+            "quality": quality_details(quality),
+            "filtered": filtered,
+            "scripts": scripts,
+            "changes": changes,
+            "dry_run": dry_run,
+        }),
+    );
+}
+
+/// Withdraw the default routes from an interface's table while its gateway
+/// is down, and put them back once it is up
+///
+/// The gateways stay tracked either way, so the routes come back via the
+/// gateway the interface has by then.
+async fn update_table_routes(
+    handle: &Handle,
+    state: &Arc<RwLock<NetworkState>>,
+    name: &str,
+    healthy: bool,
+    dry_run: bool,
+) -> Vec<String> {
+    let (ifindex, table, gateways) = {
+        let state_read = state.read().await;
+        let Some(ifindex) = state_read.get_link_index(name) else {
+            return Vec::new();
+        };
+        let Some(table) = state_read.link_table(ifindex) else {
+            return Vec::new();
+        };
+        let Some(route) = state_read.routes.get(&(ifindex, table)) else {
+            return Vec::new();
+        };
+        let gateways: Vec<IpAddr> = [false, true]
+            .into_iter()
+            .filter_map(|ipv6| route.default_gateway(ipv6))
+            .collect();
+        (ifindex, table, gateways)
+    };
+
+    let mut changes = Vec::new();
+    for gateway in gateways {
+        let action = if healthy { "replace" } else { "del" };
+        let change = format!("{}route {} default via {} table {}", ip_family(&gateway), action, gateway, table);
+        if dry_run {
+            info!("Dry run: would run `ip {}` for interface {}", change, name);
+            changes.push(change);
+            continue;
+        }
+
+        let result = if healthy {
+            replace_route(handle, ifindex, gateway, table).await
+        } else {
+            remove_default_route(handle, ifindex, gateway, table).await
+        };
+        match result {
+            Ok(()) => changes.push(change),
+            Err(e) => warn!("{:#}", e),
+        }
+    }
+    changes
+}
+
+/// Act on a path quality measurement crossing its threshold
+#[allow(clippy::too_many_arguments)]
+async fn quality_changed(
+    handle: &Handle,
+    state: &Arc<RwLock<NetworkState>>,
+    config: &Arc<RwLock<Config>>,
+    events: &EventHistory,
+    name: &str,
+    check: &HealthCheckConfig,
+    metric: &str,
+    degraded: bool,
+    quality: &PathQuality,
+    dry_run: bool,
+) {
+    let event_type = if degraded { "uplink_degraded" } else { "uplink_recovered" };
+    let threshold = match metric {
+        "rtt" => check.thresholds.rtt_ms,
+        "loss" => check.thresholds.loss_percent,
+        _ => check.thresholds.jitter_ms,
+    };
+    info!(
+        "Uplink {}: {} {} its threshold of {}",
+        name,
+        metric,
+        if degraded { "exceeds" } else { "is back within" },
+        threshold.unwrap_or_default()
+    );
+
+    let healthy = !state.read().await.failed_gateways.contains(name);
+    let event = uplink_event(handle, state, name, event_type, healthy, quality).await;
+    let env = vec![("METRIC", metric.to_string())];
+    let (scripts, filtered) = run_scripts(config, &event, check, env, dry_run).await;
+
+    events.record_event(
+        event_type,
+        name,
+        serde_json::json!({
+            "source": "probe",
+            "metric": metric,
+            "threshold": threshold,
+            "quality": quality_details(quality),
+            "filtered": filtered,
+            "scripts": scripts,
+            "dry_run": dry_run,
+        }),
+    );
+}
+
+/// The event as the filters see it
+async fn uplink_event(
+    handle: &Handle,
+    state: &Arc<RwLock<NetworkState>>,
+    name: &str,
+    event_type: &str,
+    healthy: bool,
+    quality: &PathQuality,
+) -> NetworkEvent {
+    let ifindex = state.read().await.get_link_index(name);
+    let addresses = match ifindex {
+        Some(ifindex) => get_policy_addresses(handle, ifindex).await.unwrap_or_default(),
+        None => Vec::new(),
+    };
+
+    NetworkEvent {
+        interface: name.to_string(),
+        event_type: event_type.to_string(),
+        backend: "netevd".to_string(),
+        addresses,
+        has_gateway: healthy,
+        dns_servers: Vec::new(),
+        path: Some(*quality),
+    }
+}
+
+fn quality_details(quality: &PathQuality) -> serde_json::Value {
+    serde_json::json!({
+        "rtt_ms": quality.rtt_ms,
+        "loss_percent": quality.loss_percent,
+        "jitter_ms": quality.jitter_ms,
+    })
+}
+
+/// Run the event's scripts unless the event filters suppress them
+///
+/// Returns the script results and whether the filters suppressed them.
+async fn run_scripts(
+    config: &Arc<RwLock<Config>>,
+    event: &NetworkEvent,
+    check: &HealthCheckConfig,
+    extra_env: Vec<(&str, String)>,
+    dry_run: bool,
+) -> (Vec<ScriptResult>, bool) {
+    let event_filter = EventFilter {
+        filters: config.read().await.filters.clone(),
+    };
+    if !event_filter.should_execute(event) {
+        debug!("Event filtered out, skipping script execution for {}", event.interface);
+        return (Vec::new(), true);
+    }
+
+    let script_dir = get_script_dir(&event.event_type);
+    let targets: Vec<String> = check.targets.iter().map(|target| target.to_string()).collect();
+    let addresses: Vec<String> = event.addresses.iter().map(|address| address.to_string()).collect();
+    let mut env_vars = HashMap::new();
+    env_vars.insert("LINK".to_string(), event.interface.clone());
+    env_vars.insert("STATE".to_string(), event.event_type.clone());
+    env_vars.insert("BACKEND".to_string(), event.backend.clone());
+    env_vars.insert("ADDRESSES".to_string(), addresses.join(" "));
+    env_vars.insert("TARGETS".to_string(), targets.join(" "));
+    if let Some(path) = &event.path {
+        env_vars.insert("LOSS_PERCENT".to_string(), format!("{:.1}", path.loss_percent));
+        if let Some(rtt) = path.rtt_ms {
+            env_vars.insert("RTT_MS".to_string(), format!("{:.3}", rtt));
+        }
+        if let Some(jitter) = path.jitter_ms {
+            env_vars.insert("JITTER_MS".to_string(), format!("{:.3}", jitter));
+        }
+    }
+    for (key, value) in extra_env {
+        env_vars.insert(key.to_string(), value);
+    }
+
+    let results = execute::run_scripts(&script_dir, env_vars, dry_run).await;
+    match results {
+        Ok(results) => (results, false),
+        Err(e) => {
+            warn!("Failed to execute scripts in {}: {}", script_dir, e);
+            (Vec::new(), false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hysteresis() {
+        let mut health = Hysteresis::new(3, 2);
+        assert!(health.healthy());
+
+        // Two failures are not enough, and a success resets the count
+        assert_eq!(health.record(false), None);
+        assert_eq!(health.record(false), None);
+        assert_eq!(health.record(true), None);
+        assert_eq!(health.record(false), None);
+        assert_eq!(health.record(false), None);
+        assert_eq!(health.record(false), Some(false));
+        assert!(!health.healthy());
+        assert_eq!(health.record(false), None);
+
+        assert_eq!(health.record(true), None);
+        assert_eq!(health.record(true), Some(true));
+        assert!(health.healthy());
+    }
+
+    #[test]
+    fn test_failed_gateway_route_withdrawn() {
+        let gateway: IpAddr = "192.168.1.1".parse().unwrap();
+        let mut state = NetworkState::new();
+        state.add_link("eth1".to_string(), 2);
+        state.add_route(2, 1002, Some(gateway));
+        let mut health = Hysteresis::new(2, 2);
+
+        // Round outcome, and the default route expected in table 1002 after it
+        let rounds = [
+            (false, Some(gateway)),
+            (false, None),
+            (false, None),
+            (true, None),
+            (true, Some(gateway)),
+            (true, Some(gateway)),
+        ];
+        for (success, expected) in rounds {
+            if let Some(healthy) = health.record(success) {
+                assert!(state.set_gateway_health("eth1", healthy));
+            }
+            assert_eq!(state.table_gateway(2, 1002, false), expected);
+        }
+
+        // The gateway stays tracked while its route is withdrawn
+        state.set_gateway_health("eth1", false);
+        assert_eq!(state.table_gateway(2, 1002, false), None);
+        assert_eq!(state.routes[&(2, 1002)].default_gateway(false), Some(gateway));
+    }
+
+    #[test]
+    fn test_probe_window_quality() {
+        let ms = |millis: u64| ProbeOutcome::Answered(Duration::from_millis(millis));
+        let lost = ProbeOutcome::Lost;
+        let mut window = ProbeWindow::new(3);
+        assert_eq!(window.quality(), PathQuality::default());
+
+        // Two targets, 10ms and 50ms away; the second misses one probe
+        window.push(vec![ms(10), ms(50)]);
+        window.push(vec![ms(14), lost]);
+        assert!(!window.is_full());
+        window.push(vec![ms(12), ms(54)]);
+        assert!(window.is_full());
+
+        let quality = window.quality();
+        assert_eq!(quality.rtt_ms, Some(140.0 / 5.0));
+        assert!((quality.loss_percent - 100.0 / 6.0).abs() < 1e-9);
+        // |14-10|, |12-14| and |54-50|, not the distance between targets
+        assert_eq!(quality.jitter_ms, Some(10.0 / 3.0));
+
+        // The oldest round drops out
+        window.push(vec![lost, lost]);
+        let quality = window.quality();
+        assert_eq!(quality.loss_percent, 50.0);
+        assert_eq!(quality.rtt_ms, Some(80.0 / 3.0));
+        assert_eq!(quality.jitter_ms, Some(2.0));
+
+        window.push(vec![lost, lost]);
+        window.push(vec![lost, lost]);
+        assert_eq!(window.quality().rtt_ms, None);
+        assert_eq!(window.quality().jitter_ms, None);
+        assert_eq!(window.quality().loss_percent, 100.0);
+
+        // A probe that was never sent is not a loss
+        let mut window = ProbeWindow::new(2);
+        window.push(vec![ms(10), ProbeOutcome::NotSent]);
+        window.push(vec![lost, ProbeOutcome::NotSent]);
+        let quality = window.quality();
+        assert_eq!(quality.loss_percent, 50.0);
+        assert_eq!(quality.rtt_ms, Some(10.0));
+    }
+
+    #[test]
+    fn test_exceeded_thresholds() {
+        let quality = PathQuality {
+            rtt_ms: Some(120.0),
+            loss_percent: 10.0,
+            jitter_ms: None,
+        };
+        assert!(exceeded_thresholds(&quality, &QualityThresholds::default()).is_empty());
+
+        let thresholds = QualityThresholds {
+            rtt_ms: Some(100),
+            loss_percent: Some(10),
+            jitter_ms: Some(5),
+        };
+        // At the limit is still within it, and an unknown jitter exceeds nothing
+        assert_eq!(
+            exceeded_thresholds(&quality, &thresholds),
+            BTreeSet::from(["rtt"])
+        );
+
+        let quality = PathQuality {
+            rtt_ms: Some(20.0),
+            loss_percent: 30.0,
+            jitter_ms: Some(8.0),
+        };
+        assert_eq!(
+            exceeded_thresholds(&quality, &thresholds),
+            BTreeSet::from(["jitter", "loss"])
+        );
+    }
+
+    #[test]
+    fn test_echo_packets() {
+        let request = echo_request(false, 0x1234);
+        assert_eq!(request[0], ICMP_ECHO_REQUEST);
+        assert_eq!(request[6..8], [0x12, 0x34]);
+        assert_eq!(echo_request(true, 1)[0], ICMPV6_ECHO_REQUEST);
+
+        let mut reply = request.clone();
+        reply[0] = ICMP_ECHO_REPLY;
+        assert!(is_echo_reply(false, &reply, 0x1234));
+        assert!(!is_echo_reply(false, &reply, 0x1235));
+        assert!(!is_echo_reply(true, &reply, 0x1234));
+        assert!(!is_echo_reply(false, &reply[..4], 0x1234));
+    }
+
+    #[test]
+    fn test_socket_addr_scope() {
+        let v4 = socket_addr("192.0.2.1".parse().unwrap(), 443, 3);
+        assert_eq!(v4, "192.0.2.1:443".parse().unwrap());
+        match socket_addr("fe80::1".parse().unwrap(), 0, 3) {
+            SocketAddr::V6(v6) => assert_eq!(v6.scope_id(), 3),
+            other => panic!("unexpected {}", other),
+        }
+    }
+}
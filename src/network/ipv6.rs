@@ -1,65 +1,44 @@
-use anyhow::Result;
-use rtnetlink::Handle;
-use std::net::Ipv6Addr;
+use anyhow::{Context, Result};
+use rtnetlink::{Handle, RouteMessageBuilder};
+use std::net::{IpAddr, Ipv6Addr};
 use tracing::{debug, info};
 
+use super::{route, routing_rule};
+
 /// Add IPv6 routing policy rule for source-based routing
-/// Note: Currently a placeholder - full implementation requires rtnetlink API updates
 pub async fn add_ipv6_routing_rule(
-    _handle: &Handle,
+    handle: &Handle,
     address: &Ipv6Addr,
     table_id: u32,
 ) -> Result<()> {
-    info!(
-        "Adding IPv6 routing policy rule: from {} lookup table {}",
-        address, table_id
-    );
-
-    // TODO: Implement when rtnetlink supports IPv6 rules properly
-    // For now, this would need to be done via `ip -6 rule add` command
-    debug!("IPv6 routing policy rules (placeholder for future implementation)");
-    Ok(())
+    routing_rule::add_routing_rule_from(handle, IpAddr::V6(*address), table_id).await
 }
 
-/// Remove IPv6 routing policy rule
-/// Note: Currently a placeholder - full implementation requires rtnetlink API updates
+/// Remove the IPv6 routing policy rules for an address
 pub async fn remove_ipv6_routing_rule(
-    _handle: &Handle,
+    handle: &Handle,
     address: &Ipv6Addr,
     table_id: u32,
 ) -> Result<()> {
-    info!(
-        "Removing IPv6 routing policy rule: from {} lookup table {}",
-        address, table_id
-    );
-
-    // TODO: Implement when rtnetlink supports IPv6 rules properly
-    debug!("IPv6 routing policy rules removal (placeholder for future implementation)");
-    Ok(())
+    routing_rule::remove_routing_rules(handle, IpAddr::V6(*address), table_id).await
 }
 
 /// Add IPv6 default route in custom table
-/// Note: Currently a placeholder - full implementation requires rtnetlink API updates
+///
+/// The route always carries the output interface, so link-local gateways
+/// (the norm with router advertisements) work.
 pub async fn add_ipv6_default_route(
-    _handle: &Handle,
+    handle: &Handle,
     gateway: Ipv6Addr,
     ifindex: u32,
     table_id: u32,
 ) -> Result<()> {
-    info!(
-        "Adding IPv6 default route: via {} dev {} table {}",
-        gateway, ifindex, table_id
-    );
-
-    // TODO: Implement when rtnetlink supports IPv6 routes properly
-    debug!("IPv6 default route (placeholder for future implementation)");
-    Ok(())
+    route::add_route(handle, ifindex, IpAddr::V6(gateway), table_id).await
 }
 
 /// Remove IPv6 default route from custom table
-/// Note: Currently a placeholder - full implementation requires rtnetlink API updates
 pub async fn remove_ipv6_default_route(
-    _handle: &Handle,
+    handle: &Handle,
     gateway: Ipv6Addr,
     ifindex: u32,
     table_id: u32,
@@ -69,8 +48,23 @@ pub async fn remove_ipv6_default_route(
         gateway, ifindex, table_id
     );
 
-    // TODO: Implement when rtnetlink supports IPv6 routes properly
-    Ok(())
+    let route_msg = RouteMessageBuilder::<Ipv6Addr>::new()
+        .destination_prefix(Ipv6Addr::UNSPECIFIED, 0)
+        .gateway(gateway)
+        .output_interface(ifindex)
+        .table_id(table_id)
+        .build();
+
+    match handle.route().del(route_msg).execute().await {
+        Ok(()) => Ok(()),
+        Err(ref e) if e.to_string().contains("No such process") => {
+            debug!("IPv6 default route not present in table {}", table_id);
+            Ok(())
+        }
+        Err(e) => Err(e).with_context(|| {
+            format!("Failed to remove IPv6 default route via {} in table {}", gateway, table_id)
+        }),
+    }
 }
 
 /// Check if an IPv6 address is link-local
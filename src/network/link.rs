@@ -4,12 +4,42 @@
 
 use anyhow::{Context, Result};
 use futures::stream::TryStreamExt;
-use rtnetlink::packet_route::link::LinkAttribute;
+use rtnetlink::packet_route::link::{LinkAttribute, LinkFlags, LinkMessage, State};
 use rtnetlink::Handle;
 use tracing::{debug, info};
 
 use super::NetworkState;
 
+/// Interface flags reported to users, in `ip link` order and naming
+const LINK_FLAG_NAMES: &[(LinkFlags, &str)] = &[
+    (LinkFlags::Up, "UP"),
+    (LinkFlags::Broadcast, "BROADCAST"),
+    (LinkFlags::Loopback, "LOOPBACK"),
+    (LinkFlags::Pointopoint, "POINTOPOINT"),
+    (LinkFlags::Running, "RUNNING"),
+    (LinkFlags::Noarp, "NOARP"),
+    (LinkFlags::Promisc, "PROMISC"),
+    (LinkFlags::Allmulti, "ALLMULTI"),
+    (LinkFlags::Controller, "MASTER"),
+    (LinkFlags::Port, "SLAVE"),
+    (LinkFlags::Multicast, "MULTICAST"),
+    (LinkFlags::Dynamic, "DYNAMIC"),
+    (LinkFlags::LowerUp, "LOWER_UP"),
+    (LinkFlags::Dormant, "DORMANT"),
+];
+
+/// Kernel view of a single link
+#[derive(Debug, Clone)]
+pub struct LinkDetails {
+    pub index: u32,
+    pub name: String,
+    pub mac_address: Option<String>,
+    pub mtu: Option<u32>,
+    /// RFC 2863 operational state, lowercase (e.g. "up", "down", "dormant")
+    pub oper_state: String,
+    pub flags: Vec<String>,
+}
+
 /// Acquire all network links and populate the network state
 pub async fn acquire_links(state: &mut NetworkState, handle: &Handle) -> Result<()> {
     info!("Acquiring network links");
@@ -36,12 +66,89 @@ pub async fn acquire_links(state: &mut NetworkState, handle: &Handle) -> Result<
 
         debug!("Found link: {} (index={})", name, index);
         state.add_link(name, index);
+        state.set_link_up(
+            index,
+            link.header.flags.contains(LinkFlags::Up) && link.header.flags.contains(LinkFlags::Running),
+        );
     }
 
     info!("Acquired {} links", state.links_by_index.len());
     Ok(())
 }
 
+/// Dump details for every link known to the kernel
+pub async fn get_link_details(handle: &Handle) -> Result<Vec<LinkDetails>> {
+    let mut links = handle.link().get().execute();
+    let mut details = Vec::new();
+
+    while let Some(link) = links
+        .try_next()
+        .await
+        .context("Failed to get next link")?
+    {
+        details.push(link_details_from_message(&link));
+    }
+
+    Ok(details)
+}
+
+fn link_details_from_message(link: &LinkMessage) -> LinkDetails {
+    let index = link.header.index;
+    let mut details = LinkDetails {
+        index,
+        name: format!("link{}", index),
+        mac_address: None,
+        mtu: None,
+        oper_state: oper_state_name(&State::Unknown),
+        flags: flag_names(link.header.flags),
+    };
+
+    for attr in &link.attributes {
+        match attr {
+            LinkAttribute::IfName(name) => details.name = name.clone(),
+            LinkAttribute::Address(mac) if !mac.is_empty() => {
+                details.mac_address = Some(format_mac(mac));
+            }
+            LinkAttribute::Mtu(mtu) => details.mtu = Some(*mtu),
+            LinkAttribute::OperState(state) => details.oper_state = oper_state_name(state),
+            _ => {}
+        }
+    }
+
+    details
+}
+
+/// Format a hardware address as colon-separated hex
+fn format_mac(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn oper_state_name(state: &State) -> String {
+    match state {
+        State::Unknown => "unknown".to_string(),
+        State::NotPresent => "notpresent".to_string(),
+        State::Down => "down".to_string(),
+        State::LowerLayerDown => "lowerlayerdown".to_string(),
+        State::Testing => "testing".to_string(),
+        State::Dormant => "dormant".to_string(),
+        State::Up => "up".to_string(),
+        State::Other(code) => format!("other({})", code),
+        _ => "unknown".to_string(),
+    }
+}
+
+fn flag_names(flags: LinkFlags) -> Vec<String> {
+    LINK_FLAG_NAMES
+        .iter()
+        .filter(|(flag, _)| flags.contains(*flag))
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
 /// Get a netlink handle
 pub async fn get_netlink_handle() -> Result<Handle> {
     let (connection, handle, _) = rtnetlink::new_connection()
@@ -57,6 +164,24 @@ pub async fn get_netlink_handle() -> Result<Handle> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_format_mac() {
+        assert_eq!(format_mac(&[0x00, 0x11, 0x22, 0xaa, 0xbb, 0xcc]), "00:11:22:aa:bb:cc");
+    }
+
+    #[test]
+    fn test_flag_names() {
+        let flags = LinkFlags::Up | LinkFlags::Broadcast | LinkFlags::LowerUp;
+        assert_eq!(flag_names(flags), vec!["UP", "BROADCAST", "LOWER_UP"]);
+        assert!(flag_names(LinkFlags::empty()).is_empty());
+    }
+
+    #[test]
+    fn test_oper_state_name() {
+        assert_eq!(oper_state_name(&State::Up), "up");
+        assert_eq!(oper_state_name(&State::LowerLayerDown), "lowerlayerdown");
+    }
+
     #[tokio::test]
     async fn test_get_netlink_handle() {
         // This test requires CAP_NET_ADMIN or root
@@ -0,0 +1,199 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+
+//! Firewall mark rules
+//!
+//! Each `routing.mark_rules` entry sends packets carrying a firewall mark,
+//! set for example by nftables, through the table of a policy interface:
+//! `fwmark X/mask lookup <table>`. The rule of an address family exists
+//! only while the interface is usable for it, as for `load_balance`: the
+//! link is up, its health check (if any) passes, and netevd has set up the
+//! table for the family. Otherwise marked packets fall through to the
+//! later rules and the main table. The address, route and link watchers
+//! and the health checks call [`update`] whenever one of those changes.
+
+use anyhow::Result;
+use rtnetlink::Handle;
+use std::sync::Arc;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, warn};
+
+use super::routing_rule::{add_mark_rule, remove_mark_rule};
+use super::NetworkState;
+
+/// Updates come from several watchers; one at a time keeps the kernel and
+/// the tracked rules in step
+static UPDATE: Mutex<()> = Mutex::const_new(());
+
+/// A firewall mark rule for one address family
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MarkRule {
+    /// Interface whose table the rule points at
+    pub interface: String,
+    pub ipv6: bool,
+    pub fwmark: u32,
+    pub fwmask: Option<u32>,
+    pub priority: Option<u32>,
+    pub table: u32,
+}
+
+impl MarkRule {
+    /// The rule's selector and action, in `ip rule` terms
+    pub fn describe(&self) -> String {
+        let mut description = match self.fwmask {
+            Some(mask) => format!("fwmark {:#x}/{:#x}", self.fwmark, mask),
+            None => format!("fwmark {:#x}", self.fwmark),
+        };
+        description.push_str(&format!(" lookup {}", self.table));
+        if let Some(priority) = self.priority {
+            description.push_str(&format!(" priority {}", priority));
+        }
+        description
+    }
+
+    fn family(&self) -> &'static str {
+        if self.ipv6 {
+            "-6 "
+        } else {
+            ""
+        }
+    }
+}
+
+/// The mark rules netevd should have installed, given the configured
+/// entries and the tables they point at
+///
+/// With `usable_only`, entries whose interface cannot carry the family
+/// right now are left out.
+pub fn configured_rules(state: &NetworkState, usable_only: bool) -> Vec<MarkRule> {
+    let mut rules = Vec::new();
+    for config in &state.mark_rule_configs {
+        let Some(table) = state.tables.get(&config.interface) else {
+            continue;
+        };
+        for ipv6 in [false, true] {
+            if usable_only && state.usable_uplink(&config.interface, ipv6).is_none() {
+                continue;
+            }
+            rules.push(MarkRule {
+                interface: config.interface.clone(),
+                ipv6,
+                fwmark: config.fwmark,
+                fwmask: config.fwmask,
+                priority: config.priority,
+                table,
+            });
+        }
+    }
+    rules
+}
+
+/// Bring the mark rules in line with the interfaces' state
+///
+/// Returns the changes made, in `ip rule` terms. In dry-run mode nothing
+/// is sent to the kernel or tracked; the changes are only logged.
+pub async fn update(handle: &Handle, state: &Arc<RwLock<NetworkState>>, dry_run: bool) -> Vec<String> {
+    let _guard = UPDATE.lock().await;
+    let (wanted, installed) = {
+        let state_read = state.read().await;
+        (configured_rules(&state_read, true), state_read.mark_rules.clone())
+    };
+
+    let mut changes = Vec::new();
+    // New rules go in before stale ones go, so that marked traffic moving
+    // to another table is never left without a rule
+    for rule in wanted.iter().filter(|rule| !installed.contains(rule)) {
+        let change = format!("{}rule add {}", rule.family(), rule.describe());
+        if dry_run {
+            info!("Dry run: would run `ip {}`", change);
+        } else if let Err(e) = add_mark_rule(handle, rule).await {
+            warn!("Failed to add mark rule: {:#}", e);
+            continue;
+        } else {
+            state.write().await.mark_rules.push(rule.clone());
+        }
+        changes.push(change);
+    }
+
+    for rule in installed.iter().filter(|rule| !wanted.contains(rule)) {
+        let change = format!("{}rule del {}", rule.family(), rule.describe());
+        if dry_run {
+            info!("Dry run: would run `ip {}`", change);
+        } else if let Err(e) = remove_mark_rule(handle, rule).await {
+            warn!("Failed to remove mark rule: {:#}", e);
+            continue;
+        } else {
+            state.write().await.mark_rules.retain(|tracked| tracked != rule);
+        }
+        changes.push(change);
+    }
+
+    changes
+}
+
+/// Remove the mark rules netevd installed
+///
+/// Used on shutdown with `routing.on_shutdown: flush`.
+pub async fn flush(handle: &Handle, state: &Arc<RwLock<NetworkState>>, dry_run: bool) -> Result<Vec<String>> {
+    let _guard = UPDATE.lock().await;
+    let installed = state.read().await.mark_rules.clone();
+
+    let mut changes = Vec::new();
+    for rule in installed {
+        let change = format!("{}rule del {}", rule.family(), rule.describe());
+        if dry_run {
+            info!("Dry run: would run `ip {}`", change);
+        } else {
+            remove_mark_rule(handle, &rule).await?;
+            state.write().await.mark_rules.retain(|tracked| *tracked != rule);
+        }
+        changes.push(change);
+    }
+
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MarkRuleConfig;
+
+    fn mark_rule(fwmark: u32, interface: &str) -> MarkRuleConfig {
+        MarkRuleConfig {
+            fwmark,
+            fwmask: None,
+            interface: interface.to_string(),
+            priority: Some(500),
+        }
+    }
+
+    #[test]
+    fn test_configured_rules() {
+        let mut state = NetworkState::new();
+        state.add_link("eth1".to_string(), 3);
+        state.tables.allocate("eth1");
+        state.tables.allocate("wg0");
+        state.add_route(3, 1000, Some("192.168.1.1".parse().unwrap()));
+        state.mark_rule_configs = vec![mark_rule(0x1, "eth1"), mark_rule(0x2, "wg0")];
+
+        // wg0 is not there, and eth1 only routes IPv4
+        let wanted = configured_rules(&state, true);
+        assert_eq!(wanted.len(), 1);
+        assert!(!wanted[0].ipv6);
+        assert_eq!(wanted[0].describe(), "fwmark 0x1 lookup 1000 priority 500");
+        assert_eq!(configured_rules(&state, false).len(), 4);
+
+        state.set_link_up(3, false);
+        assert!(configured_rules(&state, true).is_empty());
+        state.set_link_up(3, true);
+        state.failed_gateways.insert("eth1".to_string());
+        assert!(configured_rules(&state, true).is_empty());
+        state.failed_gateways.clear();
+
+        state.add_route(3, 1000, Some("fe80::1".parse().unwrap()));
+        state.mark_rule_configs[0].fwmask = Some(0xff);
+        let wanted = configured_rules(&state, true);
+        assert_eq!(wanted.len(), 2);
+        assert_eq!(wanted[1].describe(), "fwmark 0x1/0xff lookup 1000 priority 500");
+        assert_eq!(wanted[1].family(), "-6 ");
+    }
+}
@@ -3,31 +3,147 @@
 //! Network state management and operations
 
 pub mod address;
+pub mod balance;
+pub mod health;
 pub mod link;
+pub mod mark;
 pub mod route;
 pub mod routing_rule;
 pub mod watcher;
 pub mod ipv6;
+pub mod reconcile;
+pub mod repair;
+pub mod table;
+pub mod vrf;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::net::IpAddr;
 
+use crate::config::{LoadBalanceConfig, MarkRuleConfig, RoutingMode, RuleConfig, RuleOptions};
+use address::{host_prefix_len, network_address};
+use balance::MultipathRoute;
+use mark::MarkRule;
+use table::TableAllocator;
+use vrf::Vrf;
+
 /// Represents a routing rule (from/to)
+/// What a tracked rule is keyed by: its selector's address and prefix
+/// length, its table and its priority, if configured
+pub type RuleKey = (IpAddr, u8, u32, Option<u32>);
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct RoutingRule {
     pub address: IpAddr,
+    /// Prefix length of the selector; below 32 (128) the rule covers the
+    /// subnet with `address` as its network address
+    pub prefix_len: u8,
     pub table: u32,
     pub is_from: bool, // true for "from", false for "to"
+    /// Priority and extra matches from `routing.rules`
+    pub options: RuleOptions,
+}
+
+impl RoutingRule {
+    /// Rules for an interface address, as configured in `routing.rules`
+    ///
+    /// With `to_subnet`, the `to` rule covers the address's whole subnet.
+    pub fn for_address(address: IpAddr, prefix_len: u8, table: u32, config: &RuleConfig) -> Vec<RoutingRule> {
+        [true, false]
+            .into_iter()
+            .filter(|is_from| config.selectors.includes(*is_from))
+            .map(|is_from| {
+                let prefix_len = if is_from || !config.to_subnet {
+                    host_prefix_len(&address)
+                } else {
+                    prefix_len
+                };
+                RoutingRule {
+                    address: network_address(address, prefix_len),
+                    prefix_len,
+                    table,
+                    is_from,
+                    options: config.options.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Key the rule is tracked under
+    ///
+    /// Interfaces with overlapping subnets produce `to` rules for the same
+    /// network, which differ only in their table.
+    pub fn key(&self) -> RuleKey {
+        (self.address, self.prefix_len, self.table, self.options.priority)
+    }
+
+    /// The rule's selectors and action, in `ip rule` terms
+    pub fn describe(&self) -> String {
+        self.describe_lookup(&self.table.to_string(), self.options.priority)
+    }
+
+    /// The `lookup main suppress_prefixlength` rule installed in front of
+    /// this one, if configured
+    pub fn describe_suppress(&self) -> Option<String> {
+        let length = self.options.suppress_prefixlength?;
+        let priority = self.options.priority.map(|priority| priority - 1);
+        Some(self.describe_lookup(&format!("main suppress_prefixlength {}", length), priority))
+    }
+
+    fn describe_lookup(&self, lookup: &str, priority: Option<u32>) -> String {
+        let mut description = format!(
+            "{} {}",
+            if self.is_from { "from" } else { "to" },
+            self.address
+        );
+        if self.prefix_len != host_prefix_len(&self.address) {
+            description.push_str(&format!("/{}", self.prefix_len));
+        }
+        if let Some(iif) = &self.options.iif {
+            description.push_str(&format!(" iif {}", iif));
+        }
+        if let Some(oif) = &self.options.oif {
+            description.push_str(&format!(" oif {}", oif));
+        }
+        match (self.options.fwmark, self.options.fwmask) {
+            (Some(mark), Some(mask)) => description.push_str(&format!(" fwmark {:#x}/{:#x}", mark, mask)),
+            (Some(mark), None) => description.push_str(&format!(" fwmark {:#x}", mark)),
+            _ => {}
+        }
+        description.push_str(&format!(" lookup {}", lookup));
+        if let Some(priority) = priority {
+            description.push_str(&format!(" priority {}", priority));
+        }
+        description
+    }
 }
 
 /// Represents a route entry
+///
+/// A per-link table holds one default route per address family, plus the
+/// connected routes of the interface's subnets.
 #[derive(Debug, Clone)]
 pub struct RouteEntry {
     pub ifindex: u32,
+    /// IPv4 gateway of the default route
     pub gateway: Option<IpAddr>,
+    /// IPv6 gateway of the default route, often link-local
+    pub gateway6: Option<IpAddr>,
+    /// Connected subnets, as network address and prefix length
+    pub subnets: BTreeSet<(IpAddr, u8)>,
     pub table: u32,
 }
 
+impl RouteEntry {
+    /// Gateway of the family's default route
+    pub fn default_gateway(&self, ipv6: bool) -> Option<IpAddr> {
+        if ipv6 {
+            self.gateway6
+        } else {
+            self.gateway
+        }
+    }
+}
+
 /// Network state tracking
 #[derive(Debug, Default)]
 pub struct NetworkState {
@@ -40,9 +156,42 @@ pub struct NetworkState {
     /// Track routes by interface index and table
     pub routes: HashMap<(u32, u32), RouteEntry>,
 
-    /// Track routing rules by address (for cleanup)
-    pub routing_rules_from: HashMap<IpAddr, RoutingRule>,
-    pub routing_rules_to: HashMap<IpAddr, RoutingRule>,
+    /// Track routing rules by selector (for cleanup)
+    pub routing_rules_from: HashMap<RuleKey, RoutingRule>,
+    pub routing_rules_to: HashMap<RuleKey, RoutingRule>,
+
+    /// Routing table of each policy interface
+    pub tables: TableAllocator,
+
+    /// `routing.rules` entries by interface name
+    pub rule_configs: HashMap<String, RuleConfig>,
+
+    /// Links that are administratively down or have no carrier
+    pub down_links: HashSet<u32>,
+
+    /// `routing.load_balance`, if configured
+    pub load_balance: Option<LoadBalanceConfig>,
+
+    /// Multipath default routes installed for `load_balance`, one per family
+    pub multipath: Vec<MultipathRoute>,
+
+    /// Interfaces whose gateway health check currently fails
+    pub failed_gateways: HashSet<String>,
+
+    /// `routing.mark_rules`
+    pub mark_rule_configs: Vec<MarkRuleConfig>,
+
+    /// Firewall mark rules installed for `mark_rule_configs`
+    pub mark_rules: Vec<MarkRule>,
+
+    /// `routing.mode`
+    pub mode: RoutingMode,
+
+    /// VRF device name of each policy interface, in `mode: vrf`
+    pub vrf_names: BTreeMap<String, String>,
+
+    /// VRF devices netevd created or took over, by policy interface name
+    pub vrfs: BTreeMap<String, Vrf>,
 }
 
 impl NetworkState {
@@ -62,10 +211,75 @@ impl NetworkState {
         if let Some(name) = self.links_by_index.remove(&index) {
             self.links_by_name.remove(&name);
         }
+        self.down_links.remove(&index);
         // Also remove associated routes and rules
         self.routes.retain(|(idx, _), _| *idx != index);
     }
 
+    /// Record whether a link is up; returns true if that changed
+    pub fn set_link_up(&mut self, index: u32, up: bool) -> bool {
+        if up {
+            self.down_links.remove(&index)
+        } else {
+            self.down_links.insert(index)
+        }
+    }
+
+    /// Whether an interface belongs to the load-balance group
+    pub fn is_load_balance_member(&self, name: &str) -> bool {
+        self.load_balance
+            .as_ref()
+            .is_some_and(|config| config.members.contains_key(name))
+    }
+
+    /// Whether a link is a policy interface of `mode: vrf` or the VRF
+    /// device of one
+    pub fn is_vrf_link(&self, name: &str) -> bool {
+        self.vrf_names
+            .iter()
+            .any(|(interface, vrf)| interface == name || vrf == name)
+    }
+
+    /// The table of a policy interface, if it can carry traffic of a family
+    ///
+    /// That is the case while its link is up, its gateway passes the health
+    /// check (if any), and netevd has set up its table for the family, so
+    /// the table has a default route.
+    pub fn usable_uplink(&self, name: &str, ipv6: bool) -> Option<&RouteEntry> {
+        let ifindex = self.get_link_index(name)?;
+        if self.down_links.contains(&ifindex) || self.failed_gateways.contains(name) {
+            return None;
+        }
+        let route = self.routes.get(&(ifindex, self.link_table(ifindex)?))?;
+        route.default_gateway(ipv6).is_some().then_some(route)
+    }
+
+    /// Record the outcome of an interface's gateway health check; returns
+    /// true if that changed
+    pub fn set_gateway_health(&mut self, name: &str, healthy: bool) -> bool {
+        if healthy {
+            self.failed_gateways.remove(name)
+        } else {
+            self.failed_gateways.insert(name.to_string())
+        }
+    }
+
+    /// Whether the gateway health check of a link currently fails
+    pub fn gateway_failed(&self, ifindex: u32) -> bool {
+        self.get_link_name(ifindex)
+            .is_some_and(|name| self.failed_gateways.contains(name))
+    }
+
+    /// Gateway of the default route that belongs in a link's table for a
+    /// family: the tracked one, unless the link's gateway health check
+    /// fails and the route is withdrawn
+    pub fn table_gateway(&self, ifindex: u32, table: u32, ipv6: bool) -> Option<IpAddr> {
+        if self.gateway_failed(ifindex) {
+            return None;
+        }
+        self.routes.get(&(ifindex, table))?.default_gateway(ipv6)
+    }
+
     /// Get link name by index
     pub fn get_link_name(&self, index: u32) -> Option<&String> {
         self.links_by_index.get(&index)
@@ -76,14 +290,40 @@ impl NetworkState {
         self.links_by_name.get(name).copied()
     }
 
-    /// Add a route entry
+    /// Add a route entry, or set the gateway of the given address family
+    /// on an existing one
     pub fn add_route(&mut self, ifindex: u32, table: u32, gateway: Option<IpAddr>) {
-        let route = RouteEntry {
+        let route = self.route_entry(ifindex, table);
+        match gateway {
+            Some(IpAddr::V6(_)) => route.gateway6 = gateway,
+            _ => route.gateway = gateway,
+        }
+    }
+
+    /// Forget the gateway of one address family of a route entry
+    pub fn clear_gateway(&mut self, ifindex: u32, table: u32, ipv6: bool) {
+        if let Some(route) = self.routes.get_mut(&(ifindex, table)) {
+            if ipv6 {
+                route.gateway6 = None;
+            } else {
+                route.gateway = None;
+            }
+        }
+    }
+
+    /// Track the connected route of a subnet in a link's table
+    pub fn add_subnet_route(&mut self, ifindex: u32, table: u32, subnet: (IpAddr, u8)) {
+        self.route_entry(ifindex, table).subnets.insert(subnet);
+    }
+
+    fn route_entry(&mut self, ifindex: u32, table: u32) -> &mut RouteEntry {
+        self.routes.entry((ifindex, table)).or_insert(RouteEntry {
             ifindex,
-            gateway,
+            gateway: None,
+            gateway6: None,
+            subnets: BTreeSet::new(),
             table,
-        };
-        self.routes.insert((ifindex, table), route);
+        })
     }
 
     /// Remove a route entry
@@ -93,34 +333,129 @@ impl NetworkState {
 
     /// Add a routing rule (from)
     pub fn add_routing_rule_from(&mut self, address: IpAddr, table: u32) {
-        let rule = RoutingRule {
+        self.add_routing_rule(RoutingRule {
             address,
+            prefix_len: host_prefix_len(&address),
             table,
             is_from: true,
-        };
-        self.routing_rules_from.insert(address, rule);
+            options: RuleOptions::default(),
+        });
     }
 
     /// Add a routing rule (to)
     pub fn add_routing_rule_to(&mut self, address: IpAddr, table: u32) {
-        let rule = RoutingRule {
+        self.add_routing_rule(RoutingRule {
             address,
+            prefix_len: host_prefix_len(&address),
             table,
             is_from: false,
+            options: RuleOptions::default(),
+        });
+    }
+
+    /// Whether exactly this rule is tracked
+    pub fn has_routing_rule(&self, rule: &RoutingRule) -> bool {
+        let tracked = if rule.is_from {
+            &self.routing_rules_from
+        } else {
+            &self.routing_rules_to
         };
-        self.routing_rules_to.insert(address, rule);
+        tracked.get(&rule.key()) == Some(rule)
+    }
+
+    /// Track a routing rule
+    ///
+    /// Rules are keyed by [`RoutingRule::key`], so the `to` rule of a
+    /// subnet is tracked once for all addresses in it.
+    pub fn add_routing_rule(&mut self, rule: RoutingRule) {
+        if rule.is_from {
+            self.routing_rules_from.insert(rule.key(), rule);
+        } else {
+            self.routing_rules_to.insert(rule.key(), rule);
+        }
+    }
+
+    /// Stop tracking a routing rule, if exactly this rule is tracked
+    pub fn remove_routing_rule(&mut self, rule: &RoutingRule) {
+        if self.has_routing_rule(rule) {
+            if rule.is_from {
+                self.routing_rules_from.remove(&rule.key());
+            } else {
+                self.routing_rules_to.remove(&rule.key());
+            }
+        }
+    }
+
+    /// Every tracked routing rule
+    pub fn routing_rules(&self) -> impl Iterator<Item = &RoutingRule> {
+        self.routing_rules_from.values().chain(self.routing_rules_to.values())
     }
 
     /// Remove routing rules for an address
     pub fn remove_routing_rules(&mut self, address: &IpAddr) {
-        self.routing_rules_from.remove(address);
-        self.routing_rules_to.remove(address);
+        self.routing_rules_from.retain(|_, rule| rule.address != *address);
+        self.routing_rules_to.retain(|_, rule| rule.address != *address);
     }
 
     /// Check if we have routing rules for an address
     pub fn has_routing_rules(&self, address: &IpAddr) -> bool {
-        self.routing_rules_from.contains_key(address) || self.routing_rules_to.contains_key(address)
+        self.routing_rules().any(|rule| rule.address == *address)
+    }
+
+    /// Routing table of a link
+    ///
+    /// Looked up by name; for a link that is already gone, the table its
+    /// tracked routes were installed in.
+    pub fn link_table(&self, ifindex: u32) -> Option<u32> {
+        self.get_link_name(ifindex)
+            .and_then(|name| self.tables.get(name))
+            .or_else(|| {
+                self.routes
+                    .keys()
+                    .find(|(idx, _)| *idx == ifindex)
+                    .map(|(_, table)| *table)
+            })
+    }
+
+    /// Rule shape configured for a link
+    pub fn link_rule_config(&self, ifindex: u32) -> RuleConfig {
+        self.get_link_name(ifindex)
+            .and_then(|name| self.rule_configs.get(name))
+            .cloned()
+            .unwrap_or_default()
     }
+
+    /// Check if netevd has installed rules or routes in a table
+    pub fn owns_table(&self, table: u32) -> bool {
+        self.routes.keys().any(|(_, t)| *t == table)
+            || self
+                .routing_rules_from
+                .values()
+                .chain(self.routing_rules_to.values())
+                .any(|rule| rule.table == table)
+            || self.mark_rules.iter().any(|rule| rule.table == table)
+    }
+}
+
+/// `ip` option selecting the address family, for change descriptions
+pub(crate) fn ip_family(address: &IpAddr) -> &'static str {
+    if address.is_ipv6() {
+        "-6 "
+    } else {
+        ""
+    }
+}
+
+/// Whether a failed delete means the object was already gone
+///
+/// The kernel answers ESRCH for a missing route, ENOENT for a missing
+/// rule and ENODEV for a missing link.
+pub(crate) fn is_not_found(error: &rtnetlink::Error) -> bool {
+    matches!(
+        error,
+        rtnetlink::Error::NetlinkError(e)
+            if [libc::ESRCH, libc::ENOENT, libc::ENODEV].contains(&-e.raw_code())
+    )
 }
 
 #[cfg(test)]
@@ -141,6 +476,20 @@ mod tests {
         assert_eq!(state.get_link_name(2), None);
     }
 
+    #[test]
+    fn test_link_up() {
+        let mut state = NetworkState::new();
+        state.add_link("eth0".to_string(), 2);
+
+        assert!(!state.set_link_up(2, true));
+        assert!(state.set_link_up(2, false));
+        assert!(!state.set_link_up(2, false));
+        assert!(state.down_links.contains(&2));
+
+        state.remove_link(2);
+        assert!(state.down_links.is_empty());
+    }
+
     #[test]
     fn test_routing_rules() {
         let mut state = NetworkState::new();
@@ -150,9 +499,38 @@ mod tests {
         state.add_routing_rule_to(addr, 10000);
 
         assert!(state.has_routing_rules(&addr));
+        assert!(state.owns_table(10000));
 
         state.remove_routing_rules(&addr);
         assert!(!state.has_routing_rules(&addr));
+        assert!(!state.owns_table(10000));
+    }
+
+    #[test]
+    fn test_routing_rules_overlapping_subnets() {
+        let mut state = NetworkState::new();
+        let config = RuleConfig {
+            to_subnet: true,
+            ..RuleConfig::default()
+        };
+        // eth1 and eth2 both sit on 192.168.1.0/24
+        let eth1 = RoutingRule::for_address("192.168.1.10".parse().unwrap(), 24, 1001, &config);
+        let eth2 = RoutingRule::for_address("192.168.1.20".parse().unwrap(), 24, 1002, &config);
+        assert_eq!(eth1[1].address, eth2[1].address);
+        for rule in eth1.iter().chain(&eth2) {
+            state.add_routing_rule(rule.clone());
+        }
+
+        // Both subnet rules are tracked, each with its own table
+        assert_eq!(state.routing_rules_to.len(), 2);
+        assert!(eth1.iter().chain(&eth2).all(|rule| state.has_routing_rule(rule)));
+
+        for rule in &eth1 {
+            state.remove_routing_rule(rule);
+        }
+        assert!(!state.owns_table(1001));
+        assert!(state.owns_table(1002));
+        assert!(eth2.iter().all(|rule| state.has_routing_rule(rule)));
     }
 
     #[test]
@@ -166,4 +544,113 @@ mod tests {
         state.remove_route(2, 254);
         assert!(!state.routes.contains_key(&(2, 254)));
     }
+
+    #[test]
+    fn test_dual_stack_route() {
+        let mut state = NetworkState::new();
+        let gateway = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1));
+        let gateway6: IpAddr = "fe80::1".parse().unwrap();
+
+        state.add_route(2, 1002, Some(gateway));
+        state.add_route(2, 1002, Some(gateway6));
+
+        let route = &state.routes[&(2, 1002)];
+        assert_eq!(route.gateway, Some(gateway));
+        assert_eq!(route.gateway6, Some(gateway6));
+
+        state.add_subnet_route(2, 1002, ("192.168.1.0".parse().unwrap(), 24));
+        assert_eq!(state.routes[&(2, 1002)].subnets.len(), 1);
+        assert_eq!(state.routes[&(2, 1002)].gateway, Some(gateway));
+
+        // Losing the IPv4 default route keeps the IPv6 one and the subnets
+        state.clear_gateway(2, 1002, false);
+        let route = &state.routes[&(2, 1002)];
+        assert_eq!(route.gateway, None);
+        assert_eq!(route.gateway6, Some(gateway6));
+        assert_eq!(route.subnets.len(), 1);
+    }
+
+    #[test]
+    fn test_describe_rule() {
+        let mut rule = RoutingRule {
+            address: "192.168.1.10".parse().unwrap(),
+            prefix_len: 32,
+            table: 1002,
+            is_from: true,
+            options: RuleOptions::default(),
+        };
+        assert_eq!(rule.describe(), "from 192.168.1.10 lookup 1002");
+        assert_eq!(rule.describe_suppress(), None);
+
+        rule.options = RuleOptions {
+            priority: Some(100),
+            iif: Some("eth1".to_string()),
+            fwmark: Some(0x10),
+            fwmask: Some(0xff),
+            suppress_prefixlength: Some(0),
+            ..RuleOptions::default()
+        };
+        assert_eq!(
+            rule.describe(),
+            "from 192.168.1.10 iif eth1 fwmark 0x10/0xff lookup 1002 priority 100"
+        );
+        assert_eq!(
+            rule.describe_suppress().unwrap(),
+            "from 192.168.1.10 iif eth1 fwmark 0x10/0xff lookup main suppress_prefixlength 0 priority 99"
+        );
+    }
+
+    #[test]
+    fn test_rules_for_address() {
+        let address: IpAddr = "192.168.1.10".parse().unwrap();
+        let mut config = RuleConfig::default();
+
+        let rules = RoutingRule::for_address(address, 24, 1002, &config);
+        assert_eq!(rules.len(), 2);
+        assert!(rules.iter().all(|rule| rule.address == address && rule.prefix_len == 32));
+
+        config.to_subnet = true;
+        let rules = RoutingRule::for_address(address, 24, 1002, &config);
+        assert_eq!(rules[0].describe(), "from 192.168.1.10 lookup 1002");
+        assert_eq!(rules[1].describe(), "to 192.168.1.0/24 lookup 1002");
+
+        // Every address of the subnet shares the same "to" rule
+        let mut state = NetworkState::new();
+        rules.into_iter().for_each(|rule| state.add_routing_rule(rule));
+        let sibling = RoutingRule::for_address("192.168.1.11".parse().unwrap(), 24, 1002, &config);
+        assert!(!state.has_routing_rule(&sibling[0]));
+        assert!(state.has_routing_rule(&sibling[1]));
+    }
+
+    #[test]
+    fn test_link_table() {
+        let mut state = NetworkState::new();
+        state.tables.allocate("eth1");
+        state.add_link("eth1".to_string(), 7);
+        assert_eq!(state.link_table(7), Some(1000));
+
+        // Recreated with a new ifindex, same table
+        state.remove_link(7);
+        state.add_link("eth1".to_string(), 9);
+        assert_eq!(state.link_table(9), Some(1000));
+
+        // Gone, but routes still tracked
+        state.add_route(9, 1000, Some("192.168.1.1".parse().unwrap()));
+        state.links_by_index.clear();
+        assert_eq!(state.link_table(9), Some(1000));
+        assert_eq!(state.link_table(3), None);
+    }
+
+    #[test]
+    fn test_is_not_found() {
+        let error = |errno: i32| {
+            let mut message = netlink_packet_core::ErrorMessage::default();
+            message.code = std::num::NonZeroI32::new(-errno);
+            rtnetlink::Error::NetlinkError(message)
+        };
+        assert!(is_not_found(&error(libc::ESRCH)));
+        assert!(is_not_found(&error(libc::ENOENT)));
+        assert!(is_not_found(&error(libc::ENODEV)));
+        assert!(!is_not_found(&error(libc::EPERM)));
+    }
 }
@@ -0,0 +1,622 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+
+//! Startup reconciliation with rules and routes left by a previous run
+//!
+//! netevd keeps what it installed only in memory. After a crash or a
+//! SIGKILL the kernel still holds the old rules and routes, so on startup
+//! everything in netevd's tables is either adopted into [`NetworkState`],
+//! when it still matches a policy interface and its current addresses or a
+//! configured mark rule, or removed. Tables in netevd's range that it did
+//! not assign may belong to another tool and are only logged.
+
+use anyhow::Result;
+use rtnetlink::{Handle, IpVersion};
+use std::collections::{BTreeSet, HashSet};
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use super::mark::{self, MarkRule};
+use super::route::{self, KernelRoute};
+use super::routing_rule::{self, mark_rule_matches, suppress_rule_matches, tracked_rule_matches, KernelRule};
+use super::table::TableAllocator;
+use super::{NetworkState, RoutingRule};
+use crate::config::{RoutingMode, RuleConfig};
+
+/// A `routing.policy_rules` interface as found at startup
+#[derive(Debug, Clone)]
+pub struct PolicyLink {
+    pub ifindex: u32,
+    pub table: u32,
+    /// Current addresses, with their prefix lengths
+    pub addresses: Vec<(IpAddr, u8)>,
+    /// Shape of the rules the interface should have
+    pub rules: RuleConfig,
+    /// Enslaved to a VRF, whose l3mdev rule replaces the per-address rules
+    pub vrf: bool,
+}
+
+impl PolicyLink {
+    /// The rules netevd installs for the link's current addresses
+    fn expected_rules(&self) -> impl Iterator<Item = RoutingRule> + '_ {
+        self.addresses
+            .iter()
+            .filter(|_| !self.vrf)
+            .flat_map(move |(address, prefix_len)| RoutingRule::for_address(*address, *prefix_len, self.table, &self.rules))
+    }
+}
+
+/// What reconciliation did, in `ip rule`/`ip route` terms
+#[derive(Debug, Default)]
+pub struct ReconcileReport {
+    pub adopted: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl ReconcileReport {
+    pub fn is_empty(&self) -> bool {
+        self.adopted.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// What to do with a rule or route found in a netevd table
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    /// Keep it, tracking the rule if it is one of the per-address rules
+    Adopt(Option<RoutingRule>),
+    /// Keep a firewall mark rule, tracking it
+    AdoptMark(MarkRule),
+    Remove,
+    /// In netevd's range, but in a table it did not assign
+    Leave,
+}
+
+/// Adopt or remove every rule and route in netevd's tables
+///
+/// In dry-run mode matching entries are still adopted, since they describe
+/// what is already installed, but orphans are only logged.
+pub async fn reconcile(
+    handle: &Handle,
+    state: &Arc<RwLock<NetworkState>>,
+    links: &[PolicyLink],
+    dry_run: bool,
+) -> Result<ReconcileReport> {
+    let mut report = ReconcileReport::default();
+    let (tables, marks, vrf) = {
+        let state_read = state.read().await;
+        (
+            state_read.tables.clone(),
+            mark::configured_rules(&state_read, false),
+            state_read.mode == RoutingMode::Vrf,
+        )
+    };
+
+    // Rules: adopt one per tracked selector, remove the rest
+    let mut foreign = BTreeSet::new();
+    let mut adopted_rules = HashSet::new();
+    let mut adopted_marks = HashSet::new();
+    for family in [IpVersion::V4, IpVersion::V6] {
+        let messages = routing_rule::dump_rules(handle, family.clone()).await?;
+        let dumped: Vec<KernelRule> = messages
+            .iter()
+            .map(|message| routing_rule::kernel_rule_from_message(message, family.clone()))
+            .collect();
+
+        for (message, rule) in messages.into_iter().zip(&dumped) {
+            let Some(verdict) = mark_verdict(rule, &marks, &mut adopted_marks)
+                .or_else(|| rule_verdict(rule, &dumped, &tables, links, &mut adopted_rules))
+            else {
+                continue;
+            };
+
+            let description = describe_rule(rule);
+            match verdict {
+                Verdict::Adopt(tracked) => {
+                    if let Some(tracked) = tracked {
+                        state.write().await.add_routing_rule(tracked);
+                    }
+                    debug!("Adopted `ip {}`", description);
+                    report.adopted.push(description);
+                }
+                Verdict::AdoptMark(tracked) => {
+                    // Whether the interface can still carry the family is
+                    // settled by the mark rule update after address sync
+                    state.write().await.mark_rules.push(tracked);
+                    debug!("Adopted `ip {}`", description);
+                    report.adopted.push(description);
+                }
+                Verdict::Remove => {
+                    if dry_run {
+                        info!("Dry run: would run `ip {}`", removal(&description));
+                    } else if let Err(e) = handle.rule().del(message).execute().await {
+                        warn!("Failed to remove stale `ip {}`: {}", description, e);
+                        continue;
+                    }
+                    report.removed.push(removal(&description));
+                }
+                Verdict::Leave => {
+                    debug!("Leaving `ip {}` alone", description);
+                    foreign.insert(rule.table);
+                }
+            }
+        }
+    }
+
+    // Routes: keep whatever goes out of the table's own interface
+    for family in [IpVersion::V4, IpVersion::V6] {
+        for message in route::dump_routes(handle, family.clone()).await? {
+            let kernel_route = route::kernel_route_from_message(&message, family.clone());
+            let Some(verdict) = route_verdict(&kernel_route, &tables, links, vrf) else {
+                continue;
+            };
+
+            let description = describe_route(&kernel_route);
+            match verdict {
+                Verdict::Adopt(_) | Verdict::AdoptMark(_) => {
+                    if let (Some(ifindex), Some(gateway), true) = (
+                        kernel_route.oif,
+                        kernel_route.gateway,
+                        kernel_route.destination.ends_with("/0"),
+                    ) {
+                        state
+                            .write()
+                            .await
+                            .add_route(ifindex, kernel_route.table, Some(gateway));
+                    } else if let (Some(ifindex), None, Some(subnet)) =
+                        (kernel_route.oif, kernel_route.gateway, route_subnet(&kernel_route))
+                    {
+                        // Subnets the interface no longer has are pruned
+                        // when its addresses are synced
+                        state
+                            .write()
+                            .await
+                            .add_subnet_route(ifindex, kernel_route.table, subnet);
+                    }
+                    debug!("Adopted `ip {}`", description);
+                    report.adopted.push(description);
+                }
+                Verdict::Remove => {
+                    if dry_run {
+                        info!("Dry run: would run `ip {}`", removal(&description));
+                    } else if let Err(e) = handle.route().del(message).execute().await {
+                        warn!("Failed to remove stale `ip {}`: {}", description, e);
+                        continue;
+                    }
+                    report.removed.push(removal(&description));
+                }
+                Verdict::Leave => {
+                    debug!("Leaving `ip {}` alone", description);
+                    foreign.insert(kernel_route.table);
+                }
+            }
+        }
+    }
+
+    if !foreign.is_empty() {
+        warn!(
+            "Tables {:?} are in netevd's range but not assigned to any of its interfaces; \
+             leaving their rules and routes in place",
+            foreign
+        );
+    }
+
+    if !report.is_empty() {
+        info!(
+            "Startup reconciliation: adopted {} and removed {} rules and routes",
+            report.adopted.len(),
+            report.removed.len()
+        );
+    }
+
+    Ok(report)
+}
+
+/// Decide about a rule that may be a configured firewall mark rule;
+/// `None` if it is not one
+///
+/// Duplicates of an adopted mark rule are removed.
+fn mark_verdict(rule: &KernelRule, marks: &[MarkRule], adopted: &mut HashSet<MarkRule>) -> Option<Verdict> {
+    let mark = marks.iter().find(|mark| mark_rule_matches(mark, rule))?;
+    Some(if adopted.insert(mark.clone()) {
+        Verdict::AdoptMark(mark.clone())
+    } else {
+        Verdict::Remove
+    })
+}
+
+/// Decide about a rule; `None` if it is not netevd's
+///
+/// Rules in tables of the range that netevd did not assign are left alone.
+/// A rule in a netevd table is adopted if it is exactly one of the rules
+/// netevd would install for the current addresses of the table's interface,
+/// with the configured priority and matches. Duplicates of an adopted rule
+/// are removed, and so is a rule whose `suppress_prefixlength` lookup of
+/// the main table is missing from `dumped`, so that both get installed
+/// again. Such lookups are adopted when they belong to an expected rule
+/// and left alone otherwise.
+fn rule_verdict(
+    rule: &KernelRule,
+    dumped: &[KernelRule],
+    tables: &TableAllocator,
+    links: &[PolicyLink],
+    adopted: &mut HashSet<(RoutingRule, bool)>,
+) -> Option<Verdict> {
+    if !tables.is_managed(rule.table) && !tables.is_owned(rule.table) {
+        let suppress = links
+            .iter()
+            .flat_map(|link| link.expected_rules())
+            .find(|expected| suppress_rule_matches(expected, rule))?;
+        return Some(if adopted.insert((suppress, true)) {
+            Verdict::Adopt(None)
+        } else {
+            Verdict::Remove
+        });
+    }
+    if !tables.is_owned(rule.table) {
+        return Some(Verdict::Leave);
+    }
+
+    let expected = links
+        .iter()
+        .filter(|link| link.table == rule.table)
+        .flat_map(|link| link.expected_rules())
+        .find(|expected| tracked_rule_matches(expected, rule));
+
+    let complete = |expected: &RoutingRule| {
+        expected.options.suppress_prefixlength.is_none()
+            || dumped.iter().any(|kernel| suppress_rule_matches(expected, kernel))
+    };
+
+    Some(match expected {
+        Some(expected) if complete(&expected) && adopted.insert((expected.clone(), false)) => {
+            Verdict::Adopt(Some(expected))
+        }
+        _ => Verdict::Remove,
+    })
+}
+
+/// Decide about a route; `None` if it is not in netevd's range
+///
+/// Routes in tables of the range that netevd did not assign are left
+/// alone. In a VRF table only the routes via a gateway are netevd's; the
+/// connected, local and unreachable routes belong to the kernel.
+fn route_verdict(route: &KernelRoute, tables: &TableAllocator, links: &[PolicyLink], vrf: bool) -> Option<Verdict> {
+    if (!tables.is_managed(route.table) && !tables.is_owned(route.table)) || (vrf && route.gateway.is_none()) {
+        return None;
+    }
+    if !tables.is_owned(route.table) {
+        return Some(Verdict::Leave);
+    }
+
+    let owned = links
+        .iter()
+        .any(|link| link.table == route.table && route.oif == Some(link.ifindex));
+
+    Some(if owned { Verdict::Adopt(None) } else { Verdict::Remove })
+}
+
+/// Destination of a connected route, as network address and prefix length
+fn route_subnet(route: &KernelRoute) -> Option<(IpAddr, u8)> {
+    let (network, prefix_len) = route.destination.split_once('/')?;
+    let prefix_len = prefix_len.parse().ok().filter(|len| *len > 0)?;
+    Some((network.parse().ok()?, prefix_len))
+}
+
+fn family_flag(family: &IpVersion) -> &'static str {
+    match family {
+        IpVersion::V4 => "",
+        IpVersion::V6 => "-6 ",
+    }
+}
+
+fn describe_rule(rule: &KernelRule) -> String {
+    let mut description = format!("{}rule", family_flag(&rule.family));
+    if let Some((address, len)) = rule.source {
+        description.push_str(&format!(" from {}/{}", address, len));
+    }
+    if let Some((address, len)) = rule.destination {
+        description.push_str(&format!(" to {}/{}", address, len));
+    }
+    if let Some(iif) = &rule.iif {
+        description.push_str(&format!(" iif {}", iif));
+    }
+    if let Some(oif) = &rule.oif {
+        description.push_str(&format!(" oif {}", oif));
+    }
+    match (rule.fwmark, rule.fwmask) {
+        (Some(mark), Some(mask)) if mask != u32::MAX => {
+            description.push_str(&format!(" fwmark {:#x}/{:#x}", mark, mask))
+        }
+        (Some(mark), _) => description.push_str(&format!(" fwmark {:#x}", mark)),
+        _ => {}
+    }
+    description.push_str(&format!(" {} {}", rule.action, rule.table));
+    if let Some(length) = rule.suppress_prefixlength {
+        description.push_str(&format!(" suppress_prefixlength {}", length));
+    }
+    description
+}
+
+fn describe_route(route: &KernelRoute) -> String {
+    let mut description = format!("{}route {}", family_flag(&route.family), route.destination);
+    if let Some(gateway) = route.gateway {
+        description.push_str(&format!(" via {}", gateway));
+    }
+    description.push_str(&format!(" table {}", route.table));
+    description
+}
+
+/// Turn `rule ...`/`route ...` into the matching `del` command
+fn removal(description: &str) -> String {
+    description
+        .replacen("rule", "rule del", 1)
+        .replacen("route", "route del", 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{RuleSelectors, TablesConfig};
+
+    fn link() -> PolicyLink {
+        PolicyLink {
+            ifindex: 2,
+            table: 1002,
+            addresses: vec![("192.168.1.10".parse().unwrap(), 24)],
+            rules: RuleConfig::default(),
+            vrf: false,
+        }
+    }
+
+    /// eth1 has table 1002 and is present as `link()`, eth3 is configured
+    /// but absent
+    fn tables() -> TableAllocator {
+        TableAllocator::new(&TablesConfig {
+            interfaces: [("eth1".to_string(), 1002), ("eth3".to_string(), 1003)].into(),
+            ..Default::default()
+        })
+    }
+
+    fn tracked(address: &str, table: u32) -> RoutingRule {
+        RoutingRule {
+            address: address.parse().unwrap(),
+            prefix_len: 32,
+            table,
+            is_from: true,
+            options: Default::default(),
+        }
+    }
+
+    fn rule(source: &str, table: u32) -> KernelRule {
+        KernelRule {
+            family: IpVersion::V4,
+            priority: 32765,
+            source: Some((source.parse().unwrap(), 32)),
+            destination: None,
+            fwmark: None,
+            fwmask: None,
+            iif: None,
+            oif: None,
+            suppress_prefixlength: None,
+            table,
+            action: "lookup".to_string(),
+        }
+    }
+
+    fn route(oif: u32, table: u32) -> KernelRoute {
+        KernelRoute {
+            family: IpVersion::V4,
+            destination: "0.0.0.0/0".to_string(),
+            gateway: Some("192.168.1.1".parse().unwrap()),
+            oif: Some(oif),
+            metric: None,
+            table,
+        }
+    }
+
+    #[test]
+    fn test_rule_verdict() {
+        let links = [link()];
+        let tables = tables();
+        let mut adopted = HashSet::new();
+
+        // Outside netevd's range: not ours to touch
+        assert_eq!(rule_verdict(&rule("192.168.1.10", 254), &[], &tables, &links, &mut adopted), None);
+
+        assert_eq!(
+            rule_verdict(&rule("192.168.1.10", 1002), &[], &tables, &links, &mut adopted),
+            Some(Verdict::Adopt(Some(tracked("192.168.1.10", 1002))))
+        );
+        // A second copy of the same rule is a duplicate
+        assert_eq!(
+            rule_verdict(&rule("192.168.1.10", 1002), &[], &tables, &links, &mut adopted),
+            Some(Verdict::Remove)
+        );
+        // Address no longer on the interface
+        assert_eq!(
+            rule_verdict(&rule("192.168.1.99", 1002), &[], &tables, &links, &mut adopted),
+            Some(Verdict::Remove)
+        );
+        // Table of a policy interface that is not present
+        assert_eq!(
+            rule_verdict(&rule("192.168.1.10", 1003), &[], &tables, &links, &mut adopted),
+            Some(Verdict::Remove)
+        );
+        // In the range, but not a table netevd assigned
+        assert_eq!(
+            rule_verdict(&rule("192.168.1.10", 1007), &[], &tables, &links, &mut adopted),
+            Some(Verdict::Leave)
+        );
+
+        let mut marked = rule("192.168.1.10", 1002);
+        marked.fwmark = Some(1);
+        assert_eq!(rule_verdict(&marked, &[], &tables, &links, &mut HashSet::new()), Some(Verdict::Remove));
+
+        // A link in a VRF has no per-address rules, so leftovers go
+        let vrf_links = [PolicyLink { vrf: true, ..link() }];
+        assert_eq!(
+            rule_verdict(&rule("192.168.1.10", 1002), &[], &tables, &vrf_links, &mut HashSet::new()),
+            Some(Verdict::Remove)
+        );
+    }
+
+    #[test]
+    fn test_rule_verdict_configured_shape() {
+        let mut link = link();
+        link.rules.selectors = RuleSelectors::From;
+        link.rules.options.priority = Some(100);
+        link.rules.options.fwmark = Some(0x10);
+        link.rules.options.suppress_prefixlength = Some(0);
+        let links = [link];
+        let tables = tables();
+        let mut adopted = HashSet::new();
+
+        let mut kernel = rule("192.168.1.10", 1002);
+        kernel.priority = 100;
+        kernel.fwmark = Some(0x10);
+        kernel.fwmask = Some(u32::MAX);
+        let mut suppress = kernel.clone();
+        suppress.table = 254;
+        suppress.priority = 99;
+        suppress.suppress_prefixlength = Some(0);
+        let dumped = [kernel.clone(), suppress.clone()];
+
+        // Without the main table lookup in front of it, the rule is
+        // removed so that both are installed again
+        assert_eq!(
+            rule_verdict(&kernel, &dumped[..1], &tables, &links, &mut HashSet::new()),
+            Some(Verdict::Remove)
+        );
+
+        let mut expected = tracked("192.168.1.10", 1002);
+        expected.options = links[0].rules.options.clone();
+        assert_eq!(
+            rule_verdict(&kernel, &dumped, &tables, &links, &mut adopted),
+            Some(Verdict::Adopt(Some(expected)))
+        );
+
+        // Wrong priority, or a "to" rule the interface no longer wants
+        let mut moved = kernel.clone();
+        moved.priority = 32765;
+        assert_eq!(rule_verdict(&moved, &dumped, &tables, &links, &mut HashSet::new()), Some(Verdict::Remove));
+        let mut to = kernel.clone();
+        to.destination = to.source.take();
+        assert_eq!(rule_verdict(&to, &dumped, &tables, &links, &mut HashSet::new()), Some(Verdict::Remove));
+
+        // The main table lookup in front of it is kept, other main table
+        // rules are not netevd's
+        assert_eq!(rule_verdict(&suppress, &dumped, &tables, &links, &mut adopted), Some(Verdict::Adopt(None)));
+        suppress.suppress_prefixlength = None;
+        assert_eq!(rule_verdict(&suppress, &dumped, &tables, &links, &mut adopted), None);
+    }
+
+    #[test]
+    fn test_rule_verdict_subnet() {
+        let mut link = link();
+        link.addresses.push(("192.168.1.11".parse().unwrap(), 24));
+        link.rules.selectors = RuleSelectors::To;
+        link.rules.to_subnet = true;
+        let links = [link];
+        let tables = tables();
+        let mut adopted = HashSet::new();
+
+        let mut kernel = rule("192.168.1.10", 1002);
+        kernel.destination = Some(("192.168.1.0".parse().unwrap(), 24));
+        kernel.source = None;
+        let mut expected = tracked("192.168.1.0", 1002);
+        expected.prefix_len = 24;
+        expected.is_from = false;
+        expected.options = links[0].rules.options.clone();
+
+        // One rule for both addresses, so a second copy is a duplicate
+        assert_eq!(
+            rule_verdict(&kernel, &[], &tables, &links, &mut adopted),
+            Some(Verdict::Adopt(Some(expected)))
+        );
+        assert_eq!(rule_verdict(&kernel, &[], &tables, &links, &mut adopted), Some(Verdict::Remove));
+
+        // A host rule is not what the interface wants
+        kernel.destination = Some(("192.168.1.10".parse().unwrap(), 32));
+        assert_eq!(rule_verdict(&kernel, &[], &tables, &links, &mut HashSet::new()), Some(Verdict::Remove));
+    }
+
+    #[test]
+    fn test_mark_verdict() {
+        let mark = MarkRule {
+            interface: "eth1".to_string(),
+            ipv6: false,
+            fwmark: 0x10,
+            fwmask: None,
+            priority: Some(500),
+            table: 1002,
+        };
+        let marks = [mark.clone()];
+        let mut adopted = HashSet::new();
+
+        let mut kernel = rule("192.168.1.10", 1002);
+        kernel.source = None;
+        kernel.priority = 500;
+        kernel.fwmark = Some(0x10);
+        kernel.fwmask = Some(u32::MAX);
+        assert_eq!(mark_verdict(&kernel, &marks, &mut adopted), Some(Verdict::AdoptMark(mark)));
+        assert_eq!(mark_verdict(&kernel, &marks, &mut adopted), Some(Verdict::Remove));
+
+        // Anything else is left to the per-address rules, where a mark
+        // rule that is no longer configured is removed
+        kernel.priority = 600;
+        assert_eq!(mark_verdict(&kernel, &marks, &mut HashSet::new()), None);
+        let links = [link()];
+        assert_eq!(
+            rule_verdict(&kernel, &[], &tables(), &links, &mut HashSet::new()),
+            Some(Verdict::Remove)
+        );
+    }
+
+    #[test]
+    fn test_route_subnet() {
+        let mut subnet = route(2, 1002);
+        subnet.destination = "192.168.1.0/24".to_string();
+        subnet.gateway = None;
+        assert_eq!(route_subnet(&subnet), Some(("192.168.1.0".parse().unwrap(), 24)));
+        assert_eq!(route_subnet(&route(2, 1002)), None);
+    }
+
+    #[test]
+    fn test_route_verdict() {
+        let links = [link()];
+        let tables = tables();
+        assert_eq!(route_verdict(&route(2, 254), &tables, &links, false), None);
+        assert_eq!(route_verdict(&route(2, 1002), &tables, &links, false), Some(Verdict::Adopt(None)));
+        assert_eq!(route_verdict(&route(3, 1002), &tables, &links, false), Some(Verdict::Remove));
+        assert_eq!(route_verdict(&route(7, 1003), &tables, &links, false), Some(Verdict::Remove));
+        assert_eq!(route_verdict(&route(7, 1007), &tables, &links, false), Some(Verdict::Leave));
+
+        // The kernel's own routes in a VRF table are left alone
+        let mut connected = route(2, 1002);
+        connected.destination = "192.168.1.0/24".to_string();
+        connected.gateway = None;
+        assert_eq!(route_verdict(&connected, &tables, &links, false), Some(Verdict::Adopt(None)));
+        assert_eq!(route_verdict(&connected, &tables, &links, true), None);
+        assert_eq!(route_verdict(&route(2, 1002), &tables, &links, true), Some(Verdict::Adopt(None)));
+    }
+
+    #[test]
+    fn test_descriptions() {
+        let description = describe_rule(&rule("192.168.1.10", 1002));
+        assert_eq!(description, "rule from 192.168.1.10/32 lookup 1002");
+        assert_eq!(removal(&description), "rule del from 192.168.1.10/32 lookup 1002");
+
+        let mut marked = rule("192.168.1.10", 1002);
+        marked.source = None;
+        marked.fwmark = Some(0x100);
+        marked.fwmask = Some(0xff00);
+        assert_eq!(describe_rule(&marked), "rule fwmark 0x100/0xff00 lookup 1002");
+        marked.fwmask = Some(u32::MAX);
+        assert_eq!(describe_rule(&marked), "rule fwmark 0x100 lookup 1002");
+        assert_eq!(
+            removal(&describe_route(&route(2, 1002))),
+            "route del 0.0.0.0/0 via 192.168.1.1 table 1002"
+        );
+    }
+}
@@ -0,0 +1,523 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+
+//! Re-installation of netevd's rules and routes after external removal
+//!
+//! An administrator, another daemon or `networkctl reload` can delete the
+//! policy rules and routes netevd installed. The rule watcher
+//! notices the deletion; this module compares the kernel with
+//! [`NetworkState`] and puts back whatever is missing.
+
+use anyhow::Result;
+use rtnetlink::Handle;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::audit::{AuditEventType, AuditLogger};
+use crate::metrics::MetricsHandle;
+
+use super::mark::MarkRule;
+use super::route::{self, KernelRoute};
+use super::routing_rule::{self, mark_rule_matches, suppress_rule_matches, tracked_rule_matches, KernelRule};
+use super::{ip_family, NetworkState, RoutingRule};
+
+/// Quiet period after a deletion before the table is checked, so that
+/// netevd's own teardown has updated the state by then
+pub const REPAIR_DELAY: Duration = Duration::from_millis(500);
+
+/// Delay before a table is checked again after a repair failed
+pub const REPAIR_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Repairs allowed per table within [`REPAIR_WINDOW`]
+pub const REPAIR_BURST: usize = 5;
+
+/// Window over which [`REPAIR_BURST`] applies
+pub const REPAIR_WINDOW: Duration = Duration::from_secs(60);
+
+/// Sliding-window limit on repairs, per table
+///
+/// Without it, anything that keeps deleting netevd's rules would turn the
+/// daemon into a busy loop fighting it.
+pub struct RepairLimiter {
+    burst: usize,
+    window: Duration,
+    attempts: HashMap<u32, VecDeque<Instant>>,
+}
+
+impl RepairLimiter {
+    pub fn new(burst: usize, window: Duration) -> Self {
+        Self {
+            burst,
+            window,
+            attempts: HashMap::new(),
+        }
+    }
+
+    /// Record a repair of `table` at `now` if the limit allows it;
+    /// otherwise return when the next one may run
+    pub fn acquire(&mut self, table: u32, now: Instant) -> Result<(), Instant> {
+        let attempts = self.attempts.entry(table).or_default();
+        while attempts
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= self.window)
+        {
+            attempts.pop_front();
+        }
+
+        if let Some(oldest) = attempts.front().filter(|_| attempts.len() >= self.burst) {
+            return Err(*oldest + self.window);
+        }
+
+        attempts.push_back(now);
+        Ok(())
+    }
+}
+
+impl Default for RepairLimiter {
+    fn default() -> Self {
+        Self::new(REPAIR_BURST, REPAIR_WINDOW)
+    }
+}
+
+/// A tracked rule or route the kernel no longer has
+#[derive(Debug, Clone, PartialEq)]
+pub enum Missing {
+    Rule(RoutingRule),
+    MarkRule(MarkRule),
+    Route {
+        ifindex: u32,
+        gateway: IpAddr,
+        table: u32,
+    },
+    /// Connected route of a subnet
+    Subnet {
+        ifindex: u32,
+        subnet: (IpAddr, u8),
+        table: u32,
+    },
+}
+
+impl Missing {
+    /// The re-installation, in `ip rule`/`ip route` terms
+    pub fn describe(&self) -> String {
+        match self {
+            Missing::Rule(rule) => format!("{}rule add {}", ip_family(&rule.address), rule.describe()),
+            Missing::MarkRule(rule) => format!(
+                "{}rule add {}",
+                if rule.ipv6 { "-6 " } else { "" },
+                rule.describe()
+            ),
+            Missing::Route { gateway, table, .. } => format!(
+                "{}route add default via {} table {}",
+                ip_family(gateway),
+                gateway,
+                table
+            ),
+            Missing::Subnet {
+                subnet: (network, prefix_len),
+                table,
+                ..
+            } => format!(
+                "{}route add {}/{} scope link table {}",
+                ip_family(network),
+                network,
+                prefix_len,
+                table
+            ),
+        }
+    }
+}
+
+/// Result of re-installing the missing entries of a table
+#[derive(Debug, Default)]
+pub struct RepairOutcome {
+    /// Entries put back, in `ip` terms
+    pub repaired: Vec<String>,
+    /// Entries that could not be put back, with the error
+    pub failed: Vec<String>,
+}
+
+/// Compare the kernel's view of `table` with what netevd has installed
+pub async fn find_missing(
+    handle: &Handle,
+    state: &Arc<RwLock<NetworkState>>,
+    table: u32,
+) -> Result<Vec<Missing>> {
+    let kernel_rules = routing_rule::list_rules(handle, None).await?;
+    let kernel_routes = route::list_routes(handle, None).await?;
+
+    let state = state.read().await;
+    Ok(missing_entries(&state, table, &kernel_rules, &kernel_routes))
+}
+
+/// Tracked entries of `table` absent from the kernel dumps
+///
+/// A default or subnet route is only expected while rules of its family
+/// still point at the table. Once the last address of a family is gone the
+/// kernel drops the routes by itself, and netevd should not fight that.
+fn missing_entries(
+    state: &NetworkState,
+    table: u32,
+    kernel_rules: &[KernelRule],
+    kernel_routes: &[KernelRoute],
+) -> Vec<Missing> {
+    let tracked: Vec<&RoutingRule> = state
+        .routing_rules_from
+        .values()
+        .chain(state.routing_rules_to.values())
+        .filter(|rule| rule.table == table)
+        .collect();
+
+    // A rule also counts as missing when the main table lookup configured
+    // in front of it is gone; re-adding skips whichever half is present
+    let mut missing: Vec<Missing> = tracked
+        .iter()
+        .filter(|rule| {
+            !kernel_rules.iter().any(|kernel| tracked_rule_matches(rule, kernel))
+                || (rule.options.suppress_prefixlength.is_some()
+                    && !kernel_rules.iter().any(|kernel| suppress_rule_matches(rule, kernel)))
+        })
+        .map(|rule| Missing::Rule((*rule).clone()))
+        .collect();
+    missing.extend(
+        state
+            .mark_rules
+            .iter()
+            .filter(|rule| rule.table == table)
+            .filter(|rule| !kernel_rules.iter().any(|kernel| mark_rule_matches(rule, kernel)))
+            .map(|rule| Missing::MarkRule(rule.clone())),
+    );
+
+    let family_in_use = |address: &IpAddr| {
+        tracked
+            .iter()
+            .any(|rule| rule.address.is_ipv6() == address.is_ipv6())
+    };
+
+    for entry in state.routes.values().filter(|entry| entry.table == table) {
+        // Subnets first, so that the gateway is reachable in the table
+        // when the default route goes back in
+        for (network, prefix_len) in &entry.subnets {
+            let destination = format!("{}/{}", network, prefix_len);
+            let present = kernel_routes.iter().any(|route| {
+                route.table == table && route.oif == Some(entry.ifindex) && route.destination == destination
+            });
+
+            if family_in_use(network) && !present {
+                missing.push(Missing::Subnet {
+                    ifindex: entry.ifindex,
+                    subnet: (*network, *prefix_len),
+                    table,
+                });
+            }
+        }
+
+        // A default route withdrawn for a failed health check stays out
+        let gateways = [false, true].map(|ipv6| state.table_gateway(entry.ifindex, table, ipv6));
+        for gateway in gateways.into_iter().flatten() {
+            let present = kernel_routes.iter().any(|route| {
+                route.table == table
+                    && route.oif == Some(entry.ifindex)
+                    && route.destination.ends_with("/0")
+                    && route
+                        .gateway
+                        .is_some_and(|kernel_gw| kernel_gw.is_ipv6() == gateway.is_ipv6())
+            });
+
+            if family_in_use(&gateway) && !present {
+                missing.push(Missing::Route {
+                    ifindex: entry.ifindex,
+                    gateway,
+                    table,
+                });
+            }
+        }
+    }
+
+    missing
+}
+
+/// Put back the given entries
+///
+/// Routes go first so that a restored rule never points at an empty
+/// table. Every attempt is audited and counted in
+/// `netevd_routing_repairs_total`; in dry-run mode they are only logged.
+pub async fn reinstall(
+    handle: &Handle,
+    missing: &[Missing],
+    audit: &AuditLogger,
+    metrics: Option<&MetricsHandle>,
+    dry_run: bool,
+) -> RepairOutcome {
+    let mut outcome = RepairOutcome::default();
+
+    let (routes, rules): (Vec<&Missing>, Vec<&Missing>) = missing
+        .iter()
+        .partition(|entry| !matches!(entry, Missing::Rule(_) | Missing::MarkRule(_)));
+
+    for entry in routes.into_iter().chain(rules) {
+        let change = entry.describe();
+        if dry_run {
+            info!("Dry run: would run `ip {}` to repair netevd routing", change);
+            outcome.repaired.push(change);
+            continue;
+        }
+
+        let (kind, event_type, table, result) = match entry {
+            Missing::Rule(rule) => {
+                let result = routing_rule::add_routing_rule(handle, rule).await;
+                ("rule", AuditEventType::RuleChange, rule.table, result)
+            }
+            Missing::MarkRule(rule) => {
+                let result = routing_rule::add_mark_rule(handle, rule).await;
+                ("rule", AuditEventType::RuleChange, rule.table, result)
+            }
+            Missing::Route {
+                ifindex,
+                gateway,
+                table,
+            } => {
+                let result = route::add_route(handle, *ifindex, *gateway, *table).await;
+                ("route", AuditEventType::RouteChange, *table, result)
+            }
+            Missing::Subnet {
+                ifindex,
+                subnet,
+                table,
+            } => {
+                let result = route::add_subnet_route(handle, *ifindex, *subnet, *table).await;
+                ("route", AuditEventType::RouteChange, *table, result)
+            }
+        };
+
+        let error = result.err().map(|e| format!("{:#}", e));
+        audit.log_routing_repair(event_type, &change, table, error.as_deref());
+        if let Some(metrics) = metrics {
+            metrics
+                .routing_repairs_total
+                .with_label_values(&[kind, if error.is_none() { "success" } else { "failure" }])
+                .inc();
+        }
+
+        match error {
+            None => {
+                info!("Repaired netevd routing: `ip {}`", change);
+                outcome.repaired.push(change);
+            }
+            Some(error) => {
+                warn!("Failed to repair netevd routing `ip {}`: {}", change, error);
+                outcome.failed.push(format!("{}: {}", change, error));
+            }
+        }
+    }
+
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rtnetlink::IpVersion;
+
+    fn kernel_rule(source: &str, table: u32) -> KernelRule {
+        KernelRule {
+            family: IpVersion::V4,
+            priority: 32765,
+            source: Some((source.parse().unwrap(), 32)),
+            destination: None,
+            fwmark: None,
+            fwmask: None,
+            iif: None,
+            oif: None,
+            suppress_prefixlength: None,
+            table,
+            action: "lookup".to_string(),
+        }
+    }
+
+    fn default_route(gateway: &str, oif: u32, table: u32) -> KernelRoute {
+        KernelRoute {
+            family: IpVersion::V4,
+            destination: "0.0.0.0/0".to_string(),
+            gateway: Some(gateway.parse().unwrap()),
+            oif: Some(oif),
+            metric: None,
+            table,
+        }
+    }
+
+    #[test]
+    fn test_limiter_window() {
+        let mut limiter = RepairLimiter::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert!(limiter.acquire(1002, start).is_ok());
+        assert!(limiter.acquire(1002, start + Duration::from_secs(1)).is_ok());
+        assert_eq!(
+            limiter.acquire(1002, start + Duration::from_secs(2)),
+            Err(start + Duration::from_secs(60))
+        );
+
+        // Other tables have their own budget, and the window slides
+        assert!(limiter.acquire(1003, start + Duration::from_secs(2)).is_ok());
+        assert!(limiter.acquire(1002, start + Duration::from_secs(60)).is_ok());
+    }
+
+    #[test]
+    fn test_missing_entries() {
+        let address: IpAddr = "192.168.1.10".parse().unwrap();
+        let gateway: IpAddr = "192.168.1.1".parse().unwrap();
+
+        let mut state = NetworkState::new();
+        state.add_routing_rule_from(address, 1002);
+        state.add_routing_rule_to(address, 1002);
+        state.add_route(2, 1002, Some(gateway));
+
+        // The "to" rule and the default route were deleted
+        let missing = missing_entries(&state, 1002, &[kernel_rule("192.168.1.10", 1002)], &[]);
+        assert_eq!(missing.len(), 2);
+        assert!(missing.contains(&Missing::Rule(RoutingRule {
+            address,
+            prefix_len: 32,
+            table: 1002,
+            is_from: false,
+            options: Default::default(),
+        })));
+        assert!(missing.contains(&Missing::Route {
+            ifindex: 2,
+            gateway,
+            table: 1002,
+        }));
+
+        // The default route of a failed gateway is withdrawn on purpose
+        state.add_link("eth1".to_string(), 2);
+        state.set_gateway_health("eth1", false);
+        let missing = missing_entries(&state, 1002, &[kernel_rule("192.168.1.10", 1002)], &[]);
+        assert_eq!(missing.len(), 1);
+        state.set_gateway_health("eth1", true);
+
+        // Nothing to do for another table
+        assert!(missing_entries(&state, 1003, &[], &[]).is_empty());
+
+        // The same rule in another table does not count
+        let kernel_rules = [kernel_rule("192.168.1.10", 1003)];
+        let kernel_routes = [default_route("192.168.1.1", 2, 1002)];
+        assert_eq!(missing_entries(&state, 1002, &kernel_rules, &kernel_routes).len(), 2);
+    }
+
+    #[test]
+    fn test_missing_suppress_rule() {
+        let mut state = NetworkState::new();
+        let rule = RoutingRule {
+            address: "192.168.1.10".parse().unwrap(),
+            prefix_len: 32,
+            table: 1002,
+            is_from: true,
+            options: crate::config::RuleOptions {
+                priority: Some(100),
+                suppress_prefixlength: Some(0),
+                ..Default::default()
+            },
+        };
+        state.add_routing_rule(rule.clone());
+
+        let mut lookup = kernel_rule("192.168.1.10", 1002);
+        lookup.priority = 100;
+        let mut suppress = kernel_rule("192.168.1.10", 254);
+        suppress.priority = 99;
+        suppress.suppress_prefixlength = Some(0);
+
+        assert!(missing_entries(&state, 1002, &[lookup.clone(), suppress], &[]).is_empty());
+        assert_eq!(missing_entries(&state, 1002, &[lookup], &[]), vec![Missing::Rule(rule)]);
+    }
+
+    #[test]
+    fn test_missing_mark_rule() {
+        let mut state = NetworkState::new();
+        let rule = MarkRule {
+            interface: "eth1".to_string(),
+            ipv6: false,
+            fwmark: 0x10,
+            fwmask: Some(0xff),
+            priority: Some(500),
+            table: 1002,
+        };
+        state.mark_rules.push(rule.clone());
+
+        let mut kernel = kernel_rule("192.168.1.10", 1002);
+        kernel.source = None;
+        kernel.priority = 500;
+        kernel.fwmark = Some(0x10);
+        kernel.fwmask = Some(0xff);
+
+        assert!(missing_entries(&state, 1002, std::slice::from_ref(&kernel), &[]).is_empty());
+        assert!(missing_entries(&state, 1003, &[], &[]).is_empty());
+        let missing = missing_entries(&state, 1002, &[], &[]);
+        assert_eq!(missing, vec![Missing::MarkRule(rule)]);
+        assert_eq!(missing[0].describe(), "rule add fwmark 0x10/0xff lookup 1002 priority 500");
+    }
+
+    #[test]
+    fn test_missing_subnet_route() {
+        let mut state = NetworkState::new();
+        state.add_routing_rule_from("192.168.1.10".parse().unwrap(), 1002);
+        state.add_route(2, 1002, Some("192.168.1.1".parse().unwrap()));
+        state.add_subnet_route(2, 1002, ("192.168.1.0".parse().unwrap(), 24));
+
+        let kernel_rules = [kernel_rule("192.168.1.10", 1002)];
+        let mut kernel_routes = vec![default_route("192.168.1.1", 2, 1002)];
+        assert_eq!(
+            missing_entries(&state, 1002, &kernel_rules, &kernel_routes),
+            vec![Missing::Subnet {
+                ifindex: 2,
+                subnet: ("192.168.1.0".parse().unwrap(), 24),
+                table: 1002,
+            }]
+        );
+
+        kernel_routes.push(KernelRoute {
+            destination: "192.168.1.0/24".to_string(),
+            gateway: None,
+            ..default_route("192.168.1.1", 2, 1002)
+        });
+        assert!(missing_entries(&state, 1002, &kernel_rules, &kernel_routes).is_empty());
+    }
+
+    #[test]
+    fn test_route_without_rules_not_repaired() {
+        let mut state = NetworkState::new();
+        state.add_route(2, 1002, Some("192.168.1.1".parse().unwrap()));
+
+        assert!(missing_entries(&state, 1002, &[], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_describe() {
+        let rule = Missing::Rule(RoutingRule {
+            address: "2001:db8::10".parse().unwrap(),
+            prefix_len: 128,
+            table: 1002,
+            is_from: true,
+            options: Default::default(),
+        });
+        assert_eq!(rule.describe(), "-6 rule add from 2001:db8::10 lookup 1002");
+
+        let route = Missing::Route {
+            ifindex: 2,
+            gateway: "192.168.1.1".parse().unwrap(),
+            table: 1002,
+        };
+        assert_eq!(route.describe(), "route add default via 192.168.1.1 table 1002");
+
+        let subnet = Missing::Subnet {
+            ifindex: 2,
+            subnet: ("2001:db8::".parse().unwrap(), 64),
+            table: 1002,
+        };
+        assert_eq!(subnet.describe(), "-6 route add 2001:db8::/64 scope link table 1002");
+    }
+}
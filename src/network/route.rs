@@ -4,46 +4,90 @@
 
 use anyhow::{Context, Result};
 use futures::stream::TryStreamExt;
-use rtnetlink::packet_route::route::{RouteAddress, RouteAttribute, RouteMessage};
+use rtnetlink::packet_route::route::{
+    RouteAddress, RouteAttribute, RouteHeader, RouteMessage, RouteScope, RouteType,
+};
 use rtnetlink::packet_route::AddressFamily;
-use rtnetlink::{Handle, RouteMessageBuilder};
+use rtnetlink::{Handle, IpVersion, RouteMessageBuilder};
 use std::net::IpAddr;
 use tracing::{debug, info, warn};
 
-use super::routing_rule::ROUTE_TABLE_BASE;
+use super::is_not_found;
+
+/// Family-independent summary of a route dumped from the kernel
+#[derive(Debug, Clone)]
+pub struct KernelRoute {
+    pub family: IpVersion,
+    /// Destination in CIDR notation (e.g. "0.0.0.0/0", "2001:db8::/64")
+    pub destination: String,
+    pub gateway: Option<IpAddr>,
+    pub oif: Option<u32>,
+    pub metric: Option<u32>,
+    pub table: u32,
+}
 
 /// Discover the default gateway for a specific interface
 pub async fn discover_gateway(handle: &Handle, ifindex: u32) -> Result<Option<IpAddr>> {
+    find_default_gateway(handle, ifindex, AddressFamily::Inet, false).await
+}
+
+/// Discover the IPv6 default gateway for a specific interface
+///
+/// Router-advertised gateways are usually link-local, which is why the
+/// routes netevd installs always carry the output interface.
+pub async fn discover_ipv6_gateway(handle: &Handle, ifindex: u32) -> Result<Option<IpAddr>> {
+    find_default_gateway(handle, ifindex, AddressFamily::Inet6, false).await
+}
+
+/// Gateway of the interface's default route in the main table only
+///
+/// Unlike [`discover_gateway`], never falls back to another table, so a
+/// deleted main default route is not masked by netevd's own copy of it.
+pub async fn discover_main_gateway(handle: &Handle, ifindex: u32, ipv6: bool) -> Result<Option<IpAddr>> {
+    let family = if ipv6 { AddressFamily::Inet6 } else { AddressFamily::Inet };
+    find_default_gateway(handle, ifindex, family, true).await
+}
+
+/// Gateway of the interface's default route
+///
+/// The dump covers every table, including netevd's own, so the main table
+/// wins; another table's default route is only used if main has none,
+/// unless `main_only` is set. Within a table the kernel lists the route
+/// with the lowest metric first.
+async fn find_default_gateway(
+    handle: &Handle,
+    ifindex: u32,
+    family: AddressFamily,
+    main_only: bool,
+) -> Result<Option<IpAddr>> {
     let mut get_msg = RouteMessage::default();
-    get_msg.header.address_family = AddressFamily::Inet;
+    get_msg.header.address_family = family;
     let mut routes = handle.route().get(get_msg).execute();
 
+    let mut fallback = None;
     while let Some(route) = routes
         .try_next()
         .await
         .context("Failed to get next route")?
     {
-        // Look for default route (0.0.0.0/0) on this interface
+        // Look for default route (0.0.0.0/0 or ::/0) on this interface
         if is_default_route(&route) && route_matches_interface(&route, ifindex) {
             if let Some(gateway) = extract_gateway(&route) {
-                // RouteAddress is a type alias or enum - convert to IpAddr
-                // RouteAddress likely has Inet(Ipv4Addr) or Inet6(Ipv6Addr) variants
-                match gateway {
-                    RouteAddress::Inet(ipv4) => {
-                        debug!("Found gateway {} for interface {}", ipv4, ifindex);
-                        return Ok(Some(IpAddr::V4(ipv4)));
-                    }
-                    RouteAddress::Inet6(ipv6) => {
-                        debug!("Found gateway {} for interface {}", ipv6, ifindex);
-                        return Ok(Some(IpAddr::V6(ipv6)));
-                    }
-                    _ => {}
+                if route_in_table(&route, RouteHeader::RT_TABLE_MAIN.into()) {
+                    debug!("Found gateway {} for interface {}", gateway, ifindex);
+                    return Ok(Some(gateway));
+                }
+                if !main_only {
+                    fallback.get_or_insert(gateway);
                 }
             }
         }
     }
 
-    Ok(None)
+    if let Some(gateway) = fallback {
+        debug!("Found gateway {} for interface {} outside the main table", gateway, ifindex);
+    }
+    Ok(fallback)
 }
 
 /// Add a default route via gateway to a custom routing table
@@ -58,27 +102,11 @@ pub async fn add_route(
         ifindex, gateway, table
     );
 
-    // Convert gateway to the proper type
-    let result = match gateway {
-        IpAddr::V4(gw_v4) => {
-            let route_msg = RouteMessageBuilder::<std::net::Ipv4Addr>::new()
-                .destination_prefix(std::net::Ipv4Addr::new(0, 0, 0, 0), 0)
-                .gateway(gw_v4)
-                .output_interface(ifindex)
-                .table_id(table)
-                .build();
-            handle.route().add(route_msg).execute().await
-        }
-        IpAddr::V6(gw_v6) => {
-            let route_msg = RouteMessageBuilder::<std::net::Ipv6Addr>::new()
-                .destination_prefix(std::net::Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0), 0)
-                .gateway(gw_v6)
-                .output_interface(ifindex)
-                .table_id(table)
-                .build();
-            handle.route().add(route_msg).execute().await
-        }
-    };
+    let result = handle
+        .route()
+        .add(default_route_message(ifindex, gateway, table))
+        .execute()
+        .await;
 
     match result {
         Ok(()) => {
@@ -97,11 +125,180 @@ pub async fn add_route(
     Ok(())
 }
 
-/// Remove a route from a custom routing table (both IPv4 and IPv6)
+/// Point the default route of a custom routing table at a new gateway
+///
+/// The route is replaced in place, so the table never lacks a default
+/// route. It is created if it does not exist.
+pub async fn replace_route(
+    handle: &Handle,
+    ifindex: u32,
+    gateway: IpAddr,
+    table: u32,
+) -> Result<()> {
+    info!(
+        "Replacing default route: ifindex={}, gateway={}, table={}",
+        ifindex, gateway, table
+    );
+
+    handle
+        .route()
+        .add(default_route_message(ifindex, gateway, table))
+        .replace()
+        .execute()
+        .await
+        .with_context(|| {
+            format!("Failed to replace route for interface {} via {} in table {}", ifindex, gateway, table)
+        })
+}
+
+/// Remove the default route via `gateway` from a custom routing table
+///
+/// A route that is already gone counts as removed.
+pub async fn remove_default_route(
+    handle: &Handle,
+    ifindex: u32,
+    gateway: IpAddr,
+    table: u32,
+) -> Result<()> {
+    info!(
+        "Removing default route: ifindex={}, gateway={}, table={}",
+        ifindex, gateway, table
+    );
+
+    match handle.route().del(default_route_message(ifindex, gateway, table)).execute().await {
+        Err(e) if is_not_found(&e) => Ok(()),
+        result => result.with_context(|| {
+            format!("Failed to remove route for interface {} via {} from table {}", ifindex, gateway, table)
+        }),
+    }
+}
+
+fn default_route_message(ifindex: u32, gateway: IpAddr, table: u32) -> RouteMessage {
+    match gateway {
+        IpAddr::V4(gw_v4) => RouteMessageBuilder::<std::net::Ipv4Addr>::new()
+            .destination_prefix(std::net::Ipv4Addr::UNSPECIFIED, 0)
+            .gateway(gw_v4)
+            .output_interface(ifindex)
+            .table_id(table)
+            .build(),
+        IpAddr::V6(gw_v6) => RouteMessageBuilder::<std::net::Ipv6Addr>::new()
+            .destination_prefix(std::net::Ipv6Addr::UNSPECIFIED, 0)
+            .gateway(gw_v6)
+            .output_interface(ifindex)
+            .table_id(table)
+            .build(),
+    }
+}
+
+/// Add the connected route of a subnet to a custom routing table
+///
+/// Without it, traffic to the interface's own subnet that is looked up in
+/// the table would go via the gateway.
+pub async fn add_subnet_route(
+    handle: &Handle,
+    ifindex: u32,
+    subnet: (IpAddr, u8),
+    table: u32,
+) -> Result<()> {
+    let (network, prefix_len) = subnet;
+    info!(
+        "Adding subnet route: {}/{}, ifindex={}, table={}",
+        network, prefix_len, ifindex, table
+    );
+
+    let route_msg = match network {
+        IpAddr::V4(network) => RouteMessageBuilder::<std::net::Ipv4Addr>::new()
+            .destination_prefix(network, prefix_len)
+            .output_interface(ifindex)
+            .scope(RouteScope::Link)
+            .table_id(table)
+            .build(),
+        IpAddr::V6(network) => RouteMessageBuilder::<std::net::Ipv6Addr>::new()
+            .destination_prefix(network, prefix_len)
+            .output_interface(ifindex)
+            .scope(RouteScope::Link)
+            .table_id(table)
+            .build(),
+    };
+
+    match handle.route().add(route_msg).execute().await {
+        Ok(()) => {
+            info!("Successfully added subnet route in table {}", table);
+        }
+        Err(ref e) if e.to_string().contains("File exists") => {
+            debug!("Subnet route already exists in table {}, skipping", table);
+        }
+        Err(e) => {
+            return Err(e).with_context(|| {
+                format!(
+                    "Failed to add route to {}/{} for interface {} in table {}",
+                    network, prefix_len, ifindex, table
+                )
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove the connected route of a subnet from a custom routing table
+pub async fn remove_subnet_route(
+    handle: &Handle,
+    ifindex: u32,
+    subnet: (IpAddr, u8),
+    table: u32,
+) -> Result<()> {
+    let (network, prefix_len) = subnet;
+    info!(
+        "Removing subnet route: {}/{}, ifindex={}, table={}",
+        network, prefix_len, ifindex, table
+    );
+
+    let family = match network {
+        IpAddr::V4(_) => IpVersion::V4,
+        IpAddr::V6(_) => IpVersion::V6,
+    };
+    let destination = format!("{}/{}", network, prefix_len);
+    for route in dump_routes(handle, family.clone()).await? {
+        if route_in_table(&route, table)
+            && route_matches_interface(&route, ifindex)
+            && kernel_route_from_message(&route, family.clone()).destination == destination
+        {
+            handle
+                .route()
+                .del(route)
+                .execute()
+                .await
+                .with_context(|| format!("Failed to delete route to {} in table {}", destination, table))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove every route of an interface from a custom routing table (both
+/// IPv4 and IPv6)
 pub async fn remove_route(handle: &Handle, ifindex: u32, table: u32) -> Result<()> {
     info!("Removing routes for ifindex={} in table={}", ifindex, table);
+    remove_routes(handle, ifindex, table, false).await
+}
 
+/// Remove only the default routes of an interface from a table
+///
+/// Used for VRF tables, where the connected and local routes belong to the
+/// kernel.
+pub async fn remove_default_routes(handle: &Handle, ifindex: u32, table: u32) -> Result<()> {
+    info!("Removing default routes for ifindex={} in table={}", ifindex, table);
+    remove_routes(handle, ifindex, table, true).await
+}
+
+async fn remove_routes(handle: &Handle, ifindex: u32, table: u32, defaults_only: bool) -> Result<()> {
     let mut removed = false;
+    let matches = |route: &RouteMessage| {
+        route_in_table(route, table)
+            && route_matches_interface(route, ifindex)
+            && (!defaults_only || is_default_route(route))
+    };
 
     // Remove IPv4 routes
     let mut get_v4 = RouteMessage::default();
@@ -112,7 +309,7 @@ pub async fn remove_route(handle: &Handle, ifindex: u32, table: u32) -> Result<(
         .await
         .context("Failed to get next IPv4 route")?
     {
-        if route_in_table(&route, table) && route_matches_interface(&route, ifindex) {
+        if matches(&route) {
             if let Err(e) = handle.route().del(route).execute().await {
                 warn!("Failed to delete IPv4 route: {}", e);
             } else {
@@ -131,7 +328,7 @@ pub async fn remove_route(handle: &Handle, ifindex: u32, table: u32) -> Result<(
         .await
         .context("Failed to get next IPv6 route")?
     {
-        if route_in_table(&route, table) && route_matches_interface(&route, ifindex) {
+        if matches(&route) {
             if let Err(e) = handle.route().del(route).execute().await {
                 warn!("Failed to delete IPv6 route: {}", e);
             } else {
@@ -148,9 +345,72 @@ pub async fn remove_route(handle: &Handle, ifindex: u32, table: u32) -> Result<(
     Ok(())
 }
 
-/// Calculate the custom routing table number for an interface
-pub fn calculate_table_id(ifindex: u32) -> u32 {
-    ROUTE_TABLE_BASE + ifindex
+/// Dump routes from every routing table, optionally limited to one family
+pub async fn list_routes(handle: &Handle, family: Option<IpVersion>) -> Result<Vec<KernelRoute>> {
+    let families = match family {
+        Some(family) => vec![family],
+        None => vec![IpVersion::V4, IpVersion::V6],
+    };
+
+    let mut result = Vec::new();
+    for family in families {
+        for route in dump_routes(handle, family.clone()).await? {
+            result.push(kernel_route_from_message(&route, family.clone()));
+        }
+    }
+
+    Ok(result)
+}
+
+/// Dump the raw route messages of one address family, from every table
+pub(crate) async fn dump_routes(handle: &Handle, family: IpVersion) -> Result<Vec<RouteMessage>> {
+    let mut get_msg = RouteMessage::default();
+    get_msg.header.address_family = match family {
+        IpVersion::V4 => AddressFamily::Inet,
+        IpVersion::V6 => AddressFamily::Inet6,
+    };
+
+    handle
+        .route()
+        .get(get_msg)
+        .execute()
+        .try_collect()
+        .await
+        .context("Failed to get next route")
+}
+
+pub(crate) fn kernel_route_from_message(route: &RouteMessage, family: IpVersion) -> KernelRoute {
+    let prefix_len = route.header.destination_prefix_length;
+    let destination = route
+        .attributes
+        .iter()
+        .find_map(|attr| match attr {
+            RouteAttribute::Destination(addr) => route_address_to_ip(addr),
+            _ => None,
+        })
+        .unwrap_or(match family {
+            IpVersion::V4 => IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED),
+            IpVersion::V6 => IpAddr::V6(std::net::Ipv6Addr::UNSPECIFIED),
+        });
+
+    let mut oif = None;
+    let mut metric = None;
+    for attr in &route.attributes {
+        match attr {
+            RouteAttribute::Oif(idx) => oif = Some(*idx),
+            RouteAttribute::Priority(prio) => metric = Some(*prio),
+            _ => {}
+        }
+    }
+
+    KernelRoute {
+        family,
+        destination: format!("{}/{}", destination, prefix_len),
+        gateway: extract_gateway(route),
+        oif,
+        metric,
+        table: route_table(route),
+    }
 }
 
 /// Check if a route is a default route (0.0.0.0/0)
@@ -159,6 +419,14 @@ fn is_default_route(route: &RouteMessage) -> bool {
     route.header.destination_prefix_length == 0
 }
 
+/// Whether a route is a unicast default route in the main table, the one
+/// DHCP clients and router advertisements maintain
+pub(crate) fn is_main_default_route(route: &RouteMessage) -> bool {
+    is_default_route(route)
+        && route.header.kind == RouteType::Unicast
+        && route_in_table(route, RouteHeader::RT_TABLE_MAIN.into())
+}
+
 /// Check if a route matches a specific interface
 fn route_matches_interface(route: &RouteMessage, ifindex: u32) -> bool {
     route.attributes.iter().any(|attr| {
@@ -171,27 +439,37 @@ fn route_matches_interface(route: &RouteMessage, ifindex: u32) -> bool {
 
 /// Check if a route is in a specific table
 fn route_in_table(route: &RouteMessage, table: u32) -> bool {
-    // Check the table attribute
-    if let Some(RouteAttribute::Table(t)) = route
+    route_table(route) == table
+}
+
+/// Table a route lives in
+pub(crate) fn route_table(route: &RouteMessage) -> u32 {
+    // The table attribute carries the full 32-bit ID; the header field
+    // only fits table IDs below 256
+    route
         .attributes
         .iter()
-        .find(|a| matches!(a, RouteAttribute::Table(_)))
-    {
-        return *t == table;
-    }
+        .find_map(|attr| match attr {
+            RouteAttribute::Table(t) => Some(*t),
+            _ => None,
+        })
+        .unwrap_or(route.header.table as u32)
+}
 
-    // Also check the header table field for small table IDs
-    route.header.table as u32 == table
+/// Extract the gateway of a route
+fn extract_gateway(route: &RouteMessage) -> Option<IpAddr> {
+    route.attributes.iter().find_map(|attr| match attr {
+        RouteAttribute::Gateway(gw_addr) => route_address_to_ip(gw_addr),
+        _ => None,
+    })
 }
 
-/// Extract gateway RouteAddress from a route
-fn extract_gateway(route: &RouteMessage) -> Option<RouteAddress> {
-    for attr in &route.attributes {
-        if let RouteAttribute::Gateway(gw_addr) = attr {
-            return Some(gw_addr.clone());
-        }
+fn route_address_to_ip(addr: &RouteAddress) -> Option<IpAddr> {
+    match addr {
+        RouteAddress::Inet(ipv4) => Some(IpAddr::V4(*ipv4)),
+        RouteAddress::Inet6(ipv6) => Some(IpAddr::V6(*ipv6)),
+        _ => None,
     }
-    None
 }
 
 #[cfg(test)]
@@ -199,8 +477,36 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_calculate_table_id() {
-        assert_eq!(calculate_table_id(2), ROUTE_TABLE_BASE + 2);
-        assert_eq!(calculate_table_id(10), ROUTE_TABLE_BASE + 10);
+    fn test_kernel_route_from_message() {
+        let msg = RouteMessageBuilder::<std::net::Ipv4Addr>::new()
+            .destination_prefix(std::net::Ipv4Addr::new(0, 0, 0, 0), 0)
+            .gateway(std::net::Ipv4Addr::new(192, 168, 1, 1))
+            .output_interface(3)
+            .table_id(1003)
+            .build();
+
+        let route = kernel_route_from_message(&msg, IpVersion::V4);
+        assert_eq!(route.destination, "0.0.0.0/0");
+        assert_eq!(route.gateway, Some("192.168.1.1".parse().unwrap()));
+        assert_eq!(route.oif, Some(3));
+        assert_eq!(route.table, 1003);
+        assert!(!is_main_default_route(&msg));
+    }
+
+    #[test]
+    fn test_is_main_default_route() {
+        let default = default_route_message(3, "fe80::1".parse().unwrap(), 254);
+        assert!(is_main_default_route(&default));
+
+        let mut unreachable = default.clone();
+        unreachable.header.kind = RouteType::Unreachable;
+        assert!(!is_main_default_route(&unreachable));
+
+        let subnet = RouteMessageBuilder::<std::net::Ipv4Addr>::new()
+            .destination_prefix(std::net::Ipv4Addr::new(192, 168, 1, 0), 24)
+            .output_interface(3)
+            .table_id(254)
+            .build();
+        assert!(!is_main_default_route(&subnet));
     }
 }
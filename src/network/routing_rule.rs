@@ -4,60 +4,61 @@
 
 use anyhow::{Context, Result};
 use futures::stream::TryStreamExt;
+use rtnetlink::packet_route::route::RouteHeader;
 use rtnetlink::packet_route::rule::{RuleAction, RuleAttribute, RuleMessage};
-use rtnetlink::Handle;
+use rtnetlink::packet_route::AddressFamily;
+use rtnetlink::{Handle, IpVersion};
 use std::net::IpAddr;
 use tracing::{debug, info, warn};
 
-/// Base table number for custom routing tables
+use super::address::host_prefix_len;
+use super::mark::MarkRule;
+use super::{is_not_found, RoutingRule};
+use crate::config::RuleOptions;
+
+/// Default first table for per-interface routing tables
 /// Must be above 255 to avoid conflicts with reserved system tables
 /// (0=unspec, 253=default, 254=main, 255=local)
 pub const ROUTE_TABLE_BASE: u32 = 1000;
 
+/// Default number of tables from [`ROUTE_TABLE_BASE`] that netevd hands
+/// out and considers its own
+pub const ROUTE_TABLE_RANGE: u32 = 1000;
+
+/// Family-independent summary of a policy rule dumped from the kernel
+#[derive(Debug, Clone)]
+pub struct KernelRule {
+    pub family: IpVersion,
+    pub priority: u32,
+    pub source: Option<(IpAddr, u8)>,
+    pub destination: Option<(IpAddr, u8)>,
+    pub fwmark: Option<u32>,
+    pub fwmask: Option<u32>,
+    pub iif: Option<String>,
+    pub oif: Option<String>,
+    pub suppress_prefixlength: Option<u32>,
+    pub table: u32,
+    /// `ip rule` action name ("lookup", "goto", "blackhole", ...)
+    pub action: String,
+}
+
 /// Add a routing policy rule (from address -> table)
 pub async fn add_routing_rule_from(
     handle: &Handle,
     address: IpAddr,
     table: u32,
 ) -> Result<()> {
-    info!(
-        "Adding routing rule: from {} table {}",
-        address, table
-    );
-
-    match address {
-        IpAddr::V4(ipv4) => {
-            handle
-                .rule()
-                .add()
-                .v4()
-                .source_prefix(ipv4, 32)
-                .table_id(table)
-                .action(RuleAction::ToTable)
-                .execute()
-                .await
-                .with_context(|| {
-                    format!("Failed to add IPv4 routing rule: from {} table {}", address, table)
-                })?;
-        }
-        IpAddr::V6(ipv6) => {
-            handle
-                .rule()
-                .add()
-                .v6()
-                .source_prefix(ipv6, 128)
-                .table_id(table)
-                .action(RuleAction::ToTable)
-                .execute()
-                .await
-                .with_context(|| {
-                    format!("Failed to add IPv6 routing rule: from {} table {}", address, table)
-                })?;
-        }
-    }
-
-    info!("Successfully added 'from' routing rule");
-    Ok(())
+    add_routing_rule(
+        handle,
+        &RoutingRule {
+            address,
+            prefix_len: host_prefix_len(&address),
+            table,
+            is_from: true,
+            options: RuleOptions::default(),
+        },
+    )
+    .await
 }
 
 /// Add a routing policy rule (to address -> table)
@@ -66,43 +67,207 @@ pub async fn add_routing_rule_to(
     address: IpAddr,
     table: u32,
 ) -> Result<()> {
+    add_routing_rule(
+        handle,
+        &RoutingRule {
+            address,
+            prefix_len: host_prefix_len(&address),
+            table,
+            is_from: false,
+            options: RuleOptions::default(),
+        },
+    )
+    .await
+}
+
+/// Add a routing policy rule with its configured priority and matches
+///
+/// With `suppress_prefixlength`, the main table lookup that goes in front
+/// of the rule is added first, and removed again if the rule cannot be
+/// added. A rule that is already present counts as added, so a partial
+/// repair can simply add both again.
+pub async fn add_routing_rule(handle: &Handle, rule: &RoutingRule) -> Result<()> {
+    let suppress = rule.describe_suppress();
+    if let Some(description) = &suppress {
+        info!("Adding routing rule: {}", description);
+        execute_add(handle, suppress_message(rule))
+            .await
+            .with_context(|| format!("Failed to add routing rule: {}", description))?;
+    }
+
+    let description = rule.describe();
+    info!("Adding routing rule: {}", description);
+    if let Err(e) = execute_add(handle, rule_message(rule)).await {
+        if let Some(suppress) = suppress {
+            match handle.rule().del(suppress_message(rule)).execute().await {
+                Err(e) if !is_not_found(&e) => warn!("Failed to remove routing rule: {}: {}", suppress, e),
+                _ => {}
+            }
+        }
+        return Err(e).with_context(|| format!("Failed to add routing rule: {}", description));
+    }
+
     info!(
-        "Adding routing rule: to {} table {}",
-        address, table
+        "Successfully added '{}' routing rule",
+        if rule.is_from { "from" } else { "to" }
     );
+    Ok(())
+}
+
+/// Add a firewall mark rule
+///
+/// A rule that is already present counts as added.
+pub async fn add_mark_rule(handle: &Handle, rule: &MarkRule) -> Result<()> {
+    let description = rule.describe();
+    info!("Adding routing rule: {}", description);
+    execute_add(handle, mark_message(rule))
+        .await
+        .with_context(|| format!("Failed to add routing rule: {}", description))
+}
+
+/// Remove a firewall mark rule; a rule that is already gone counts as removed
+pub async fn remove_mark_rule(handle: &Handle, rule: &MarkRule) -> Result<()> {
+    let description = rule.describe();
+    info!("Removing routing rule: {}", description);
+    match handle.rule().del(mark_message(rule)).execute().await {
+        Err(e) if is_not_found(&e) => {
+            debug!("Routing rule already gone: {}", description);
+            Ok(())
+        }
+        result => result.with_context(|| format!("Failed to remove routing rule: {}", description)),
+    }
+}
+
+async fn execute_add(handle: &Handle, message: RuleMessage) -> Result<(), rtnetlink::Error> {
+    let mut request = handle.rule().add();
+    *request.message_mut() = message;
+    match request.execute().await {
+        Err(rtnetlink::Error::NetlinkError(e)) if e.raw_code() == -libc::EEXIST => {
+            debug!("Routing rule already present");
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Netlink message for a tracked rule
+fn rule_message(rule: &RoutingRule) -> RuleMessage {
+    let mut message = selector_message(rule);
+    message.header.action = RuleAction::ToTable;
+    if let Some(priority) = rule.options.priority {
+        message.attributes.push(RuleAttribute::Priority(priority));
+    }
+    set_table(&mut message, rule.table);
+    message
+}
+
+/// Netlink message for the main table lookup in front of a tracked rule
+fn suppress_message(rule: &RoutingRule) -> RuleMessage {
+    let mut message = selector_message(rule);
+    message.header.action = RuleAction::ToTable;
+    if let Some(priority) = rule.options.priority {
+        message.attributes.push(RuleAttribute::Priority(priority - 1));
+    }
+    if let Some(length) = rule.options.suppress_prefixlength {
+        message.attributes.push(RuleAttribute::SuppressPrefixLen(length.into()));
+    }
+    set_table(&mut message, RouteHeader::RT_TABLE_MAIN.into());
+    message
+}
+
+/// Address selector plus the interface and mark matches of a rule
+fn selector_message(rule: &RoutingRule) -> RuleMessage {
+    let mut message = RuleMessage::default();
+    message.header.family = match rule.address {
+        IpAddr::V4(_) => AddressFamily::Inet,
+        IpAddr::V6(_) => AddressFamily::Inet6,
+    };
+
+    if rule.is_from {
+        message.header.src_len = rule.prefix_len;
+        message.attributes.push(RuleAttribute::Source(rule.address));
+    } else {
+        message.header.dst_len = rule.prefix_len;
+        message.attributes.push(RuleAttribute::Destination(rule.address));
+    }
+
+    let options = &rule.options;
+    if let Some(iif) = &options.iif {
+        message.attributes.push(RuleAttribute::Iifname(iif.clone()));
+    }
+    if let Some(oif) = &options.oif {
+        message.attributes.push(RuleAttribute::Oifname(oif.clone()));
+    }
+    if let Some(mark) = options.fwmark {
+        message.attributes.push(RuleAttribute::FwMark(mark));
+    }
+    if let Some(mask) = options.fwmask {
+        message.attributes.push(RuleAttribute::FwMask(mask));
+    }
+
+    message
+}
+
+/// Netlink message for a firewall mark rule
+fn mark_message(rule: &MarkRule) -> RuleMessage {
+    let mut message = RuleMessage::default();
+    message.header.family = if rule.ipv6 {
+        AddressFamily::Inet6
+    } else {
+        AddressFamily::Inet
+    };
+    message.header.action = RuleAction::ToTable;
+    message.attributes.push(RuleAttribute::FwMark(rule.fwmark));
+    if let Some(mask) = rule.fwmask {
+        message.attributes.push(RuleAttribute::FwMask(mask));
+    }
+    if let Some(priority) = rule.priority {
+        message.attributes.push(RuleAttribute::Priority(priority));
+    }
+    set_table(&mut message, rule.table);
+    message
+}
+
+fn set_table(message: &mut RuleMessage, table: u32) {
+    // Tables above 255 only fit in the FRA_TABLE attribute
+    match u8::try_from(table) {
+        Ok(table) => message.header.table = table,
+        Err(_) => {
+            message.header.table = RouteHeader::RT_TABLE_UNSPEC;
+            message.attributes.push(RuleAttribute::Table(table));
+        }
+    }
+}
 
-    match address {
-        IpAddr::V4(ipv4) => {
-            handle
-                .rule()
-                .add()
-                .v4()
-                .destination_prefix(ipv4, 32)
-                .table_id(table)
-                .action(RuleAction::ToTable)
-                .execute()
-                .await
-                .with_context(|| {
-                    format!("Failed to add IPv4 routing rule: to {} table {}", address, table)
-                })?;
+/// Remove a tracked routing rule, and the `suppress_prefixlength` lookup
+/// installed in front of it
+///
+/// Only kernel rules with the tracked selector, priority and matches are
+/// deleted, so rules that others installed for the same address stay. A
+/// rule that is already gone counts as removed.
+pub async fn remove_routing_rule(handle: &Handle, rule: &RoutingRule) -> Result<()> {
+    let description = rule.describe();
+    info!("Removing routing rule: {}", description);
+
+    let family = if rule.address.is_ipv6() {
+        IpVersion::V6
+    } else {
+        IpVersion::V4
+    };
+    for message in dump_rules(handle, family.clone()).await? {
+        let kernel = kernel_rule_from_message(&message, family.clone());
+        if !tracked_rule_matches(rule, &kernel) && !suppress_rule_matches(rule, &kernel) {
+            continue;
         }
-        IpAddr::V6(ipv6) => {
-            handle
-                .rule()
-                .add()
-                .v6()
-                .destination_prefix(ipv6, 128)
-                .table_id(table)
-                .action(RuleAction::ToTable)
-                .execute()
-                .await
-                .with_context(|| {
-                    format!("Failed to add IPv6 routing rule: to {} table {}", address, table)
-                })?;
+        match handle.rule().del(message).execute().await {
+            Ok(()) => {}
+            Err(e) if is_not_found(&e) => debug!("Routing rule already gone: {}", description),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to remove routing rule: {}", description))
+            }
         }
     }
 
-    info!("Successfully added 'to' routing rule");
     Ok(())
 }
 
@@ -148,18 +313,168 @@ pub async fn remove_routing_rules(
     Ok(())
 }
 
-/// Check if a rule matches the given address and table
-fn rule_matches(rule: &RuleMessage, address: &IpAddr, table: u32) -> bool {
-    // Check if the rule's table matches
-    let rule_table = rule.attributes.iter().find_map(|attr| {
-        if let RuleAttribute::Table(t) = attr {
-            Some(*t)
-        } else {
-            None
+/// Dump the policy rules of one or both address families
+pub async fn list_rules(handle: &Handle, family: Option<IpVersion>) -> Result<Vec<KernelRule>> {
+    let families = match family {
+        Some(family) => vec![family],
+        None => vec![IpVersion::V4, IpVersion::V6],
+    };
+
+    let mut result = Vec::new();
+    for family in families {
+        for rule in dump_rules(handle, family.clone()).await? {
+            result.push(kernel_rule_from_message(&rule, family.clone()));
         }
-    });
+    }
+
+    Ok(result)
+}
+
+/// Dump the raw policy rule messages of one address family
+pub(crate) async fn dump_rules(handle: &Handle, family: IpVersion) -> Result<Vec<RuleMessage>> {
+    handle
+        .rule()
+        .get(family)
+        .execute()
+        .try_collect()
+        .await
+        .context("Failed to get next rule")
+}
+
+pub(crate) fn kernel_rule_from_message(rule: &RuleMessage, family: IpVersion) -> KernelRule {
+    let mut kernel_rule = KernelRule {
+        family,
+        // The kernel omits FRA_PRIORITY for the priority 0 (local) rule
+        priority: 0,
+        source: None,
+        destination: None,
+        fwmark: None,
+        fwmask: None,
+        iif: None,
+        oif: None,
+        suppress_prefixlength: None,
+        table: rule.header.table as u32,
+        action: rule_action_name(&rule.header.action),
+    };
+
+    for attr in &rule.attributes {
+        match attr {
+            RuleAttribute::Priority(prio) => kernel_rule.priority = *prio,
+            RuleAttribute::Source(addr) => kernel_rule.source = Some((*addr, rule.header.src_len)),
+            RuleAttribute::Destination(addr) => {
+                kernel_rule.destination = Some((*addr, rule.header.dst_len))
+            }
+            RuleAttribute::FwMark(mark) => kernel_rule.fwmark = Some(*mark),
+            RuleAttribute::FwMask(mask) => kernel_rule.fwmask = Some(*mask),
+            RuleAttribute::Iifname(name) => kernel_rule.iif = Some(name.clone()),
+            RuleAttribute::Oifname(name) => kernel_rule.oif = Some(name.clone()),
+            // The kernel reports "no suppression" as all ones
+            RuleAttribute::SuppressPrefixLen(len) if *len != u32::MAX => {
+                kernel_rule.suppress_prefixlength = Some(*len)
+            }
+            RuleAttribute::Table(table) => kernel_rule.table = *table,
+            _ => {}
+        }
+    }
+
+    kernel_rule
+}
+
+/// Summary of a rule from a multicast notification, which carries its
+/// family only in the header
+pub(crate) fn kernel_rule_from_notification(rule: &RuleMessage) -> KernelRule {
+    let family = match rule.header.family {
+        AddressFamily::Inet6 => IpVersion::V6,
+        _ => IpVersion::V4,
+    };
+    kernel_rule_from_message(rule, family)
+}
+
+fn rule_action_name(action: &RuleAction) -> String {
+    match action {
+        RuleAction::ToTable => "lookup".to_string(),
+        RuleAction::Goto => "goto".to_string(),
+        RuleAction::Nop => "nop".to_string(),
+        RuleAction::Blackhole => "blackhole".to_string(),
+        RuleAction::Unreachable => "unreachable".to_string(),
+        RuleAction::Prohibit => "prohibit".to_string(),
+        RuleAction::Unspec => "unspec".to_string(),
+        RuleAction::Other(code) => format!("other({})", code),
+        _ => "unknown".to_string(),
+    }
+}
+
+/// Whether a kernel rule is the one netevd installed for a tracked address
+pub fn tracked_rule_matches(tracked: &RoutingRule, kernel: &KernelRule) -> bool {
+    kernel.table == tracked.table
+        && kernel.suppress_prefixlength.is_none()
+        && kernel_shape_matches(tracked, kernel, tracked.options.priority)
+}
+
+/// Whether a kernel rule is the `suppress_prefixlength` main table lookup
+/// in front of a tracked rule
+pub fn suppress_rule_matches(tracked: &RoutingRule, kernel: &KernelRule) -> bool {
+    kernel.table == u32::from(RouteHeader::RT_TABLE_MAIN)
+        && kernel.suppress_prefixlength.is_some()
+        && kernel.suppress_prefixlength == tracked.options.suppress_prefixlength.map(u32::from)
+        && kernel_shape_matches(
+            tracked,
+            kernel,
+            tracked.options.priority.map(|priority| priority - 1),
+        )
+}
+
+/// Whether a kernel rule is a firewall mark rule netevd installed
+pub fn mark_rule_matches(tracked: &MarkRule, kernel: &KernelRule) -> bool {
+    let family = if tracked.ipv6 { IpVersion::V6 } else { IpVersion::V4 };
+    // As for the per-address rules, no mask means all ones
+    kernel.family == family
+        && kernel.table == tracked.table
+        && kernel.action == "lookup"
+        && kernel.source.is_none()
+        && kernel.destination.is_none()
+        && kernel.iif.is_none()
+        && kernel.oif.is_none()
+        && kernel.suppress_prefixlength.is_none()
+        && kernel.fwmark == Some(tracked.fwmark)
+        && kernel.fwmask == Some(tracked.fwmask.unwrap_or(u32::MAX))
+        && tracked.priority.is_none_or(|priority| kernel.priority == priority)
+}
+
+fn kernel_shape_matches(tracked: &RoutingRule, kernel: &KernelRule, priority: Option<u32>) -> bool {
+    let (selector, other) = if tracked.is_from {
+        (kernel.source, kernel.destination)
+    } else {
+        (kernel.destination, kernel.source)
+    };
+    let options = &tracked.options;
+    // The kernel reports a mark without a mask as an all-ones mask
+    let fwmask = options.fwmask.or(options.fwmark.map(|_| u32::MAX));
+
+    selector == Some((tracked.address, tracked.prefix_len))
+        && other.is_none()
+        && priority.is_none_or(|priority| kernel.priority == priority)
+        && kernel.iif == options.iif
+        && kernel.oif == options.oif
+        && kernel.fwmark == options.fwmark
+        && (options.fwmark.is_none() || kernel.fwmask == fwmask)
+}
+
+/// Table a rule points at
+pub(crate) fn rule_table(rule: &RuleMessage) -> u32 {
+    // Like routes, tables above 255 only fit in the FRA_TABLE attribute
+    rule.attributes
+        .iter()
+        .find_map(|attr| match attr {
+            RuleAttribute::Table(t) => Some(*t),
+            _ => None,
+        })
+        .unwrap_or(rule.header.table as u32)
+}
 
-    if rule_table != Some(table) && rule.header.table as u32 != table {
+/// Check if a rule matches the given address and table
+fn rule_matches(rule: &RuleMessage, address: &IpAddr, table: u32) -> bool {
+    if rule_table(rule) != table {
         return false;
     }
 
@@ -177,7 +492,138 @@ mod tests {
 
     #[test]
     fn test_route_table_base() {
-        assert!(ROUTE_TABLE_BASE > 255, "table base must be above reserved range");
+        const { assert!(ROUTE_TABLE_BASE > 255, "table base must be above reserved range") };
+    }
+
+    #[test]
+    fn test_kernel_rule_from_message() {
+        let mut msg = RuleMessage::default();
+        msg.header.src_len = 32;
+        msg.header.action = RuleAction::ToTable;
+        msg.attributes = vec![
+            RuleAttribute::Priority(100),
+            RuleAttribute::Source("192.168.1.10".parse().unwrap()),
+            RuleAttribute::Table(1002),
+        ];
+
+        let rule = kernel_rule_from_message(&msg, IpVersion::V4);
+        assert_eq!(rule.priority, 100);
+        assert_eq!(rule.source, Some(("192.168.1.10".parse().unwrap(), 32)));
+        assert_eq!(rule.table, 1002);
+        assert_eq!(rule.action, "lookup");
+        assert!(rule.destination.is_none());
+    }
+
+    #[test]
+    fn test_rule_messages() {
+        let rule = RoutingRule {
+            address: "2001:db8::".parse().unwrap(),
+            prefix_len: 64,
+            table: 1002,
+            is_from: false,
+            options: RuleOptions {
+                priority: Some(100),
+                oif: Some("eth1".to_string()),
+                fwmark: Some(0x10),
+                suppress_prefixlength: Some(0),
+                ..RuleOptions::default()
+            },
+        };
+
+        let lookup = rule_message(&rule);
+        assert_eq!(lookup.header.family, AddressFamily::Inet6);
+        assert_eq!(lookup.header.dst_len, 64);
+        assert_eq!(rule_table(&lookup), 1002);
+        assert!(lookup.attributes.contains(&RuleAttribute::Priority(100)));
+        assert!(lookup.attributes.contains(&RuleAttribute::Oifname("eth1".to_string())));
+        assert!(lookup.attributes.contains(&RuleAttribute::FwMark(0x10)));
+        assert!(!lookup.attributes.iter().any(|attr| matches!(attr, RuleAttribute::SuppressPrefixLen(_))));
+
+        let suppress = suppress_message(&rule);
+        assert_eq!(rule_table(&suppress), 254);
+        assert!(suppress.attributes.contains(&RuleAttribute::Priority(99)));
+        assert!(suppress.attributes.contains(&RuleAttribute::SuppressPrefixLen(0)));
+
+        // Both are recognised when dumped back, with the mask the kernel
+        // fills in for a mark
+        let mut kernel = kernel_rule_from_notification(&suppress);
+        kernel.fwmask = Some(u32::MAX);
+        assert!(suppress_rule_matches(&rule, &kernel));
+        assert!(!tracked_rule_matches(&rule, &kernel));
+        let mut kernel = kernel_rule_from_notification(&lookup);
+        kernel.fwmask = Some(u32::MAX);
+        assert!(tracked_rule_matches(&rule, &kernel));
+        assert!(rule_matches(&lookup, &rule.address, 1002));
+        assert!(!rule_matches(&suppress, &rule.address, 1002));
+    }
+
+    #[test]
+    fn test_suppress_rule_of_others_kept() {
+        let rule = RoutingRule {
+            address: "10.8.0.2".parse().unwrap(),
+            prefix_len: 32,
+            table: 1002,
+            is_from: true,
+            options: RuleOptions {
+                priority: Some(2000),
+                suppress_prefixlength: Some(0),
+                ..RuleOptions::default()
+            },
+        };
+        let kernel = kernel_rule_from_notification(&suppress_message(&rule));
+        assert!(suppress_rule_matches(&rule, &kernel));
+
+        // The same lookup for the address at another priority, or with
+        // another prefix length, is someone else's
+        let mut other = kernel.clone();
+        other.priority = 500;
+        assert!(!suppress_rule_matches(&rule, &other));
+        let mut other = kernel.clone();
+        other.suppress_prefixlength = Some(8);
+        assert!(!suppress_rule_matches(&rule, &other));
+        let mut other = kernel;
+        other.source = Some(("10.8.0.0".parse().unwrap(), 24));
+        assert!(!suppress_rule_matches(&rule, &other));
+    }
+
+    #[test]
+    fn test_mark_rule_message() {
+        let rule = MarkRule {
+            interface: "wg0".to_string(),
+            ipv6: true,
+            fwmark: 0x100,
+            fwmask: Some(0xff00),
+            priority: Some(500),
+            table: 1001,
+        };
+
+        let message = mark_message(&rule);
+        assert_eq!(message.header.family, AddressFamily::Inet6);
+        assert_eq!(message.header.src_len, 0);
+        assert_eq!(rule_table(&message), 1001);
+        assert!(message.attributes.contains(&RuleAttribute::FwMark(0x100)));
+        assert!(message.attributes.contains(&RuleAttribute::FwMask(0xff00)));
+        assert!(message.attributes.contains(&RuleAttribute::Priority(500)));
+
+        let mut kernel = kernel_rule_from_notification(&message);
+        assert!(mark_rule_matches(&rule, &kernel));
+        kernel.family = IpVersion::V4;
+        assert!(!mark_rule_matches(&rule, &kernel));
+
+        // Without a mask the kernel reports all ones
+        let rule = MarkRule {
+            ipv6: false,
+            fwmask: None,
+            priority: None,
+            ..rule
+        };
+        let mut kernel = kernel_rule_from_notification(&mark_message(&rule));
+        assert_eq!(kernel.fwmask, None);
+        kernel.fwmask = Some(u32::MAX);
+        kernel.priority = 32765;
+        assert!(mark_rule_matches(&rule, &kernel));
+        kernel.source = Some(("192.168.1.10".parse().unwrap(), 32));
+        assert!(!mark_rule_matches(&rule, &kernel));
     }
 
     #[test]
@@ -0,0 +1,265 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+
+//! Allocation of per-interface routing tables
+//!
+//! Tables are keyed by interface name, so an interface keeps its table
+//! when it is recreated with a new ifindex. Explicit IDs from
+//! `routing.tables.interfaces` win; every other policy interface gets the
+//! lowest free table in `base..base + range`. With `routing.tables.rt_tables`
+//! the mapping is also kept in an iproute2 `rt_tables.d` file, which makes
+//! `ip rule` show names and keeps assignments across restarts.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use crate::config::{RoutingConfig, TablesConfig};
+
+/// Where the name→table mapping is written for iproute2
+pub const RT_TABLES_PATH: &str = "/etc/iproute2/rt_tables.d/netevd.conf";
+
+/// Prefix of the table names netevd writes, e.g. `netevd-eth1`
+const TABLE_NAME_PREFIX: &str = "netevd-";
+
+/// Assigns routing tables to policy interfaces
+#[derive(Debug, Clone)]
+pub struct TableAllocator {
+    base: u32,
+    range: u32,
+    explicit: BTreeMap<String, u32>,
+    assigned: BTreeMap<String, u32>,
+    /// Tables listed in [`RT_TABLES_PATH`] by a previous run
+    recorded: BTreeSet<u32>,
+}
+
+impl Default for TableAllocator {
+    fn default() -> Self {
+        Self::new(&TablesConfig::default())
+    }
+}
+
+impl TableAllocator {
+    /// Allocator with the configured range and explicit IDs, nothing assigned yet
+    pub fn new(config: &TablesConfig) -> Self {
+        Self {
+            base: config.base,
+            range: config.range,
+            explicit: config.interfaces.clone(),
+            assigned: BTreeMap::new(),
+            recorded: BTreeSet::new(),
+        }
+    }
+
+    /// Allocator with a table for every `routing.policy_rules` interface
+    ///
+    /// The tables a previous run listed in [`RT_TABLES_PATH`] are
+    /// remembered as netevd's. With `rt_tables` enabled their assignments
+    /// are also reused, so they stay the same.
+    pub fn for_config(routing: &RoutingConfig) -> Self {
+        let mut allocator = Self::new(&routing.tables);
+
+        match fs::read_to_string(RT_TABLES_PATH) {
+            Ok(contents) => {
+                for (table, name) in parse_rt_tables(&contents) {
+                    allocator.recorded.insert(table);
+                    if routing.tables.rt_tables && routing.policy_rules.contains(&name) {
+                        allocator.assign(&name, table);
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => tracing::warn!("Failed to read {}: {}", RT_TABLES_PATH, e),
+        }
+
+        for name in &routing.policy_rules {
+            if allocator.allocate(name).is_none() {
+                tracing::warn!("No free routing table left for interface {}", name);
+            }
+        }
+
+        allocator
+    }
+
+    /// Table of an interface, if it has one
+    pub fn get(&self, name: &str) -> Option<u32> {
+        self.explicit
+            .get(name)
+            .or_else(|| self.assigned.get(name))
+            .copied()
+    }
+
+    /// Table of an interface, assigning the lowest free one if needed
+    ///
+    /// Returns `None` when the range is exhausted.
+    pub fn allocate(&mut self, name: &str) -> Option<u32> {
+        if let Some(table) = self.get(name) {
+            return Some(table);
+        }
+
+        let table = (self.base..self.base + self.range).find(|table| !self.is_taken(*table))?;
+        self.assigned.insert(name.to_string(), table);
+        Some(table)
+    }
+
+    /// Give an interface a specific table from the range, if it is free
+    fn assign(&mut self, name: &str, table: u32) -> bool {
+        if self.get(name).is_some() || !self.in_range(table) || self.is_taken(table) {
+            return false;
+        }
+        self.assigned.insert(name.to_string(), table);
+        true
+    }
+
+    /// Whether a table is in the range netevd allocates from
+    ///
+    /// That is every table in the range plus the explicitly configured
+    /// ones. Other tools may use tables in the range too, so this alone
+    /// does not make a table netevd's; see [`Self::is_owned`].
+    pub fn is_managed(&self, table: u32) -> bool {
+        self.in_range(table) || self.explicit.values().any(|t| *t == table)
+    }
+
+    /// Whether a table is netevd's: assigned to a configured interface or
+    /// listed in the `rt_tables.d` file of a previous run
+    ///
+    /// Startup reconciliation removes unknown entries only from these.
+    pub fn is_owned(&self, table: u32) -> bool {
+        self.is_taken(table) || self.recorded.contains(&table)
+    }
+
+    /// Every interface with a table
+    pub fn assignments(&self) -> BTreeMap<String, u32> {
+        let mut all = self.assigned.clone();
+        all.extend(self.explicit.iter().map(|(name, table)| (name.clone(), *table)));
+        all
+    }
+
+    /// Write the mapping as an iproute2 `rt_tables.d` file
+    pub fn write_rt_tables(&self, path: &Path) -> Result<()> {
+        fs::write(path, render_rt_tables(&self.assignments()))
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    fn in_range(&self, table: u32) -> bool {
+        (self.base..self.base + self.range).contains(&table)
+    }
+
+    fn is_taken(&self, table: u32) -> bool {
+        self.explicit
+            .values()
+            .chain(self.assigned.values())
+            .any(|t| *t == table)
+    }
+}
+
+/// iproute2 name of an interface's table
+pub fn table_name(interface: &str) -> String {
+    format!("{}{}", TABLE_NAME_PREFIX, interface)
+}
+
+/// netevd's entries in an `rt_tables` file, as (table, interface)
+fn parse_rt_tables(contents: &str) -> Vec<(u32, String)> {
+    contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let table = fields.next()?.parse().ok()?;
+            let interface = fields.next()?.strip_prefix(TABLE_NAME_PREFIX)?;
+            Some((table, interface.to_string()))
+        })
+        .collect()
+}
+
+fn render_rt_tables(assignments: &BTreeMap<String, u32>) -> String {
+    let mut contents = String::from(
+        "# Routing tables of netevd policy interfaces.\n# Written by netevd on startup and reload; local changes are overwritten.\n",
+    );
+    for (interface, table) in assignments {
+        contents.push_str(&format!("{}\t{}\n", table, table_name(interface)));
+    }
+    contents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tables(base: u32, range: u32, explicit: &[(&str, u32)]) -> TablesConfig {
+        TablesConfig {
+            base,
+            range,
+            interfaces: explicit
+                .iter()
+                .map(|(name, table)| (name.to_string(), *table))
+                .collect(),
+            rt_tables: false,
+        }
+    }
+
+    #[test]
+    fn test_allocate_lowest_free() {
+        let mut allocator = TableAllocator::new(&tables(1000, 3, &[("eth9", 1000)]));
+
+        assert_eq!(allocator.allocate("eth1"), Some(1001));
+        assert_eq!(allocator.allocate("eth2"), Some(1002));
+        // Stable for an interface that already has one
+        assert_eq!(allocator.allocate("eth1"), Some(1001));
+        assert_eq!(allocator.get("eth9"), Some(1000));
+        // Range exhausted
+        assert_eq!(allocator.allocate("eth3"), None);
+        assert_eq!(allocator.get("eth3"), None);
+    }
+
+    #[test]
+    fn test_is_managed() {
+        let allocator = TableAllocator::new(&tables(2000, 10, &[("wan", 100)]));
+        assert!(allocator.is_managed(2000));
+        assert!(allocator.is_managed(2009));
+        assert!(allocator.is_managed(100));
+        assert!(!allocator.is_managed(2010));
+        assert!(!allocator.is_managed(254));
+    }
+
+    #[test]
+    fn test_is_owned() {
+        let mut allocator = TableAllocator::new(&tables(1000, 10, &[("wan", 100)]));
+        allocator.allocate("eth1");
+        allocator.recorded.insert(1005);
+
+        assert!(allocator.is_owned(100));
+        assert!(allocator.is_owned(1000));
+        assert!(allocator.is_owned(1005));
+        // In the range, but no interface of netevd's uses it
+        assert!(allocator.is_managed(1001));
+        assert!(!allocator.is_owned(1001));
+    }
+
+    #[test]
+    fn test_assign_respects_range_and_conflicts() {
+        let mut allocator = TableAllocator::new(&tables(1000, 10, &[("wan", 1005)]));
+        assert!(allocator.assign("eth1", 1007));
+        assert!(!allocator.assign("eth2", 1007));
+        assert!(!allocator.assign("eth2", 1005));
+        assert!(!allocator.assign("eth2", 5000));
+        assert_eq!(allocator.allocate("eth2"), Some(1000));
+    }
+
+    #[test]
+    fn test_rt_tables_round_trip() {
+        let mut allocator = TableAllocator::new(&tables(1000, 10, &[("wan", 100)]));
+        allocator.allocate("eth1");
+
+        let contents = render_rt_tables(&allocator.assignments());
+        assert!(contents.contains("1000\tnetevd-eth1\n"));
+        assert!(contents.contains("100\tnetevd-wan\n"));
+
+        let mut entries = parse_rt_tables(&format!("{}250 other # comment\n", contents));
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec![(100, "wan".to_string()), (1000, "eth1".to_string())]
+        );
+    }
+}
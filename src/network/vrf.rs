@@ -0,0 +1,386 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+
+//! VRF devices for `routing.mode: vrf`
+//!
+//! Instead of per-address rules, each policy interface is enslaved to a VRF
+//! device bound to its table. The kernel's l3mdev rule then sends the
+//! interface's traffic, and that of sockets bound to it, to the table, and
+//! the connected and local routes of its addresses move there on their own;
+//! netevd only adds the default route. A VRF device exists while its
+//! interface does: [`sync`] creates it when the link appears and deletes it
+//! when the link goes away or leaves `routing.policy_rules`. A device that
+//! already exists under the configured name with the right table is taken
+//! over, so a restart does not disturb the interface.
+
+use anyhow::{Context, Result};
+use futures::stream::TryStreamExt;
+use rtnetlink::packet_route::link::{InfoData, InfoKind, InfoVrf, LinkAttribute, LinkFlags, LinkInfo, LinkMessage};
+use rtnetlink::{Handle, LinkUnspec, LinkVrf};
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+use super::route::{add_route, discover_gateway, discover_ipv6_gateway};
+use super::{ip_family, is_not_found, NetworkState};
+
+/// Links and reloads both trigger syncs; one at a time keeps the kernel and
+/// the tracked devices in step
+static UPDATE: Mutex<()> = Mutex::const_new(());
+
+/// A VRF device netevd created or took over for a policy interface
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vrf {
+    /// Name of the VRF device
+    pub name: String,
+    /// Interface index of the VRF device
+    pub ifindex: u32,
+    pub table: u32,
+}
+
+/// Kernel view of an existing link that may be a VRF
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct KernelLink {
+    index: u32,
+    up: bool,
+    /// VRF table, if the link is a VRF
+    vrf_table: Option<u32>,
+    /// Device the link is enslaved to
+    controller: Option<u32>,
+}
+
+impl KernelLink {
+    fn from_message(message: &LinkMessage) -> Self {
+        let mut link = KernelLink {
+            index: message.header.index,
+            up: message.header.flags.contains(LinkFlags::Up),
+            vrf_table: None,
+            controller: None,
+        };
+        for attr in &message.attributes {
+            match attr {
+                LinkAttribute::Controller(index) if *index != 0 => link.controller = Some(*index),
+                LinkAttribute::LinkInfo(infos) => link.vrf_table = vrf_table(infos),
+                _ => {}
+            }
+        }
+        link
+    }
+}
+
+/// Table of a VRF device, from its link info; `None` for other kinds
+fn vrf_table(infos: &[LinkInfo]) -> Option<u32> {
+    if !infos.contains(&LinkInfo::Kind(InfoKind::Vrf)) {
+        return None;
+    }
+    infos.iter().find_map(|info| match info {
+        LinkInfo::Data(InfoData::Vrf(data)) => data.iter().find_map(|nla| match nla {
+            InfoVrf::TableId(table) => Some(*table),
+            _ => None,
+        }),
+        _ => None,
+    })
+}
+
+/// Bring the VRF devices in line with the policy interfaces present
+///
+/// Returns the changes made, in `ip link` terms. In dry-run mode nothing
+/// is sent to the kernel or tracked; the changes are only logged.
+pub async fn sync(handle: &Handle, state: &Arc<RwLock<NetworkState>>, dry_run: bool) -> Vec<String> {
+    let _guard = UPDATE.lock().await;
+    let (configured, tracked) = {
+        let state_read = state.read().await;
+        let configured: Vec<(String, String, u32, Option<u32>)> = state_read
+            .vrf_names
+            .iter()
+            .filter_map(|(interface, name)| {
+                let table = state_read.tables.get(interface)?;
+                Some((interface.clone(), name.clone(), table, state_read.get_link_index(interface)))
+            })
+            .collect();
+        (configured, state_read.vrfs.clone())
+    };
+
+    let mut changes = Vec::new();
+
+    // Devices of interfaces no longer in the policy go first
+    for (interface, vrf) in &tracked {
+        if configured.iter().any(|(name, ..)| name == interface) {
+            continue;
+        }
+        match delete(handle, state, interface, &vrf.name, dry_run).await {
+            Ok(Some(change)) => changes.push(change),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to remove VRF {} of {}: {:#}", vrf.name, interface, e),
+        }
+    }
+
+    for (interface, name, table, ifindex) in configured {
+        let result = match ifindex {
+            Some(ifindex) => attach(handle, state, &interface, ifindex, &name, table, dry_run).await,
+            None => delete(handle, state, &interface, &name, dry_run)
+                .await
+                .map(|change| change.into_iter().collect()),
+        };
+        match result {
+            Ok(done) => changes.extend(done),
+            Err(e) => warn!("Failed to set up VRF {} for {}: {:#}", name, interface, e),
+        }
+    }
+
+    changes
+}
+
+/// Delete the VRF devices netevd tracks
+///
+/// Their interfaces are released back to the main table. Used on shutdown
+/// with `routing.on_shutdown: flush`.
+pub async fn flush(handle: &Handle, state: &Arc<RwLock<NetworkState>>, dry_run: bool) -> Result<Vec<String>> {
+    let _guard = UPDATE.lock().await;
+    let tracked = state.read().await.vrfs.clone();
+
+    let mut changes = Vec::new();
+    for (interface, vrf) in tracked {
+        changes.extend(delete(handle, state, &interface, &vrf.name, dry_run).await?);
+    }
+
+    Ok(changes)
+}
+
+/// Create or take over an interface's VRF device and enslave the link
+async fn attach(
+    handle: &Handle,
+    state: &Arc<RwLock<NetworkState>>,
+    interface: &str,
+    ifindex: u32,
+    name: &str,
+    table: u32,
+    dry_run: bool,
+) -> Result<Vec<String>> {
+    let link = find_link(handle, interface, Some(ifindex))
+        .await?
+        .with_context(|| format!("Interface {} is gone", interface))?;
+    let mut device = find_link(handle, name, None).await?;
+    if let Some(device) = &device {
+        if device.vrf_table != Some(table) {
+            anyhow::bail!("{} exists, but is not a VRF with table {}", name, table);
+        }
+        if link.controller == Some(device.index) {
+            let vrf = Vrf {
+                name: name.to_string(),
+                ifindex: device.index,
+                table,
+            };
+            if !dry_run && state.read().await.vrfs.get(interface) != Some(&vrf) {
+                info!("Taking over VRF {} (table {}) of {}", name, table, interface);
+                state.write().await.vrfs.insert(interface.to_string(), vrf);
+            }
+            return Ok(Vec::new());
+        }
+    }
+
+    // The VRF of a renamed device may still hold the link; anything else
+    // it is enslaved to, say a bond, is not netevd's to take apart
+    let previous = state.read().await.vrfs.get(interface).cloned();
+    if let Some(controller) = link.controller {
+        if previous.as_ref().is_none_or(|vrf| vrf.ifindex != controller) {
+            anyhow::bail!("{} is already enslaved to another device", interface);
+        }
+    }
+
+    // Enslaving cycles the link, and the kernel flushes its routes on the
+    // way down, so its default routes are carried over into the VRF table
+    let gateways = current_gateways(handle, state, ifindex, table).await;
+
+    let mut changes = Vec::new();
+    if let Some(previous) = previous.filter(|vrf| vrf.name != name) {
+        changes.extend(delete(handle, state, interface, &previous.name, dry_run).await?);
+    }
+    if device.is_none() {
+        changes.push(format!("link add {} type vrf table {}", name, table));
+    }
+    changes.push(format!("link set {} master {}", interface, name));
+    for gateway in &gateways {
+        changes.push(format!("{}route add default via {} dev {} table {}", ip_family(gateway), gateway, interface, table));
+    }
+    if dry_run {
+        for change in &changes {
+            info!("Dry run: would run `ip {}`", change);
+        }
+        return Ok(changes);
+    }
+
+    if device.is_none() {
+        info!("Creating VRF {} with table {} for {}", name, table, interface);
+        handle
+            .link()
+            .add(LinkVrf::new(name, table).up().build())
+            .execute()
+            .await
+            .with_context(|| format!("Failed to create VRF {}", name))?;
+        device = find_link(handle, name, None).await?;
+    }
+    let device = device.with_context(|| format!("VRF {} did not appear", name))?;
+    if !device.up {
+        handle
+            .link()
+            .set(LinkUnspec::new_with_index(device.index).up().build())
+            .execute()
+            .await
+            .with_context(|| format!("Failed to set VRF {} up", name))?;
+    }
+
+    info!("Enslaving {} to VRF {}", interface, name);
+    handle
+        .link()
+        .set(LinkUnspec::new_with_index(ifindex).controller(device.index).build())
+        .execute()
+        .await
+        .with_context(|| format!("Failed to enslave {} to {}", interface, name))?;
+    state.write().await.vrfs.insert(
+        interface.to_string(),
+        Vrf {
+            name: name.to_string(),
+            ifindex: device.index,
+            table,
+        },
+    );
+
+    for gateway in gateways {
+        if let Err(e) = add_route(handle, ifindex, gateway, table).await {
+            warn!("Failed to carry the default route via {} over to VRF {}: {:#}", gateway, name, e);
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Delete an interface's VRF device, if it exists and is netevd's
+///
+/// An untracked device is only deleted if it is a VRF with the interface's
+/// table, as left behind by a previous run.
+async fn delete(
+    handle: &Handle,
+    state: &Arc<RwLock<NetworkState>>,
+    interface: &str,
+    name: &str,
+    dry_run: bool,
+) -> Result<Option<String>> {
+    let (tracked, table) = {
+        let state_read = state.read().await;
+        (state_read.vrfs.get(interface).cloned(), state_read.tables.get(interface))
+    };
+    let device = find_link(handle, name, None).await?;
+    let ours = |device: &KernelLink| match &tracked {
+        Some(vrf) if vrf.name == name => vrf.ifindex == device.index,
+        _ => table.is_some() && device.vrf_table == table,
+    };
+
+    let change = match device.filter(ours) {
+        Some(device) => {
+            let change = format!("link del {}", name);
+            if dry_run {
+                info!("Dry run: would run `ip {}`", change);
+                return Ok(Some(change));
+            }
+            info!("Deleting VRF {} of {}", name, interface);
+            match handle.link().del(device.index).execute().await {
+                // Gone since it was looked up
+                Err(e) if is_not_found(&e) => debug!("VRF {} already gone", name),
+                result => result.with_context(|| format!("Failed to delete VRF {}", name))?,
+            }
+            Some(change)
+        }
+        None => None,
+    };
+
+    if !dry_run && tracked.is_some_and(|vrf| vrf.name == name) {
+        state.write().await.vrfs.remove(interface);
+    }
+    Ok(change)
+}
+
+/// Look up a link by name, or by index if given; `None` if it does not exist
+async fn find_link(handle: &Handle, name: &str, index: Option<u32>) -> Result<Option<KernelLink>> {
+    let request = match index {
+        Some(index) => handle.link().get().match_index(index),
+        None => handle.link().get().match_name(name.to_string()),
+    };
+    match request.execute().try_next().await {
+        Ok(message) => Ok(message.as_ref().map(KernelLink::from_message)),
+        Err(e) if e.to_string().contains("No such device") => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to look up link {}", name)),
+    }
+}
+
+/// Gateways of the interface's default routes, per family
+///
+/// Falls back to the gateways netevd tracks for the table, which a VRF that
+/// is being replaced no longer shows.
+async fn current_gateways(handle: &Handle, state: &Arc<RwLock<NetworkState>>, ifindex: u32, table: u32) -> Vec<IpAddr> {
+    let tracked = state.read().await.routes.get(&(ifindex, table)).cloned();
+    let mut gateways = Vec::new();
+    for ipv6 in [false, true] {
+        let discovered = if ipv6 {
+            discover_ipv6_gateway(handle, ifindex).await
+        } else {
+            discover_gateway(handle, ifindex).await
+        };
+        let gateway = match discovered {
+            Ok(gateway) => gateway,
+            Err(e) => {
+                debug!("Failed to discover the gateway of interface {}: {}", ifindex, e);
+                None
+            }
+        };
+        gateways.extend(gateway.or_else(|| tracked.as_ref().and_then(|route| route.default_gateway(ipv6))));
+    }
+    gateways
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rtnetlink::packet_route::link::InfoBridge;
+
+    #[test]
+    fn test_vrf_table() {
+        let vrf = LinkVrf::new("vrf-eth1", 1002).build();
+        let infos = vrf
+            .attributes
+            .iter()
+            .find_map(|attr| match attr {
+                LinkAttribute::LinkInfo(infos) => Some(infos.clone()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(vrf_table(&infos), Some(1002));
+
+        let bridge = [
+            LinkInfo::Kind(InfoKind::Bridge),
+            LinkInfo::Data(InfoData::Bridge(vec![InfoBridge::HelloTime(200)])),
+        ];
+        assert_eq!(vrf_table(&bridge), None);
+    }
+
+    #[test]
+    fn test_kernel_link() {
+        let mut message = LinkVrf::new("vrf-eth1", 1002).up().build();
+        message.header.index = 7;
+        let link = KernelLink::from_message(&message);
+        assert_eq!(link.index, 7);
+        assert!(link.up);
+        assert_eq!(link.vrf_table, Some(1002));
+        assert_eq!(link.controller, None);
+
+        let mut port = LinkUnspec::new_with_index(3).controller(7).build();
+        port.header.index = 3;
+        let link = KernelLink::from_message(&port);
+        assert!(!link.up);
+        assert_eq!(link.vrf_table, None);
+        assert_eq!(link.controller, Some(7));
+
+        let released = LinkUnspec::new_with_index(3).nocontroller().build();
+        assert_eq!(KernelLink::from_message(&released).controller, None);
+    }
+}
@@ -6,35 +6,49 @@
 //! event notification with <100ms latency, replacing the previous polling
 //! approach which had 5-second intervals.
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use futures::channel::mpsc::UnboundedReceiver;
 use futures::stream::StreamExt;
 use rtnetlink::packet_core::NetlinkMessage;
 use rtnetlink::packet_route::RouteNetlinkMessage;
 use netlink_sys::{protocols::NETLINK_ROUTE, SocketAddr as NetlinkSocketAddr, TokioSocket};
 use rtnetlink::Handle;
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::net::IpAddr;
+use std::path::Path;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc, watch, RwLock};
+use tokio::time::Instant;
 use tracing::{debug, info, warn};
 
+use crate::audit::AuditLogger;
+use crate::config::{Config, RoutingConfig, RoutingMode};
+use crate::events::EventHistory;
+use crate::metrics::MetricsHandle;
+
 use super::{
-    address::get_ipv4_addresses,
-    route::{add_route, calculate_table_id, discover_gateway, remove_route},
-    routing_rule::{add_routing_rule_from, add_routing_rule_to, remove_routing_rules},
-    NetworkState,
+    address::{get_policy_prefixes, host_prefix_len, network_address},
+    route::{
+        add_route, add_subnet_route, discover_gateway, discover_ipv6_gateway, discover_main_gateway, remove_default_route, remove_default_routes, remove_route,
+        remove_subnet_route, replace_route,
+    },
+    balance, mark, vrf,
+    reconcile::{self, PolicyLink},
+    repair::{self, RepairLimiter, REPAIR_DELAY, REPAIR_RETRY_DELAY},
+    routing_rule::{add_routing_rule, remove_routing_rule, suppress_rule_matches},
+    table::RT_TABLES_PATH,
+    ip_family, NetworkState, RoutingRule,
 };
 
+/// Stream of multicast netlink messages delivered to an event watcher
+type EventMessages = UnboundedReceiver<(NetlinkMessage<RouteNetlinkMessage>, NetlinkSocketAddr)>;
+
 /// Create a netlink event receiver subscribed to the specified multicast groups.
 /// This only returns a message receiver (no Handle), since the event watchers
 /// only need to receive multicast notifications, not send requests.
 fn new_event_receiver(
     groups: &[u32],
-) -> std::io::Result<(
-    impl std::future::Future<Output = ()>,
-    UnboundedReceiver<(NetlinkMessage<RouteNetlinkMessage>, NetlinkSocketAddr)>,
-)> {
+) -> std::io::Result<(impl std::future::Future<Output = ()>, EventMessages)> {
     use std::os::unix::io::{AsRawFd, FromRawFd};
 
     let mut socket = netlink_sys::Socket::new(NETLINK_ROUTE)?;
@@ -59,26 +73,82 @@ fn new_event_receiver(
 }
 
 /// Watch for address changes using real-time netlink events
+///
+/// Interfaces listed in `routing.policy_rules` get per-interface routing
+/// tables and rules for their IPv4 and global IPv6 addresses. The list is
+/// re-read whenever `reloads` signals a configuration reload: removed
+/// interfaces are torn down, added ones are configured from their current
+/// addresses.
+///
+/// On startup, rules and routes left in netevd's tables by a previous run
+/// are adopted or removed, and the addresses already present on the policy
+/// interfaces are configured without waiting for a netlink event.
+///
+/// In dry-run mode the routes and rules are only logged and recorded in the
+/// event history, never sent to the kernel.
 pub async fn watch_addresses(
     handle: Handle,
     state: Arc<RwLock<NetworkState>>,
-    routing_policy_interfaces: Vec<String>,
+    config: Arc<RwLock<Config>>,
+    mut reloads: watch::Receiver<u64>,
+    events: Arc<EventHistory>,
+    dry_run: bool,
 ) -> Result<()> {
     info!("Starting address watcher (real-time netlink events)");
 
     // Track addresses we've seen before
     let mut last_seen_addresses: HashSet<(u32, IpAddr)> = HashSet::new();
+    let mut routing = config.read().await.routing.clone();
+    let mut reloads_open = true;
 
-    // Subscribe to IPv4 address change notifications only
-    // (IPv6 policy routing is handled separately via the ipv6 module)
+    // Subscribe to IPv4 and IPv6 address change notifications
     let (connection, mut messages) =
-        new_event_receiver(&[libc::RTNLGRP_IPV4_IFADDR as u32])?;
+        new_event_receiver(&[libc::RTNLGRP_IPV4_IFADDR, libc::RTNLGRP_IPV6_IFADDR])?;
     tokio::spawn(connection);
 
     info!("Address watcher subscribed to netlink multicast groups");
 
+    // Subscribed first, so no address change between the scan and the
+    // loop below goes unnoticed
+    reconcile_at_startup(
+        &handle,
+        &state,
+        &routing.policy_rules,
+        &mut last_seen_addresses,
+        &events,
+        dry_run,
+    )
+    .await;
+
     // Process address change events in real-time
-    while let Some((message, _)) = messages.next().await {
+    loop {
+        let message = tokio::select! {
+            message = messages.next() => match message {
+                Some((message, _)) => message,
+                None => break,
+            },
+            changed = reloads.changed(), if reloads_open => {
+                if changed.is_err() {
+                    reloads_open = false;
+                    continue;
+                }
+
+                let updated = config.read().await.routing.clone();
+                apply_policy_changes(
+                    &handle,
+                    &state,
+                    &routing,
+                    &updated,
+                    &mut last_seen_addresses,
+                    &events,
+                    dry_run,
+                )
+                .await;
+                routing = updated;
+                continue;
+            }
+        };
+
         use rtnetlink::packet_route::RouteNetlinkMessage;
 
         let (event_type, msg) = match message.payload {
@@ -96,7 +166,7 @@ pub async fn watch_addresses(
         // Check if this interface is in our monitoring list
         let should_monitor = {
             let state_read = state.read().await;
-            routing_policy_interfaces.iter().any(|name| {
+            routing.policy_rules.iter().any(|name| {
                 state_read.get_link_index(name) == Some(ifindex)
             })
         };
@@ -105,95 +175,587 @@ pub async fn watch_addresses(
             continue;
         }
 
-        // Get interface name
-        let link_name = {
+        sync_interface_addresses(
+            &handle,
+            &state,
+            ifindex,
+            "netlink",
+            event_type,
+            &mut last_seen_addresses,
+            &events,
+            dry_run,
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+/// Take over from a previous run, then configure the existing addresses
+///
+/// In VRF mode the policy interfaces are enslaved first, since that moves
+/// their routes.
+async fn reconcile_at_startup(
+    handle: &Handle,
+    state: &Arc<RwLock<NetworkState>>,
+    interfaces: &[String],
+    last_seen_addresses: &mut HashSet<(u32, IpAddr)>,
+    events: &EventHistory,
+    dry_run: bool,
+) {
+    let changes = vrf::sync(handle, state, dry_run).await;
+    if !changes.is_empty() {
+        events.record_event(
+            "vrf",
+            "",
+            serde_json::json!({
+                "source": "startup",
+                "changes": changes,
+                "dry_run": dry_run,
+            }),
+        );
+    }
+
+    let mut links = Vec::new();
+    for name in interfaces {
+        let (ifindex, table, rules, vrf) = {
             let state_read = state.read().await;
-            state_read.get_link_name(ifindex).cloned().unwrap_or_default()
+            (
+                state_read.get_link_index(name),
+                state_read.tables.get(name),
+                state_read.rule_configs.get(name).cloned().unwrap_or_default(),
+                state_read.mode == RoutingMode::Vrf,
+            )
+        };
+        let Some(ifindex) = ifindex else {
+            debug!("Policy interface {} not present at startup", name);
+            continue;
+        };
+        let Some(table) = table else {
+            warn!("No routing table allocated for policy interface {}", name);
+            continue;
         };
 
-        // Get current addresses for this interface
-        match get_ipv4_addresses(&handle, ifindex).await {
-            Ok(addresses) => {
-                let current_addrs: HashSet<(u32, IpAddr)> = addresses
-                    .iter()
-                    .map(|addr| (ifindex, *addr))
-                    .collect();
+        match get_policy_prefixes(handle, ifindex).await {
+            Ok(addresses) => links.push(PolicyLink {
+                ifindex,
+                table,
+                addresses,
+                rules,
+                vrf,
+            }),
+            Err(e) => {
+                // Without its addresses the interface's rules would look
+                // like orphans, so leave the tables alone this time
+                warn!("Failed to get addresses for interface {}, skipping startup reconciliation: {}", name, e);
+                return;
+            }
+        }
+    }
 
-                // Detect changes for this interface
-                let old_addrs: HashSet<(u32, IpAddr)> = last_seen_addresses
-                    .iter()
-                    .filter(|(idx, _)| *idx == ifindex)
-                    .copied()
-                    .collect();
+    match reconcile::reconcile(handle, state, &links, dry_run).await {
+        Ok(report) if !report.is_empty() => {
+            events.record_event(
+                "reconcile",
+                "",
+                serde_json::json!({
+                    "source": "startup",
+                    "adopted": report.adopted,
+                    "removed": report.removed,
+                    "dry_run": dry_run,
+                }),
+            );
+        }
+        Ok(_) => {}
+        Err(e) => warn!("Startup reconciliation failed: {}", e),
+    }
 
-                if current_addrs != old_addrs {
-                    info!(
-                        "Address change detected on interface {} ({}): {} -> {} addresses",
-                        link_name,
-                        ifindex,
-                        old_addrs.len(),
-                        addresses.len()
-                    );
+    for link in &links {
+        sync_interface_addresses(
+            handle,
+            state,
+            link.ifindex,
+            "startup",
+            "existing",
+            last_seen_addresses,
+            events,
+            dry_run,
+        )
+        .await;
+    }
+}
 
-                    if addresses.is_empty() {
-                        info!(
-                            "No addresses on interface {}, cleaning up routing configuration",
-                            link_name
-                        );
-                        if let Err(e) = drop_configuration(&handle, &state, ifindex).await {
-                            warn!("Failed to drop configuration: {}", e);
-                        }
+/// Bring an interface's policy routing in line with its current addresses
+///
+/// IPv6 addresses count once they are usable: global scope and past DAD.
+#[allow(clippy::too_many_arguments)]
+async fn sync_interface_addresses(
+    handle: &Handle,
+    state: &Arc<RwLock<NetworkState>>,
+    ifindex: u32,
+    source: &str,
+    action: &str,
+    last_seen_addresses: &mut HashSet<(u32, IpAddr)>,
+    events: &EventHistory,
+    dry_run: bool,
+) {
+    // Get interface name
+    let link_name = {
+        let state_read = state.read().await;
+        state_read.get_link_name(ifindex).cloned().unwrap_or_default()
+    };
 
-                        // Remove old addresses from tracking
-                        last_seen_addresses.retain(|(idx, _)| *idx != ifindex);
-                    } else {
-                        // Clean up rules for removed addresses before adding new ones
-                        let removed_addrs: Vec<IpAddr> = old_addrs.iter()
-                            .filter(|(_, addr)| !current_addrs.contains(&(ifindex, *addr)))
-                            .map(|(_, addr)| *addr)
-                            .collect();
-                        if !removed_addrs.is_empty() {
-                            let table = calculate_table_id(ifindex);
-                            for addr in &removed_addrs {
-                                let _ = remove_routing_rules(&handle, *addr, table).await;
-                                state.write().await.routing_rules_from.remove(addr);
-                                state.write().await.routing_rules_to.remove(addr);
-                            }
-                        }
+    // Get current addresses for this interface
+    let prefixes = match get_policy_prefixes(handle, ifindex).await {
+        Ok(prefixes) => prefixes,
+        Err(e) => {
+            warn!("Failed to get addresses for interface {}: {}", ifindex, e);
+            return;
+        }
+    };
+    let addresses: Vec<IpAddr> = prefixes.iter().map(|(address, _)| *address).collect();
+
+    let current_addrs: HashSet<(u32, IpAddr)> = addresses
+        .iter()
+        .map(|addr| (ifindex, *addr))
+        .collect();
+
+    // Detect changes for this interface
+    let old_addrs: HashSet<(u32, IpAddr)> = last_seen_addresses
+        .iter()
+        .filter(|(idx, _)| *idx == ifindex)
+        .copied()
+        .collect();
+
+    if current_addrs == old_addrs {
+        return;
+    }
 
-                        info!(
-                            "Configuring routing rules for interface {} with {} addresses",
-                            link_name,
-                            addresses.len()
-                        );
-                        if let Err(e) =
-                            configure_network(&handle, &state, ifindex, &addresses).await
-                        {
-                            warn!("Failed to configure network: {}", e);
-                        }
+    info!(
+        "Address change detected on interface {} ({}): {} -> {} addresses",
+        link_name,
+        ifindex,
+        old_addrs.len(),
+        addresses.len()
+    );
 
-                        // Update tracking
-                        last_seen_addresses.retain(|(idx, _)| *idx != ifindex);
-                        last_seen_addresses.extend(current_addrs);
+    let removed_addrs: Vec<IpAddr> = old_addrs
+        .iter()
+        .filter(|(_, addr)| !current_addrs.contains(&(ifindex, *addr)))
+        .map(|(_, addr)| *addr)
+        .collect();
+    let routing;
+    let mut changes = Vec::new();
+
+    if addresses.is_empty() {
+        info!(
+            "No addresses on interface {}, cleaning up routing configuration",
+            link_name
+        );
+        routing = match drop_configuration(handle, state, ifindex, dry_run).await {
+            Ok(dropped) => {
+                changes = dropped;
+                if dry_run { "would drop" } else { "dropped" }.to_string()
+            }
+            Err(e) => {
+                warn!("Failed to drop configuration: {}", e);
+                format!("drop failed: {}", e)
+            }
+        };
+
+        // Remove old addresses from tracking
+        last_seen_addresses.retain(|(idx, _)| *idx != ifindex);
+    } else {
+        // Clean up rules for removed addresses before adding new ones
+        let table = state.read().await.link_table(ifindex);
+        if let Some(table) = table.filter(|_| !removed_addrs.is_empty()) {
+            for addr in &removed_addrs {
+                changes.push(format!("{}rule del from/to {} lookup {}", ip_family(addr), addr, table));
+                if dry_run {
+                    info!("Dry run: would remove routing rules for {} in table {}", addr, table);
+                    continue;
+                }
+                let rules: Vec<RoutingRule> = state
+                    .read()
+                    .await
+                    .routing_rules()
+                    .filter(|rule| rule.address == *addr && rule.table == table)
+                    .cloned()
+                    .collect();
+                for rule in &rules {
+                    if let Err(e) = remove_routing_rule(handle, rule).await {
+                        warn!("{:#}", e);
                     }
+                    state.write().await.remove_routing_rule(rule);
                 }
             }
+        }
+        if let Some(table) = table {
+            changes.extend(prune_subnets(handle, state, ifindex, table, &prefixes, dry_run).await);
+        }
+
+        info!(
+            "Configuring routing rules for interface {} with {} addresses",
+            link_name,
+            addresses.len()
+        );
+        routing = match configure_network(handle, state, ifindex, &prefixes, dry_run).await {
+            Ok(configured) => {
+                changes.extend(configured);
+                if dry_run { "would configure" } else { "configured" }.to_string()
+            }
+            Err(e) => {
+                warn!("Failed to configure network: {}", e);
+                format!("configure failed: {}", e)
+            }
+        };
+
+        // Update tracking
+        last_seen_addresses.retain(|(idx, _)| *idx != ifindex);
+        last_seen_addresses.extend(current_addrs);
+    }
+    changes.extend(balance::update(handle, state, dry_run).await);
+    changes.extend(mark::update(handle, state, dry_run).await);
+
+    events.record_event(
+        "address",
+        &link_name,
+        serde_json::json!({
+            "source": source,
+            "action": action,
+            "ifindex": ifindex,
+            "addresses": addresses,
+            "removed": removed_addrs,
+            "routing": routing,
+            "changes": changes,
+            "dry_run": dry_run,
+        }),
+    );
+}
+
+/// Remove the subnet routes and subnet `to` rules of a link's table that
+/// none of its current addresses belongs to any more
+///
+/// Returns the changes made; in dry-run mode they are only logged.
+async fn prune_subnets(
+    handle: &Handle,
+    state: &Arc<RwLock<NetworkState>>,
+    ifindex: u32,
+    table: u32,
+    prefixes: &[(IpAddr, u8)],
+    dry_run: bool,
+) -> Vec<String> {
+    let in_use = |(network, prefix_len): (IpAddr, u8)| {
+        prefixes
+            .iter()
+            .any(|(address, len)| *len == prefix_len && network_address(*address, *len) == network)
+    };
+
+    let (stale_rules, stale_routes): (Vec<RoutingRule>, Vec<(IpAddr, u8)>) = {
+        let state_read = state.read().await;
+        (
+            state_read
+                .routing_rules_to
+                .values()
+                .filter(|rule| rule.table == table && rule.prefix_len != host_prefix_len(&rule.address))
+                .filter(|rule| !in_use((rule.address, rule.prefix_len)))
+                .cloned()
+                .collect(),
+            state_read
+                .routes
+                .get(&(ifindex, table))
+                .map(|route| route.subnets.iter().copied().filter(|subnet| !in_use(*subnet)).collect())
+                .unwrap_or_default(),
+        )
+    };
+
+    let mut changes = Vec::new();
+    for rule in &stale_rules {
+        changes.push(format!("{}rule del {}", ip_family(&rule.address), rule.describe()));
+        if dry_run {
+            info!("Dry run: would remove routing rule {}", rule.describe());
+            continue;
+        }
+        if let Err(e) = remove_routing_rule(handle, rule).await {
+            warn!("{:#}", e);
+        }
+        state.write().await.remove_routing_rule(rule);
+    }
+
+    for (network, prefix_len) in stale_routes {
+        changes.push(format!("{}route del {}/{} table {}", ip_family(&network), network, prefix_len, table));
+        if dry_run {
+            info!("Dry run: would remove route to {}/{} in table {}", network, prefix_len, table);
+            continue;
+        }
+        if let Err(e) = remove_subnet_route(handle, ifindex, (network, prefix_len), table).await {
+            warn!("Failed to remove route to {}/{}: {}", network, prefix_len, e);
+        }
+        if let Some(route) = state.write().await.routes.get_mut(&(ifindex, table)) {
+            route.subnets.remove(&(network, prefix_len));
+        }
+    }
+
+    changes
+}
+
+/// Apply a reloaded `routing` section
+///
+/// Interfaces whose `routing.rules` entry changed are torn down and
+/// configured again with the new rule shape. Added interfaces get a table,
+/// and with `routing.tables.rt_tables` the table names file is rewritten.
+async fn apply_policy_changes(
+    handle: &Handle,
+    state: &Arc<RwLock<NetworkState>>,
+    old_routing: &RoutingConfig,
+    new_routing: &RoutingConfig,
+    last_seen_addresses: &mut HashSet<(u32, IpAddr)>,
+    events: &EventHistory,
+    dry_run: bool,
+) {
+    let old = &old_routing.policy_rules;
+    let new = &new_routing.policy_rules;
+    {
+        let mut state_write = state.write().await;
+        state_write.rule_configs = new_routing.rules.clone().into_iter().collect();
+        state_write.load_balance = new_routing.load_balance.clone();
+        state_write.mark_rule_configs = new_routing.mark_rules.clone();
+        // The mode itself only changes with a restart
+        if state_write.mode == RoutingMode::Vrf {
+            state_write.vrf_names = new_routing.vrf_devices();
+        }
+    }
+
+    for name in old.iter().filter(|name| !new.contains(name)) {
+        let Some(ifindex) = state.read().await.get_link_index(name) else {
+            continue;
+        };
+
+        info!("Interface {} removed from routing policy, tearing down its rules", name);
+        let (routing, changes) = match drop_configuration(handle, state, ifindex, dry_run).await {
+            Ok(changes) => (if dry_run { "would drop" } else { "dropped" }.to_string(), changes),
             Err(e) => {
-                warn!("Failed to get addresses for interface {}: {}", ifindex, e);
+                warn!("Failed to drop configuration for {}: {}", name, e);
+                (format!("drop failed: {}", e), Vec::new())
             }
+        };
+        last_seen_addresses.retain(|(idx, _)| *idx != ifindex);
+
+        events.record_event(
+            "address",
+            name,
+            serde_json::json!({
+                "source": "reload",
+                "action": "policy_removed",
+                "ifindex": ifindex,
+                "routing": routing,
+                "changes": changes,
+                "dry_run": dry_run,
+            }),
+        );
+    }
+
+    let reshaped = new
+        .iter()
+        .filter(|name| old.contains(name) && old_routing.rule_config(name) != new_routing.rule_config(name));
+    for name in reshaped {
+        let Some(ifindex) = state.read().await.get_link_index(name) else {
+            continue;
+        };
+
+        info!("Routing rules of interface {} changed, re-installing them", name);
+        if let Err(e) = drop_configuration(handle, state, ifindex, dry_run).await {
+            warn!("Failed to drop configuration for {}: {}", name, e);
         }
+        last_seen_addresses.retain(|(idx, _)| *idx != ifindex);
+        sync_interface_addresses(
+            handle,
+            state,
+            ifindex,
+            "reload",
+            "rules_changed",
+            last_seen_addresses,
+            events,
+            dry_run,
+        )
+        .await;
     }
 
-    Ok(())
+    let mut added = Vec::new();
+    for name in new.iter().filter(|name| !old.contains(name)) {
+        if state.write().await.tables.allocate(name).is_none() {
+            warn!("No free routing table left for interface {}", name);
+            continue;
+        }
+        added.push(name);
+    }
+    if !added.is_empty() && new_routing.tables.rt_tables {
+        if dry_run {
+            info!("Dry run: would update routing table names in {}", RT_TABLES_PATH);
+        } else if let Err(e) = state.read().await.tables.write_rt_tables(Path::new(RT_TABLES_PATH)) {
+            warn!("{:#}", e);
+        }
+    }
+
+    // VRFs of removed interfaces go, those of added ones are set up before
+    // their addresses are configured, and renamed ones are replaced
+    let changes = vrf::sync(handle, state, dry_run).await;
+    if !changes.is_empty() {
+        events.record_event(
+            "vrf",
+            "",
+            serde_json::json!({
+                "source": "reload",
+                "changes": changes,
+                "dry_run": dry_run,
+            }),
+        );
+    }
+
+    for name in added {
+        let Some(ifindex) = state.read().await.get_link_index(name) else {
+            debug!("Interface {} added to routing policy but not present yet", name);
+            continue;
+        };
+
+        info!("Interface {} added to routing policy, configuring it", name);
+        sync_interface_addresses(
+            handle,
+            state,
+            ifindex,
+            "reload",
+            "policy_added",
+            last_seen_addresses,
+            events,
+            dry_run,
+        )
+        .await;
+    }
+
+    // Membership, weights or metric of the load-balance group may have
+    // changed without any interface being reconfigured
+    let changes = balance::update(handle, state, dry_run).await;
+    if !changes.is_empty() {
+        events.record_event(
+            "load_balance",
+            "",
+            serde_json::json!({
+                "source": "reload",
+                "changes": changes,
+                "dry_run": dry_run,
+            }),
+        );
+    }
+
+    // Likewise the mark rules
+    let changes = mark::update(handle, state, dry_run).await;
+    if !changes.is_empty() {
+        events.record_event(
+            "mark_rules",
+            "",
+            serde_json::json!({
+                "source": "reload",
+                "changes": changes,
+                "dry_run": dry_run,
+            }),
+        );
+    }
+}
+
+/// Outcome of each step of [`flush_policy_routing`], in order
+///
+/// A step still running has no result yet.
+pub type FlushResults = std::sync::Mutex<Vec<(String, Option<Result<Vec<String>>>)>>;
+
+/// Remove the policy routing of every interface netevd manages
+///
+/// Used on shutdown with `routing.on_shutdown: flush`. Records the outcome
+/// per interface name in `results` as each step finishes, with the changes
+/// made in `ip` terms, so a caller that gives up waiting still sees what
+/// was done. The multipath default route of `routing.load_balance` goes
+/// first, reported as `load_balance`, then the rules of
+/// `routing.mark_rules`, reported as `mark_rules`. In VRF mode the VRF
+/// devices go last, reported as `vrf`.
+pub async fn flush_policy_routing(
+    handle: &Handle,
+    state: &Arc<RwLock<NetworkState>>,
+    dry_run: bool,
+    results: &FlushResults,
+) {
+    let interfaces: BTreeSet<u32> = {
+        let state_read = state.read().await;
+        state_read
+            .routes
+            .keys()
+            .map(|(ifindex, _)| *ifindex)
+            .chain(
+                state_read
+                    .links_by_index
+                    .keys()
+                    .copied()
+                    .filter(|ifindex| {
+                        state_read
+                            .link_table(*ifindex)
+                            .is_some_and(|table| state_read.owns_table(table))
+                    }),
+            )
+            .collect()
+    };
+
+    if !state.read().await.multipath.is_empty() {
+        info!("Flushing the load-balance default routes");
+        flush_step(results, "load_balance".to_string(), balance::flush(handle, state, dry_run)).await;
+    }
+    if !state.read().await.mark_rules.is_empty() {
+        info!("Flushing the mark rules");
+        flush_step(results, "mark_rules".to_string(), mark::flush(handle, state, dry_run)).await;
+    }
+    for ifindex in interfaces {
+        let name = state
+            .read()
+            .await
+            .get_link_name(ifindex)
+            .cloned()
+            .unwrap_or_else(|| ifindex.to_string());
+        info!("Flushing policy routing for interface {}", name);
+        flush_step(results, name, drop_configuration(handle, state, ifindex, dry_run)).await;
+    }
+    if !state.read().await.vrfs.is_empty() {
+        info!("Deleting the VRF devices");
+        flush_step(results, "vrf".to_string(), vrf::flush(handle, state, dry_run)).await;
+    }
+}
+
+/// Run one step of [`flush_policy_routing`], recording it as started and
+/// then its outcome
+async fn flush_step<F>(results: &FlushResults, name: String, step: F)
+where
+    F: std::future::Future<Output = Result<Vec<String>>>,
+{
+    results.lock().unwrap().push((name, None));
+    let result = step.await;
+    if let Some((_, outcome)) = results.lock().unwrap().last_mut() {
+        *outcome = Some(result);
+    }
 }
 
 /// Watch for route changes using real-time netlink events
-pub async fn watch_routes(_handle: Handle, state: Arc<RwLock<NetworkState>>) -> Result<()> {
+///
+/// Besides running the `routes.d` scripts, this keeps the default routes
+/// in the per-interface tables on the current gateway: when the main
+/// table's default route of a policy interface changes, for example after
+/// a DHCP renewal with a new router, the table's default route is replaced.
+pub async fn watch_routes(
+    handle: Handle,
+    state: Arc<RwLock<NetworkState>>,
+    events: Arc<EventHistory>,
+    dry_run: bool,
+) -> Result<()> {
     info!("Starting route watcher (real-time netlink events)");
 
     // Subscribe to route change notifications via multicast groups
     let (connection, mut messages) =
-        new_event_receiver(&[libc::RTNLGRP_IPV4_ROUTE as u32, libc::RTNLGRP_IPV6_ROUTE as u32])?;
+        new_event_receiver(&[libc::RTNLGRP_IPV4_ROUTE, libc::RTNLGRP_IPV6_ROUTE])?;
     tokio::spawn(connection);
 
     info!("Route watcher subscribed to netlink multicast groups");
@@ -239,6 +801,18 @@ pub async fn watch_routes(_handle: Handle, state: Arc<RwLock<NetworkState>>) ->
             event_type, link_name, ifindex
         );
 
+        let changes = if super::route::is_main_default_route(&msg) {
+            let ipv6 = msg.header.address_family == rtnetlink::packet_route::AddressFamily::Inet6;
+            let mut changes = update_gateway(&handle, &state, ifindex, ipv6, dry_run).await;
+            if !changes.is_empty() {
+                changes.extend(balance::update(&handle, &state, dry_run).await);
+                changes.extend(mark::update(&handle, &state, dry_run).await);
+            }
+            changes
+        } else {
+            Vec::new()
+        };
+
         // Execute scripts for route changes
         let script_dir = crate::system::paths::get_script_dir("routes");
         let mut env_vars = std::collections::HashMap::new();
@@ -247,8 +821,303 @@ pub async fn watch_routes(_handle: Handle, state: Arc<RwLock<NetworkState>>) ->
         env_vars.insert("EVENT".to_string(), event_type.to_string());
         env_vars.insert("STATE".to_string(), "routes".to_string());
 
-        if let Err(e) = crate::system::execute::execute_scripts(&script_dir, env_vars).await {
-            debug!("Failed to execute route scripts: {}", e);
+        let results = crate::system::execute::run_scripts(&script_dir, env_vars, dry_run).await;
+        let scripts = match results {
+            Ok(results) => results,
+            Err(e) => {
+                debug!("Failed to execute route scripts: {}", e);
+                Vec::new()
+            }
+        };
+
+        events.record_event(
+            "route",
+            &link_name,
+            serde_json::json!({
+                "source": "netlink",
+                "action": event_type,
+                "ifindex": ifindex,
+                "table": super::route::route_table(&msg),
+                "scripts": scripts,
+                "changes": changes,
+                "dry_run": dry_run,
+            }),
+        );
+    }
+
+    Ok(())
+}
+
+/// Follow a change of an interface's default route in its policy table
+///
+/// Only families netevd routes through the table are touched: those with
+/// a tracked gateway or with policy rules into the table. If the interface
+/// has no default route left, the table's default route is withdrawn and
+/// the tracked gateway forgotten, so nothing keeps using it; a default
+/// route that appears later is followed again. Returns the change made, in
+/// `ip` terms; in dry-run mode it is only logged.
+async fn update_gateway(
+    handle: &Handle,
+    state: &Arc<RwLock<NetworkState>>,
+    ifindex: u32,
+    ipv6: bool,
+    dry_run: bool,
+) -> Vec<String> {
+    let (table, tracked) = {
+        let state_read = state.read().await;
+        let Some(table) = state_read.link_table(ifindex) else {
+            return Vec::new();
+        };
+        let Some(route) = state_read.routes.get(&(ifindex, table)) else {
+            return Vec::new();
+        };
+        let tracked = route.default_gateway(ipv6);
+        let routed = tracked.is_some()
+            || state_read
+                .routing_rules_from
+                .values()
+                .any(|rule| rule.table == table && rule.address.is_ipv6() == ipv6);
+        if !routed {
+            return Vec::new();
+        }
+        (table, tracked)
+    };
+
+    let gateway = match discover_main_gateway(handle, ifindex, ipv6).await {
+        Ok(Some(gateway)) if Some(gateway) != tracked => gateway,
+        Ok(Some(_)) => return Vec::new(),
+        Ok(None) => {
+            let Some(tracked) = tracked else {
+                return Vec::new();
+            };
+            return withdraw_gateway(handle, state, ifindex, tracked, table, dry_run).await;
+        }
+        Err(e) => {
+            warn!("Failed to discover the gateway of interface {}: {}", ifindex, e);
+            return Vec::new();
+        }
+    };
+
+    if state.read().await.gateway_failed(ifindex) {
+        info!(
+            "Gateway of interface {} is now {}; its health check fails, so table {} gets it once it passes",
+            ifindex, gateway, table
+        );
+        if !dry_run {
+            state.write().await.add_route(ifindex, table, Some(gateway));
+        }
+        return Vec::new();
+    }
+
+    let change = format!("{}route replace default via {} table {}", ip_family(&gateway), gateway, table);
+    if dry_run {
+        info!("Dry run: would run `ip {}` for interface {}", change, ifindex);
+        return vec![change];
+    }
+
+    match tracked {
+        Some(tracked) => info!(
+            "Gateway of interface {} changed from {} to {}, updating table {}",
+            ifindex, tracked, gateway, table
+        ),
+        None => info!("Interface {} has gateway {}, adding it to table {}", ifindex, gateway, table),
+    }
+    if let Err(e) = replace_route(handle, ifindex, gateway, table).await {
+        warn!("Failed to update the default route in table {}: {:#}", table, e);
+        return Vec::new();
+    }
+    state.write().await.add_route(ifindex, table, Some(gateway));
+
+    vec![change]
+}
+
+/// Remove the default route via `gateway` from an interface's table after
+/// the interface lost its default route, and stop tracking the gateway
+async fn withdraw_gateway(
+    handle: &Handle,
+    state: &Arc<RwLock<NetworkState>>,
+    ifindex: u32,
+    gateway: IpAddr,
+    table: u32,
+    dry_run: bool,
+) -> Vec<String> {
+    // The route of a failed gateway is already out of the table
+    if state.read().await.gateway_failed(ifindex) {
+        if !dry_run {
+            state.write().await.clear_gateway(ifindex, table, gateway.is_ipv6());
+        }
+        return Vec::new();
+    }
+
+    let change = format!("{}route del default via {} table {}", ip_family(&gateway), gateway, table);
+    if dry_run {
+        info!("Dry run: would run `ip {}` for interface {}", change, ifindex);
+        return vec![change];
+    }
+
+    info!(
+        "Interface {} has no default route left, removing gateway {} from table {}",
+        ifindex, gateway, table
+    );
+    if let Err(e) = remove_default_route(handle, ifindex, gateway, table).await {
+        warn!("Failed to remove the default route from table {}: {:#}", table, e);
+    }
+    state.write().await.clear_gateway(ifindex, table, gateway.is_ipv6());
+
+    vec![change]
+}
+
+/// Watch for deletions of netevd's rules and routes and put them back
+///
+/// Rule and route removals in the tables netevd owns schedule a check of
+/// that table. After a short quiet period the kernel is compared with the
+/// tracked state and whatever is missing is re-installed, at most
+/// [`repair::REPAIR_BURST`] times per table and minute. A table whose
+/// check or re-installation failed is checked again after
+/// [`REPAIR_RETRY_DELAY`], and `repair_requests` schedules a check of a
+/// table whose link has come up.
+pub async fn watch_rules(
+    handle: Handle,
+    state: Arc<RwLock<NetworkState>>,
+    mut repair_requests: mpsc::UnboundedReceiver<u32>,
+    events: Arc<EventHistory>,
+    audit: Arc<AuditLogger>,
+    metrics: Option<MetricsHandle>,
+    dry_run: bool,
+) -> Result<()> {
+    info!("Starting rule watcher (real-time netlink events)");
+
+    // Route deletions come from the route groups; both watchers subscribe
+    let (connection, mut messages) = new_event_receiver(&[
+        libc::RTNLGRP_IPV4_RULE,
+        libc::RTNLGRP_IPV6_RULE,
+        libc::RTNLGRP_IPV4_ROUTE,
+        libc::RTNLGRP_IPV6_ROUTE,
+    ])?;
+    tokio::spawn(connection);
+
+    info!("Rule watcher subscribed to netlink multicast groups");
+
+    let mut limiter = RepairLimiter::default();
+    // Tables with a deletion waiting to be checked, and when to check them
+    let mut pending: HashMap<u32, Instant> = HashMap::new();
+
+    loop {
+        let next_check = pending.values().min().copied();
+
+        tokio::select! {
+            message = messages.next() => {
+                let Some((message, _)) = message else {
+                    break;
+                };
+
+                use rtnetlink::packet_route::RouteNetlinkMessage;
+
+                let state_read = state.read().await;
+                let tables: Vec<u32> = match message.payload {
+                    rtnetlink::packet_core::NetlinkPayload::InnerMessage(RouteNetlinkMessage::DelRule(msg)) => {
+                        // A main table lookup in front of a tracked rule
+                        // counts as a deletion in that rule's table
+                        let rule = super::routing_rule::kernel_rule_from_notification(&msg);
+                        std::iter::once(rule.table)
+                            .chain(
+                                state_read
+                                    .routing_rules_from
+                                    .values()
+                                    .chain(state_read.routing_rules_to.values())
+                                    .filter(|tracked| suppress_rule_matches(tracked, &rule))
+                                    .map(|tracked| tracked.table),
+                            )
+                            .collect()
+                    }
+                    rtnetlink::packet_core::NetlinkPayload::InnerMessage(RouteNetlinkMessage::DelRoute(msg)) => {
+                        vec![super::route::route_table(&msg)]
+                    }
+                    _ => continue,
+                };
+
+                for table in tables {
+                    if state_read.owns_table(table) {
+                        debug!("Deletion in netevd table {}, scheduling a check", table);
+                        pending.entry(table).or_insert_with(|| Instant::now() + REPAIR_DELAY);
+                    }
+                }
+            }
+            Some(table) = repair_requests.recv() => {
+                debug!("Link of netevd table {} came up, scheduling a check", table);
+                // Bring forward a retry waiting for the link
+                let at = Instant::now() + REPAIR_DELAY;
+                pending.entry(table).and_modify(|due| *due = (*due).min(at)).or_insert(at);
+            }
+            _ = tokio::time::sleep_until(next_check.unwrap_or_else(Instant::now)), if next_check.is_some() => {
+                let now = Instant::now();
+                let due: Vec<u32> = pending
+                    .iter()
+                    .filter(|(_, at)| **at <= now)
+                    .map(|(table, _)| *table)
+                    .collect();
+
+                for table in due {
+                    let missing = match repair::find_missing(&handle, &state, table).await {
+                        Ok(missing) => missing,
+                        Err(e) => {
+                            warn!("Failed to check table {}, retrying: {}", table, e);
+                            pending.insert(table, now + REPAIR_RETRY_DELAY);
+                            continue;
+                        }
+                    };
+                    if missing.is_empty() {
+                        pending.remove(&table);
+                        continue;
+                    }
+
+                    if let Err(retry_at) = limiter.acquire(table, now) {
+                        warn!(
+                            "Repairs of table {} are rate limited, retrying in {}s",
+                            table,
+                            (retry_at - now).as_secs()
+                        );
+                        pending.insert(table, retry_at);
+                        continue;
+                    }
+                    pending.remove(&table);
+
+                    warn!("{} netevd entries missing from table {}, re-installing", missing.len(), table);
+                    let outcome =
+                        repair::reinstall(&handle, &missing, &audit, metrics.as_ref(), dry_run).await;
+                    if !outcome.failed.is_empty() {
+                        warn!(
+                            "{} entries of table {} could not be re-installed, retrying in {}s",
+                            outcome.failed.len(),
+                            table,
+                            REPAIR_RETRY_DELAY.as_secs()
+                        );
+                        pending.insert(table, now + REPAIR_RETRY_DELAY);
+                    }
+
+                    let link_name = {
+                        let state_read = state.read().await;
+                        state_read
+                            .routes
+                            .values()
+                            .find(|entry| entry.table == table)
+                            .and_then(|entry| state_read.get_link_name(entry.ifindex))
+                            .cloned()
+                            .unwrap_or_default()
+                    };
+                    events.record_event(
+                        "repair",
+                        &link_name,
+                        serde_json::json!({
+                            "source": "netlink",
+                            "table": table,
+                            "changes": outcome.repaired,
+                            "failed": outcome.failed,
+                            "dry_run": dry_run,
+                        }),
+                    );
+                }
+            }
         }
     }
 
@@ -256,12 +1125,26 @@ pub async fn watch_routes(_handle: Handle, state: Arc<RwLock<NetworkState>>) ->
 }
 
 /// Watch for link changes using real-time netlink events
-pub async fn watch_links(_handle: Handle, state: Arc<RwLock<NetworkState>>) -> Result<()> {
+///
+/// A member of the load-balance group whose link loses carrier or goes
+/// away is taken out of the multipath default route, and put back once it
+/// is up again. The mark rules pointing at its table follow the same way.
+/// In VRF mode a policy interface is enslaved to its VRF when it appears,
+/// and the VRF is deleted when the interface goes away. When a policy
+/// interface comes up, its table is sent on `repairs` so the rule watcher
+/// re-installs anything that went missing while it was down.
+pub async fn watch_links(
+    handle: Handle,
+    state: Arc<RwLock<NetworkState>>,
+    repairs: mpsc::UnboundedSender<u32>,
+    events: Arc<EventHistory>,
+    dry_run: bool,
+) -> Result<()> {
     info!("Starting link watcher (real-time netlink events)");
 
     // Subscribe to link change notifications via multicast groups
     let (connection, mut messages) =
-        new_event_receiver(&[libc::RTNLGRP_LINK as u32])?;
+        new_event_receiver(&[libc::RTNLGRP_LINK])?;
     tokio::spawn(connection);
 
     info!("Link watcher subscribed to netlink multicast groups");
@@ -281,7 +1164,7 @@ pub async fn watch_links(_handle: Handle, state: Arc<RwLock<NetworkState>>) -> R
 
         // For link additions, extract link name from the message and update state
         if event_type == "new" {
-            use rtnetlink::packet_route::link::LinkAttribute;
+            use rtnetlink::packet_route::link::{LinkAttribute, LinkFlags};
             let link_name = msg.attributes.iter().find_map(|attr| {
                 if let LinkAttribute::IfName(name) = attr {
                     Some(name.clone())
@@ -290,8 +1173,68 @@ pub async fn watch_links(_handle: Handle, state: Arc<RwLock<NetworkState>>) -> R
                 }
             });
             if let Some(name) = link_name {
-                info!("Link added: {} ({})", name, ifindex);
-                state.write().await.add_link(name, ifindex);
+                // RTM_NEWLINK is also sent for every change on a known link;
+                // only a link we have not seen before is worth an event
+                let known = state.read().await.get_link_name(ifindex) == Some(&name);
+                if !known {
+                    info!("Link added: {} ({})", name, ifindex);
+                    events.record_event(
+                        "link",
+                        &name,
+                        serde_json::json!({ "source": "netlink", "action": "new", "ifindex": ifindex }),
+                    );
+                }
+                let up = msg.header.flags.contains(LinkFlags::Up) && msg.header.flags.contains(LinkFlags::Running);
+                let (changed, member, vrf_link, table) = {
+                    let mut state_write = state.write().await;
+                    state_write.add_link(name.clone(), ifindex);
+                    (
+                        state_write.set_link_up(ifindex, up),
+                        state_write.is_load_balance_member(&name),
+                        state_write.is_vrf_link(&name),
+                        state_write.link_table(ifindex),
+                    )
+                };
+                if let Some(table) = table.filter(|_| changed && up) {
+                    // Ignored if the rule watcher has stopped
+                    let _ = repairs.send(table);
+                }
+                if !known && vrf_link {
+                    sync_vrfs(&handle, &state, &name, ifindex, "new", &events, dry_run).await;
+                }
+                if changed && member {
+                    info!("Load-balance member {} is {}", name, if up { "up" } else { "down" });
+                    let changes = balance::update(&handle, &state, dry_run).await;
+                    events.record_event(
+                        "load_balance",
+                        &name,
+                        serde_json::json!({
+                            "source": "netlink",
+                            "action": if up { "link_up" } else { "link_down" },
+                            "ifindex": ifindex,
+                            "changes": changes,
+                            "dry_run": dry_run,
+                        }),
+                    );
+                }
+                let changes = if changed {
+                    mark::update(&handle, &state, dry_run).await
+                } else {
+                    Vec::new()
+                };
+                if !changes.is_empty() {
+                    events.record_event(
+                        "mark_rules",
+                        &name,
+                        serde_json::json!({
+                            "source": "netlink",
+                            "action": if up { "link_up" } else { "link_down" },
+                            "ifindex": ifindex,
+                            "changes": changes,
+                            "dry_run": dry_run,
+                        }),
+                    );
+                }
             } else {
                 debug!("Link added with ifindex {} but no name in attributes", ifindex);
             }
@@ -299,121 +1242,278 @@ pub async fn watch_links(_handle: Handle, state: Arc<RwLock<NetworkState>>) -> R
 
         // For link deletions, clean up our state
         if event_type == "del" {
-            let mut state_write = state.write().await;
-            let link_name = state_write.get_link_name(ifindex).cloned().unwrap_or_default();
-            info!("Link removed: {} ({})", link_name, ifindex);
-            state_write.remove_link(ifindex);
+            let (link_name, member, vrf_link) = {
+                let mut state_write = state.write().await;
+                let link_name = state_write.get_link_name(ifindex).cloned().unwrap_or_default();
+                info!("Link removed: {} ({})", link_name, ifindex);
+                state_write.remove_link(ifindex);
+                let member = state_write.is_load_balance_member(&link_name);
+                let vrf_link = state_write.is_vrf_link(&link_name);
+                (link_name, member, vrf_link)
+            };
+            if vrf_link {
+                sync_vrfs(&handle, &state, &link_name, ifindex, "del", &events, dry_run).await;
+            }
+            let mut changes = if member {
+                balance::update(&handle, &state, dry_run).await
+            } else {
+                Vec::new()
+            };
+            changes.extend(mark::update(&handle, &state, dry_run).await);
+            events.record_event(
+                "link",
+                &link_name,
+                serde_json::json!({ "source": "netlink", "action": "del", "ifindex": ifindex, "changes": changes }),
+            );
         }
     }
 
     Ok(())
 }
 
+/// Create, enslave or delete VRF devices after a policy interface or one
+/// of their VRFs came or went
+async fn sync_vrfs(
+    handle: &Handle,
+    state: &Arc<RwLock<NetworkState>>,
+    name: &str,
+    ifindex: u32,
+    action: &str,
+    events: &EventHistory,
+    dry_run: bool,
+) {
+    let changes = vrf::sync(handle, state, dry_run).await;
+    if !changes.is_empty() {
+        events.record_event(
+            "vrf",
+            name,
+            serde_json::json!({
+                "source": "netlink",
+                "action": action,
+                "ifindex": ifindex,
+                "changes": changes,
+                "dry_run": dry_run,
+            }),
+        );
+    }
+}
+
 /// Configure routing rules and routes for an interface
+///
+/// Each address family with a gateway gets a default route in the link's
+/// table, the connected routes of its subnets and the configured rules. In
+/// VRF mode only the default route is added: the kernel keeps the connected
+/// routes in the VRF table, and its l3mdev rule does the job of the rules.
+/// Returns the changes made, in `ip route`/`ip rule` terms. In dry-run mode
+/// nothing is sent to the kernel or tracked; the changes are only logged
+/// and returned.
 async fn configure_network(
     handle: &Handle,
     state: &Arc<RwLock<NetworkState>>,
     ifindex: u32,
-    addresses: &[IpAddr],
-) -> Result<()> {
-    let table = calculate_table_id(ifindex);
+    prefixes: &[(IpAddr, u8)],
+    dry_run: bool,
+) -> Result<Vec<String>> {
+    let table = state
+        .read()
+        .await
+        .link_table(ifindex)
+        .with_context(|| format!("No routing table allocated for interface {}", ifindex))?;
+    let (ipv4, ipv6): (Vec<_>, Vec<_>) =
+        prefixes.iter().copied().partition(|(address, _)| address.is_ipv4());
+
+    // Each family needs its own gateway; a family without one gets no
+    // rules, so its traffic keeps using the main table
+    let mut gateways = Vec::new();
+    if !ipv4.is_empty() {
+        match discover_gateway(handle, ifindex).await? {
+            Some(gateway) => gateways.push((gateway, ipv4)),
+            None => warn!("No gateway found for interface {}", ifindex),
+        }
+    }
+    if !ipv6.is_empty() {
+        match discover_ipv6_gateway(handle, ifindex).await? {
+            Some(gateway) => gateways.push((gateway, ipv6)),
+            None => warn!("No IPv6 gateway found for interface {}", ifindex),
+        }
+    }
+
+    // Routes and rules already installed (or adopted at startup) are left
+    // alone; the kernel does not deduplicate rules added without an
+    // explicit priority
+    let mut families = Vec::new();
+    let failed = {
+        let state_read = state.read().await;
+        let rule_config = state_read.link_rule_config(ifindex);
+        let vrf = state_read.mode == RoutingMode::Vrf;
+        let installed = state_read.routes.get(&(ifindex, table));
+        for (gateway, family_prefixes) in gateways {
+            let family_prefixes = if vrf { Vec::new() } else { family_prefixes };
+            // A tracked gateway that no longer matches is replaced
+            let default_route = match installed
+                .and_then(|route| if gateway.is_ipv6() { route.gateway6 } else { route.gateway })
+            {
+                None => Some("add"),
+                Some(tracked) if tracked != gateway => Some("replace"),
+                Some(_) => None,
+            };
+            let mut new_subnets = Vec::new();
+            let mut new_rules = Vec::new();
+            for (address, prefix_len) in family_prefixes {
+                let subnet = (network_address(address, prefix_len), prefix_len);
+                if prefix_len > 0
+                    && prefix_len < host_prefix_len(&address)
+                    && !installed.is_some_and(|route| route.subnets.contains(&subnet))
+                    && !new_subnets.contains(&subnet)
+                {
+                    new_subnets.push(subnet);
+                }
 
-    // Discover gateway for this interface
-    let gateway = match discover_gateway(handle, ifindex).await? {
-        Some(gw) => gw,
-        None => {
-            warn!("No gateway found for interface {}", ifindex);
-            return Ok(());
+                for rule in RoutingRule::for_address(address, prefix_len, table, &rule_config) {
+                    if !state_read.has_routing_rule(&rule) && !new_rules.contains(&rule) {
+                        new_rules.push(rule);
+                    }
+                }
+            }
+            if default_route.is_some() || !new_subnets.is_empty() || !new_rules.is_empty() {
+                families.push((gateway, default_route, new_subnets, new_rules));
+            }
         }
+        // While the gateway's health check fails, its default route stays
+        // out of the table; the gateway is still tracked for the recovery
+        state_read.gateway_failed(ifindex)
     };
 
-    // Add default route to custom table
-    add_route(handle, ifindex, gateway, table).await?;
-
-    // Add routing rules for each address
-    for address in addresses {
-        // Add "from" rule
-        add_routing_rule_from(handle, *address, table).await?;
+    let mut changes = Vec::new();
+    for (gateway, default_route, subnets, rules) in &families {
+        let family = ip_family(gateway);
+        for (network, prefix_len) in subnets {
+            changes.push(format!("{}route add {}/{} scope link table {}", family, network, prefix_len, table));
+        }
+        if let Some(action) = default_route.filter(|_| !failed) {
+            changes.push(format!("{}route {} default via {} table {}", family, action, gateway, table));
+        }
+        for rule in rules {
+            if let Some(suppress) = rule.describe_suppress() {
+                changes.push(format!("{}rule add {}", family, suppress));
+            }
+            changes.push(format!("{}rule add {}", family, rule.describe()));
+        }
+    }
 
-        // Add "to" rule
-        add_routing_rule_to(handle, *address, table).await?;
+    if dry_run {
+        for change in &changes {
+            info!("Dry run: would run `ip {}` for interface {}", change, ifindex);
+        }
+        return Ok(changes);
     }
 
-    // Update state in a single atomic write operation
-    // This prevents race conditions where another watcher could modify state
-    // between individual updates
-    {
-        let mut state_write = state.write().await;
+    // Each entry is tracked as soon as it is installed, so that one that
+    // fails part way leaves the earlier ones to be flushed and repaired
+    for (gateway, default_route, subnets, rules) in families {
+        // Connected routes first, so traffic to the interface's own subnets
+        // that is looked up in the table does not go via the gateway
+        for subnet in subnets {
+            add_subnet_route(handle, ifindex, subnet, table).await?;
+            state.write().await.add_subnet_route(ifindex, table, subnet);
+        }
 
-        // Add all routing rules to state
-        for address in addresses {
-            state_write.add_routing_rule_from(*address, table);
-            state_write.add_routing_rule_to(*address, table);
+        // Add default route to custom table; the route carries the
+        // interface, so a link-local IPv6 gateway works
+        match default_route.filter(|_| !failed) {
+            Some("replace") => replace_route(handle, ifindex, gateway, table).await?,
+            Some(_) => add_route(handle, ifindex, gateway, table).await?,
+            None => {}
         }
+        state.write().await.add_route(ifindex, table, Some(gateway));
 
-        // Add route to state
-        state_write.add_route(ifindex, table, Some(gateway));
+        // Add the configured "from" and "to" rules for each address
+        for rule in rules {
+            add_routing_rule(handle, &rule).await?;
+            state.write().await.add_routing_rule(rule);
+        }
     }
 
     info!(
         "Successfully configured routing for interface {} with {} addresses",
         ifindex,
-        addresses.len()
+        prefixes.len()
     );
 
-    Ok(())
+    Ok(changes)
 }
 
 /// Remove routing configuration for an interface
+///
+/// Returns the changes made; in dry-run mode they are only logged.
 async fn drop_configuration(
     handle: &Handle,
     state: &Arc<RwLock<NetworkState>>,
     ifindex: u32,
-) -> Result<()> {
-    let table = calculate_table_id(ifindex);
-
-    // Get addresses that need to be cleaned up (deduplicated)
-    let addresses_to_clean: Vec<IpAddr> = {
-        let state_read = state.read().await;
-        let addrs: HashSet<IpAddr> = state_read
-            .routing_rules_from
-            .keys()
-            .chain(state_read.routing_rules_to.keys())
-            .filter(|addr| {
-                state_read
-                    .routing_rules_from
-                    .get(addr)
-                    .map(|rule| rule.table == table)
-                    .unwrap_or(false)
-                    || state_read
-                        .routing_rules_to
-                        .get(addr)
-                        .map(|rule| rule.table == table)
-                        .unwrap_or(false)
-            })
-            .copied()
-            .collect();
-        addrs.into_iter().collect()
+    dry_run: bool,
+) -> Result<Vec<String>> {
+    let Some(table) = state.read().await.link_table(ifindex) else {
+        return Ok(Vec::new());
     };
 
+    // Rules that need to be cleaned up, and their addresses (deduplicated)
+    let rules_to_clean: Vec<RoutingRule> = state
+        .read()
+        .await
+        .routing_rules()
+        .filter(|rule| rule.table == table)
+        .cloned()
+        .collect();
+    let addresses_to_clean: Vec<IpAddr> = rules_to_clean
+        .iter()
+        .map(|rule| rule.address)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+
+    let mut changes: Vec<String> = addresses_to_clean
+        .iter()
+        .map(|address| format!("{}rule del from/to {} lookup {}", ip_family(address), address, table))
+        .collect();
+    changes.push(format!("route del default table {}", table));
+    if let Some(route) = state.read().await.routes.get(&(ifindex, table)) {
+        if route.gateway6.is_some() {
+            changes.push(format!("-6 route del default table {}", table));
+        }
+        for (network, prefix_len) in &route.subnets {
+            changes.push(format!("{}route del {}/{} table {}", ip_family(network), network, prefix_len, table));
+        }
+    }
+
+    if dry_run {
+        for change in &changes {
+            info!("Dry run: would run `ip {}` for interface {}", change, ifindex);
+        }
+        return Ok(changes);
+    }
+
     // Remove routing rules
-    for address in &addresses_to_clean {
-        if let Err(e) = remove_routing_rules(handle, *address, table).await {
-            warn!("Failed to remove routing rules for {}: {}", address, e);
+    for rule in &rules_to_clean {
+        if let Err(e) = remove_routing_rule(handle, rule).await {
+            warn!("{:#}", e);
         }
     }
 
-    // Remove route
-    if let Err(e) = remove_route(handle, ifindex, table).await {
+    // Remove route; a VRF table's other routes are the kernel's
+    let removed = if state.read().await.mode == RoutingMode::Vrf {
+        remove_default_routes(handle, ifindex, table).await
+    } else {
+        remove_route(handle, ifindex, table).await
+    };
+    if let Err(e) = removed {
         warn!("Failed to remove route for interface {}: {}", ifindex, e);
     }
 
     // Update state
     {
         let mut state_write = state.write().await;
-        for address in &addresses_to_clean {
-            state_write.routing_rules_from.remove(address);
-            state_write.routing_rules_to.remove(address);
+        for rule in &rules_to_clean {
+            state_write.remove_routing_rule(rule);
         }
         state_write.routes.remove(&(ifindex, table));
     }
@@ -424,5 +1524,5 @@ async fn drop_configuration(
         addresses_to_clean.len()
     );
 
-    Ok(())
+    Ok(changes)
 }
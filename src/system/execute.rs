@@ -7,9 +7,10 @@
 //! validated and sanitized before being passed to scripts.
 
 use anyhow::{Context, Result};
+use serde::Serialize;
 use std::collections::HashMap;
 use std::os::unix::fs::PermissionsExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::time::Duration;
 use tokio::fs;
@@ -20,21 +21,125 @@ const SCRIPT_TIMEOUT: Duration = Duration::from_secs(30);
 
 use crate::system::validation;
 
+/// Outcome of running one script, kept in the event history
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptResult {
+    pub script: String,
+    /// Exit code; None if the script could not be run or was killed by a signal
+    pub exit_code: Option<i32>,
+    pub error: Option<String>,
+}
+
+impl ScriptResult {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
 /// Execute all scripts in a directory with provided environment variables
+///
+/// Returns the result of each script that was run, in execution order.
 pub async fn execute_scripts(
     directory: &str,
     env_vars: HashMap<String, String>,
-) -> Result<()> {
+) -> Result<Vec<ScriptResult>> {
+    let scripts = find_scripts(directory).await?;
+    if scripts.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    info!("Executing {} scripts in: {}", scripts.len(), directory);
+
+    // Execute each script
+    let mut results = Vec::with_capacity(scripts.len());
+    for script_path in scripts {
+        let result = match execute_script(&script_path, &env_vars).await {
+            Ok(exit_code) => {
+                if exit_code == Some(0) {
+                    info!("Successfully executed script: {:?}", script_path);
+                    ScriptResult {
+                        script: script_path.display().to_string(),
+                        exit_code,
+                        error: None,
+                    }
+                } else {
+                    warn!("Script {:?} exited with status: {:?}", script_path, exit_code);
+                    ScriptResult {
+                        script: script_path.display().to_string(),
+                        exit_code,
+                        error: Some("non-zero exit status".to_string()),
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("Failed to execute script {:?}: {}", script_path, e);
+                ScriptResult {
+                    script: script_path.display().to_string(),
+                    exit_code: None,
+                    error: Some(e.to_string()),
+                }
+            }
+        };
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+/// Log the scripts `execute_scripts` would run, without running them
+///
+/// Used in dry-run mode. The results carry no exit code or error.
+pub async fn dry_run_scripts(
+    directory: &str,
+    env_vars: HashMap<String, String>,
+) -> Result<Vec<ScriptResult>> {
+    let scripts = find_scripts(directory).await?;
+
+    let mut env: Vec<String> = env_vars
+        .iter()
+        .filter(|(key, _)| key.as_str() != "JSON")
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    env.sort();
+
+    Ok(scripts
+        .into_iter()
+        .map(|script_path| {
+            info!("Dry run: would execute {:?} with {}", script_path, env.join(" "));
+            ScriptResult {
+                script: script_path.display().to_string(),
+                exit_code: None,
+                error: None,
+            }
+        })
+        .collect())
+}
+
+/// Run the scripts in a directory, or only log them when `dry_run` is set
+pub async fn run_scripts(
+    directory: &str,
+    env_vars: HashMap<String, String>,
+    dry_run: bool,
+) -> Result<Vec<ScriptResult>> {
+    if dry_run {
+        dry_run_scripts(directory, env_vars).await
+    } else {
+        execute_scripts(directory, env_vars).await
+    }
+}
+
+/// Executable files in a directory, sorted by name
+async fn find_scripts(directory: &str) -> Result<Vec<PathBuf>> {
     let dir_path = Path::new(directory);
 
     if !dir_path.exists() {
         debug!("Script directory does not exist: {}", directory);
-        return Ok(());
+        return Ok(Vec::new());
     }
 
     if !dir_path.is_dir() {
         warn!("Script path is not a directory: {}", directory);
-        return Ok(());
+        return Ok(Vec::new());
     }
 
     // Read directory entries
@@ -75,31 +180,21 @@ pub async fn execute_scripts(
 
     if scripts.is_empty() {
         debug!("No executable scripts found in: {}", directory);
-        return Ok(());
-    }
-
-    info!("Executing {} scripts in: {}", scripts.len(), directory);
-
-    // Execute each script
-    for script_path in scripts {
-        match execute_script(&script_path, &env_vars).await {
-            Ok(_) => {
-                info!("Successfully executed script: {:?}", script_path);
-            }
-            Err(e) => {
-                warn!("Failed to execute script {:?}: {}", script_path, e);
-            }
-        }
     }
 
-    Ok(())
+    Ok(scripts)
 }
 
 /// Execute a single script with environment variables
 ///
 /// All environment variable values are validated and sanitized to prevent
 /// command injection attacks. Dangerous values are rejected with warnings.
-async fn execute_script(script_path: &Path, env_vars: &HashMap<String, String>) -> Result<()> {
+///
+/// Returns the exit code, or None if the script was terminated by a signal.
+async fn execute_script(
+    script_path: &Path,
+    env_vars: &HashMap<String, String>,
+) -> Result<Option<i32>> {
     debug!("Executing script: {:?}", script_path);
 
     let mut cmd = Command::new(script_path);
@@ -195,14 +290,5 @@ async fn execute_script(script_path: &Path, env_vars: &HashMap<String, String>)
         }
     }
 
-    // Check exit status
-    if !output.status.success() {
-        anyhow::bail!(
-            "Script {:?} exited with status: {}",
-            script_path,
-            output.status
-        );
-    }
-
-    Ok(())
+    Ok(output.status.code())
 }
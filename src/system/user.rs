@@ -22,6 +22,15 @@ pub fn lookup_user(username: &str) -> Result<(Uid, Gid)> {
     Ok((user.uid, user.gid))
 }
 
+/// Make `path` owned by the specified user and their primary group
+pub fn chown_to(path: &std::path::Path, username: &str) -> Result<()> {
+    let (uid, gid) = lookup_user(username)
+        .with_context(|| format!("Failed to lookup user '{}'", username))?;
+
+    std::os::unix::fs::chown(path, Some(uid.as_raw()), Some(gid.as_raw()))
+        .with_context(|| format!("Failed to change the owner of {}", path.display()))
+}
+
 /// Drop privileges to specified user while retaining necessary capabilities
 ///
 /// This function:
@@ -142,6 +142,10 @@ pub fn validate_state_name(state: &str) -> bool {
             | "disconnected"
             | "manager"
             | "routes"
+            | "gateway_down"
+            | "gateway_up"
+            | "uplink_degraded"
+            | "uplink_recovered"
             | "off"
             | "dormant"
             | "enslaved"
@@ -239,6 +243,8 @@ mod tests {
         assert!(validate_state_name("routable"));
         assert!(validate_state_name("activated"));
         assert!(validate_state_name("no-carrier"));
+        assert!(validate_state_name("gateway_down"));
+        assert!(validate_state_name("uplink_degraded"));
 
         assert!(!validate_state_name(""));
         assert!(!validate_state_name("invalid-state"));
@@ -5,6 +5,8 @@
 //! These tests require root privileges and create real network interfaces.
 //! Run with: sudo cargo test --test functional_test -- --test-threads=1
 
+#![allow(clippy::needless_borrows_for_generic_args)]
+
 use std::fs;
 use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
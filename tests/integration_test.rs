@@ -2,6 +2,8 @@
 
 //! Integration tests for netevd daemon
 
+#![allow(unused_imports, unused_variables, clippy::assertions_on_constants)]
+
 use std::fs;
 use std::path::PathBuf;
 use tempfile::TempDir;
@@ -561,3 +563,51 @@ fn test_state_name_validation() {
     assert!(!validate_state_name("../../../etc/passwd"));
     assert!(!validate_state_name("unknown"));
 }
+
+/// Test that script results carry exit codes in execution order
+#[tokio::test]
+async fn test_execute_scripts_exit_codes() {
+    use netevd::system::execute::execute_scripts;
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = TempDir::new().unwrap();
+    for (name, body) in [("01-ok", "#!/bin/sh\nexit 0\n"), ("02-fail", "#!/bin/sh\nexit 3\n")] {
+        let path = temp_dir.path().join(name);
+        fs::write(&path, body).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    let results = execute_scripts(temp_dir.path().to_str().unwrap(), Default::default())
+        .await
+        .unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert!(results[0].script.ends_with("01-ok"));
+    assert!(results[0].success());
+    assert_eq!(results[1].exit_code, Some(3));
+    assert!(!results[1].success());
+}
+
+/// Test that dry-run lists the scripts without running them
+#[tokio::test]
+async fn test_dry_run_scripts_does_not_execute() {
+    use netevd::system::execute::dry_run_scripts;
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = TempDir::new().unwrap();
+    let marker = temp_dir.path().join("ran");
+    let script = temp_dir.path().join("01-touch");
+    fs::write(&script, format!("#!/bin/sh\ntouch {}\n", marker.display())).unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(temp_dir.path().join("README"), "not executable").unwrap();
+
+    let results = dry_run_scripts(temp_dir.path().to_str().unwrap(), Default::default())
+        .await
+        .unwrap();
+
+    assert_eq!(results.len(), 1);
+    assert!(results[0].script.ends_with("01-touch"));
+    assert_eq!(results[0].exit_code, None);
+    assert!(results[0].error.is_none());
+    assert!(!marker.exists());
+}